@@ -0,0 +1,50 @@
+# Read throughput of RoutingTable.lookup/2 by number of concurrent readers.
+#
+#     mix run bench/concurrent_lookup.exs [routes] [seconds]
+#
+# Lookups only take the table lock for reading, so throughput should grow with
+# the number of readers up to the number of schedulers.
+
+{routes, seconds} =
+  case System.argv() do
+    [routes, seconds] -> {String.to_integer(routes), String.to_integer(seconds)}
+    [routes] -> {String.to_integer(routes), 2}
+    [] -> {100_000, 2}
+  end
+
+:rand.seed(:exsss, {1, 2, 3})
+random_ip = fn -> {:rand.uniform(256) - 1, :rand.uniform(256) - 1, :rand.uniform(256) - 1, :rand.uniform(256) - 1} end
+
+table = RoutingTable.new()
+for n <- 1..routes, do: RoutingTable.add(table, random_ip.(), 8 + :rand.uniform(24), n)
+ips = for _ <- 1..10_000, do: random_ip.()
+
+IO.puts("#{routes} routes, #{System.schedulers_online()} schedulers online")
+
+readers =
+  Stream.iterate(1, &(&1 * 2))
+  |> Enum.take_while(&(&1 <= System.schedulers_online()))
+
+for n <- readers do
+  deadline = System.monotonic_time(:millisecond) + seconds * 1000
+
+  lookups =
+    1..n
+    |> Task.async_stream(
+      fn _ ->
+        Stream.repeatedly(fn ->
+          Enum.each(ips, &RoutingTable.lookup(table, &1))
+          length(ips)
+        end)
+        |> Enum.reduce_while(0, fn count, acc ->
+          if System.monotonic_time(:millisecond) < deadline,
+            do: {:cont, acc + count},
+            else: {:halt, acc + count}
+        end)
+      end,
+      timeout: :infinity
+    )
+    |> Enum.reduce(0, fn {:ok, count}, acc -> acc + count end)
+
+  IO.puts("#{String.pad_leading(Integer.to_string(n), 3)} readers: #{div(lookups, seconds)} lookups/s")
+end
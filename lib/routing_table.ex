@@ -1,13 +1,27 @@
 defmodule RoutingTable do
-  alias RoutingTable.TreeBitmap
-  defstruct [:i4, :i6, :ets]
+  alias RoutingTable.{Prefix, TreeBitmap}
+  defstruct [:i4, :i6]
 
   @opaque t() :: %__MODULE__{}
   @type masklen :: non_neg_integer()
+  @typedoc "An address packed in network byte order."
+  @type raw_address() :: <<_::32>> | <<_::128>>
+  @typedoc ~S'An address tuple, an address string such as `"10.0.0.1"`, or a tagged `t:raw_address/0`.'
+  @type address() :: :inet.ip_address() | String.t() | {:raw, raw_address()}
+  @typedoc ~S'A prefix in CIDR notation, such as `"10.0.0.0/8"` or `"2001:db8::/32"`.'
+  @type cidr() :: String.t()
 
   @moduledoc """
   Efficient routing table.
 
+  Values can be any term, they are stored in the table itself.
+
+  Prefixes are given either as an address tuple or a `t:raw_address/0` binary
+  with a separate mask length, or as a `t:cidr/0` string. Parsing happens in
+  the NIF, invalid strings raise `ArgumentError`. Lookups take a single
+  `t:address/0`, where a binary is always an address string: raw addresses
+  are tagged as `{:raw, raw_address}`.
+
   ```elixir
   table = RoutingTable.new()
   RoutingTable.add(table, {10, 69, 0, 0}, 16, :vpn)
@@ -19,153 +33,744 @@ defmodule RoutingTable do
   ```
   """
 
-  def new(_opts \\ []) do
-    %__MODULE__{i4: TreeBitmap.new(), i6: TreeBitmap.new(), ets: :ets.new(__MODULE__, [:public])}
+  @doc """
+  Creates a new table.
+
+  Options:
+
+    * `:strict` - when `true`, prefixes with host bits set (`{10, 0, 0, 1}/8`)
+      are rejected instead of being masked. Defaults to `false`.
+    * `:prefix_format` - `:tuple` returns prefixes as address tuples, `:cidr`
+      as canonical CIDR strings (`"2001:db8::/32"`). Defaults to `:tuple`.
+    * `:embedded_ipv4` - IPv6 addresses embedding an IPv4 address in one of
+      the listed ways are looked up in the IPv4 routes: `:mapped`
+      (`::ffff:10.1.2.3`, as handed by dual-stack sockets), `:compatible`
+      (`::10.1.2.3`), `:nat64` (`64:ff9b::10.1.2.3`) and `:six_to_four`
+      (`2002:a01:203::`). The route found is returned as the IPv6 prefix
+      embedding it, `::ffff:10.0.0.0/104` for `10.0.0.0/8`, unless an IPv6
+      route is more specific: IPv6 routes such as `64:ff9b::/96` still match
+      the addresses the IPv4 routes miss. Only lookups by address are
+      affected, prefixes are added and matched as given.
+      Defaults to `[]`.
+
+  Adding, removing or matching a prefix whose mask length is too long for its
+  address family, or a prefix rejected by `:strict`, raises `ArgumentError`.
+  """
+  @spec new(keyword()) :: t()
+  def new(opts \\ []) do
+    link(TreeBitmap.new_with_options(opts), TreeBitmap.new_with_options(opts))
+  end
+
+  @doc """
+  Returns an independent copy of the table, with the same options.
+
+  Use it to keep a snapshot before applying a batch of changes. Each address
+  family is copied under its own lock, so concurrent writers should be paused
+  for the snapshot to be consistent across families.
+  """
+  @spec clone(t()) :: t()
+  def clone(tree) do
+    link(TreeBitmap.clone(tree.i4), TreeBitmap.clone(tree.i6))
   end
 
-  @spec add(t(), :inet.ip_address(), masklen(), any()) :: nil | any()
+  @spec add(t(), cidr(), any()) :: nil | any()
+  def add(tree, cidr, value) do
+    {ip, masklen} = Prefix.parse!(cidr)
+    do_add(family_tbm(tree, ip), ip, masklen, value)
+  end
+
+  @spec add(t(), :inet.ip_address() | raw_address(), masklen(), any()) :: nil | any()
   def add(tree, ip, masklen, value)
 
   def add(tree, {a, b, c, d}, masklen, value) do
-    add(tree, tree.i4, {:inet4, a, b, c, d}, masklen, value)
+    do_add(tree.i4, {:inet4, a, b, c, d}, masklen, value)
   end
 
   def add(tree, {a, b, c, d, e, f, g, h}, masklen, value) do
-    add(tree, tree.i6, {:inet6, a, b, c, d, e, f, g, h}, masklen, value)
+    do_add(tree.i6, {:inet6, a, b, c, d, e, f, g, h}, masklen, value)
+  end
+
+  def add(tree, <<_::32>> = ip, masklen, value) do
+    do_add(tree.i4, ip, masklen, value)
+  end
+
+  def add(tree, <<_::128>> = ip, masklen, value) do
+    do_add(tree.i6, ip, masklen, value)
   end
 
-  @spec remove(t(), :inet.ip_address(), masklen()) :: nil | any()
+  @doc """
+  Adds many routes at once, replacing existing values.
+
+  Much faster than calling `add/4` for each route when loading full tables.
+  Invalid routes do not prevent the others from being added, they are
+  returned along with the reason they were rejected.
+  """
+  @spec add_many(t(), [route()]) :: [{route(), reason :: atom()}]
+  def add_many(tree, routes) do
+    {i4, i6, invalid} =
+      Enum.reduce(routes, {[], [], []}, fn
+        {{a, b, c, d}, masklen, value} = route, {i4, i6, invalid} ->
+          {[{route, {{:inet4, a, b, c, d}, masklen, value}} | i4], i6, invalid}
+
+        {{a, b, c, d, e, f, g, h}, masklen, value} = route, {i4, i6, invalid} ->
+          {i4, [{route, {{:inet6, a, b, c, d, e, f, g, h}, masklen, value}} | i6], invalid}
+
+        route, {i4, i6, invalid} ->
+          {i4, i6, [{route, :badarg} | invalid]}
+      end)
+
+    Enum.reverse(invalid) ++ do_add_many(tree.i4, Enum.reverse(i4)) ++ do_add_many(tree.i6, Enum.reverse(i6))
+  end
+
+  @spec remove(t(), cidr()) :: nil | any()
+  def remove(tree, cidr) do
+    {ip, masklen} = Prefix.parse!(cidr)
+    do_remove(family_tbm(tree, ip), ip, masklen)
+  end
+
+  @spec remove(t(), :inet.ip_address() | raw_address(), masklen()) :: nil | any()
   def remove(tree, ip, masklen)
 
   def remove(tree, {a, b, c, d}, masklen) do
-    remove(tree, tree.i4, {:inet4, a, b, c, d}, masklen)
+    do_remove(tree.i4, {:inet4, a, b, c, d}, masklen)
   end
 
   def remove(tree, {a, b, c, d, e, f, g, h}, masklen) do
-    remove(tree, tree.i6, {:inet6, a, b, c, d, e, f, g, h}, masklen)
+    do_remove(tree.i6, {:inet6, a, b, c, d, e, f, g, h}, masklen)
+  end
+
+  def remove(tree, <<_::32>> = ip, masklen) do
+    do_remove(tree.i4, ip, masklen)
+  end
+
+  def remove(tree, <<_::128>> = ip, masklen) do
+    do_remove(tree.i6, ip, masklen)
+  end
+
+  @typedoc """
+  A candidate route for a prefix: where it was learnt from, its
+  administrative distance and metric, and its value.
+  """
+  @type candidate() :: %{
+          source: any(),
+          distance: non_neg_integer(),
+          metric: non_neg_integer(),
+          value: any()
+        }
+
+  @doc """
+  Adds a candidate route to a prefix, replacing the one from the same source.
+
+  A prefix holds at most one route per source, the selected route is the one
+  with the lowest distance, then the lowest metric, then the oldest. Lookups
+  return the value of the selected route, `routes/3` lists all of them.
+
+  Sources are compared by value, atoms such as `:static`, `:ospf` or `:bgp`
+  or small tuples work best. Adding a route to a prefix set with `add/4`
+  replaces its value, and `add/4` replaces all the candidate routes of a
+  prefix.
+
+  Returns the route replaced from the same source, or `nil`.
+  """
+  @spec add_route(t(), cidr(), candidate()) :: nil | candidate()
+  def add_route(tree, cidr, route) do
+    {ip, masklen} = Prefix.parse!(cidr)
+    add_route(tree, ip, masklen, route)
+  end
+
+  @spec add_route(t(), :inet.ip_address() | raw_address(), masklen(), candidate()) :: nil | candidate()
+  def add_route(tree, ip, masklen, %{source: source, distance: distance, metric: metric, value: value}) do
+    {tbm, ip} = Prefix.family_key(tree, ip)
+    route = {source, distance, metric, value}
+    {:ok, prev} = Prefix.check!(TreeBitmap.add_route(tbm, ip, masklen, route), ip, masklen)
+    to_candidate(prev)
+  end
+
+  @doc """
+  Adds `value` for the addresses from `first` to `last` included, as the
+  fewest prefixes covering them. Returns the prefixes added, in order.
+
+  RIR delegation files and GeoIP databases list address ranges rather than
+  prefixes:
+
+      RoutingTable.add_range(t, "10.0.0.1", "10.0.0.10", :lab)
+      #=> [{{10, 0, 0, 1}, 32}, {{10, 0, 0, 2}, 31}, {{10, 0, 0, 4}, 30},
+      #=>  {{10, 0, 0, 8}, 31}, {{10, 0, 0, 10}, 32}]
+  """
+  @spec add_range(t(), address, address, any()) :: [{:inet.ip_address() | cidr(), masklen()}]
+        when address: address()
+  def add_range(tree, first, last, value) do
+    {tbm, first_ip} = Prefix.family_key(tree, Prefix.address!(first))
+    {_, last_ip} = Prefix.family_key(tree, Prefix.address!(last))
+
+    case TreeBitmap.add_range(tbm, first_ip, last_ip, value) do
+      {:ok, prefixes} -> for {prefix, masklen} <- prefixes, do: {Prefix.to_inet(prefix), masklen}
+      {:error, reason} -> raise ArgumentError, "invalid range #{inspect(first)} to #{inspect(last)}: #{reason}"
+    end
+  end
+
+  @doc """
+  Removes the candidate route from `source`, and the prefix with its last
+  route. Returns the removed route, or `nil`.
+  """
+  @spec remove_route(t(), cidr(), any()) :: nil | candidate()
+  def remove_route(tree, cidr, source) do
+    {ip, masklen} = Prefix.parse!(cidr)
+    remove_route(tree, ip, masklen, source)
+  end
+
+  @spec remove_route(t(), :inet.ip_address() | raw_address(), masklen(), any()) :: nil | candidate()
+  def remove_route(tree, ip, masklen, source) do
+    {tbm, ip} = Prefix.family_key(tree, ip)
+    {:ok, route} = Prefix.check!(TreeBitmap.remove_route(tbm, ip, masklen, source), ip, masklen)
+    to_candidate(route)
+  end
+
+  @doc """
+  Returns the candidate routes of a prefix by order of preference, the first
+  one being selected. Prefixes set with `add/4` have no candidates.
+  """
+  @spec routes(t(), cidr()) :: [candidate()]
+  def routes(tree, cidr) do
+    {ip, masklen} = Prefix.parse!(cidr)
+    routes(tree, ip, masklen)
+  end
+
+  @spec routes(t(), :inet.ip_address() | raw_address(), masklen()) :: [candidate()]
+  def routes(tree, ip, masklen) do
+    {tbm, ip} = Prefix.family_key(tree, ip)
+    {:ok, routes} = Prefix.check!(TreeBitmap.routes(tbm, ip, masklen), ip, masklen)
+    Enum.map(routes, &to_candidate/1)
+  end
+
+  @doc """
+  Sets a prefix to a group of equal-cost next hops, replacing its value or
+  candidate routes.
+
+  `lookup/2` returns the whole group as value, `lookup_hashed/3` picks one
+  member per flow. Returns the previous value, or `nil`.
+  """
+  @spec add_ecmp(t(), cidr(), [any(), ...]) :: nil | any()
+  def add_ecmp(tree, cidr, members) do
+    {ip, masklen} = Prefix.parse!(cidr)
+    add_ecmp(tree, ip, masklen, members)
   end
 
-  @spec lookup(t(), :inet.ip_address()) :: map() | nil
+  @spec add_ecmp(t(), :inet.ip_address() | raw_address(), masklen(), [any(), ...]) :: nil | any()
+  def add_ecmp(tree, ip, masklen, [_ | _] = members) do
+    {tbm, ip} = Prefix.family_key(tree, ip)
+    {:ok, prev} = Prefix.check!(TreeBitmap.add_ecmp(tbm, ip, masklen, members), ip, masklen)
+    prev
+  end
+
+  @doc """
+  Same as `lookup/2`, but when the route is an ECMP group, returns the member
+  selected for the flow `hash` (such as `:erlang.phash2/1` of the 5-tuple)
+  as value.
+
+  Selection is deterministic and resilient: a hash always selects the same
+  member, and removing a member from the group only moves the flows it was
+  selected for.
+  """
+  @spec lookup_hashed(t(), address(), non_neg_integer()) :: map() | nil
+  def lookup_hashed(tree, ip, hash) do
+    {tbm, ip} = Prefix.family_key(tree, Prefix.address!(ip))
+
+    case TreeBitmap.longest_match_hashed(tbm, ip, hash) do
+      {:ok, prefix, masklen, value} -> to_match({prefix, masklen, value})
+      {:ok, nil} -> nil
+    end
+  end
+
+  @doc """
+  Returns the most specific route covering `ip`, given as a tuple, an
+  address string (`"10.0.0.1"`) or a tagged raw address
+  (`{:raw, <<10, 0, 0, 1>>}`).
+
+  Strings with a mask length raise `ArgumentError`, use `match/2` for
+  prefixes.
+  """
+  @spec lookup(t(), address()) :: map() | nil
   def lookup(tree, ip)
 
   def lookup(tree, {a, b, c, d}) do
-    longest_match(tree, tree.i4, {:inet4, a, b, c, d})
+    longest_match(tree.i4, {:inet4, a, b, c, d})
   end
 
   def lookup(tree, {a, b, c, d, e, f, g, h}) do
-    longest_match(tree, tree.i6, {:inet6, a, b, c, d, e, f, g, h})
+    longest_match(tree.i6, {:inet6, a, b, c, d, e, f, g, h})
+  end
+
+  def lookup(tree, address) do
+    {tbm, ip} = Prefix.family_key(tree, Prefix.address!(address))
+    longest_match(tbm, ip)
+  end
+
+  @doc """
+  Looks up many addresses at once, returning the result of `lookup/2` for
+  each of them, in order.
+  """
+  @spec lookup_many(t(), [address()]) :: [map() | nil]
+  def lookup_many(tree, ips) do
+    i4_tbm = tree.i4
+    keys = Enum.map(ips, &Prefix.family_key(tree, Prefix.address!(&1)))
+    {i4, i6} = Enum.split_with(keys, &match?({^i4_tbm, _}, &1))
+
+    r4 = longest_match_many(tree.i4, Enum.map(i4, &elem(&1, 1)))
+    r6 = longest_match_many(tree.i6, Enum.map(i6, &elem(&1, 1)))
+
+    {results, [], []} =
+      Enum.reduce(keys, {[], r4, r6}, fn
+        {^i4_tbm, _}, {acc, [result | r4], r6} -> {[result | acc], r4, r6}
+        _, {acc, r4, [result | r6]} -> {[result | acc], r4, r6}
+      end)
+
+    Enum.reverse(results)
+  end
+
+  @doc """
+  Same as `lookup_many/2`, with the addresses packed in network byte order
+  in a binary (`<<ip::32, ...>>` for `:inet4`, `<<ip::128, ...>>` for `:inet6`).
+  """
+  @spec lookup_packed(t(), :inet4 | :inet6, binary()) :: [map() | nil]
+  def lookup_packed(tree, family, ips)
+
+  def lookup_packed(tree, :inet4, ips) do
+    for result <- TreeBitmap.longest_match_packed(tree.i4, :inet4, ips), do: to_match(result)
+  end
+
+  def lookup_packed(tree, :inet6, ips) do
+    for result <- TreeBitmap.longest_match_packed(tree.i6, :inet6, ips), do: to_match(result)
   end
 
-  @spec match(t(), :inet.ip_address(), masklen()) :: map() | nil
+  @doc """
+  Resolves `ip` recursively, for routes whose value is a next-hop address.
+
+  Looks `ip` up, and as long as the value of the route is an
+  `t::inet.ip_address/0`, looks that next hop up in turn, until a route with
+  any other value (such as an interface) is reached. Returns the route of
+  each lookup, in order.
+
+  Options:
+
+    * `:via` - the table next hops are resolved in, such as the IGP table for
+      BGP routes. Defaults to `tree` itself.
+
+  Fails with `:unreachable` when a lookup finds no route, `:routing_loop`
+  when a route is reached twice, or `:max_depth` after `max_depth` lookups,
+  along with the routes resolved so far.
+  """
+  @spec resolve(t(), address(), non_neg_integer(), keyword()) ::
+          {:ok, [map()]} | {:error, :unreachable | :routing_loop | :max_depth, [map()]}
+  def resolve(tree, ip, max_depth, opts \\ []) do
+    {_tbm, ip} = Prefix.family_key(tree, Prefix.address!(ip))
+
+    via = Keyword.get(opts, :via, tree)
+
+    case TreeBitmap.resolve(tree.i4, tree.i6, via.i4, via.i6, ip, max_depth) do
+      {:ok, chain} -> {:ok, Enum.map(chain, &to_match/1)}
+      {:error, reason, chain} -> {:error, reason, Enum.map(chain, &to_match/1)}
+    end
+  end
+
+  @doc """
+  Returns every route covering `ip`, from the least to the most specific.
+
+  The last element is the route `lookup/2` returns.
+  """
+  @spec matches(t(), address()) :: [map()]
+  def matches(tree, ip)
+
+  def matches(tree, {a, b, c, d}) do
+    do_matches(tree.i4, {:inet4, a, b, c, d})
+  end
+
+  def matches(tree, {a, b, c, d, e, f, g, h}) do
+    do_matches(tree.i6, {:inet6, a, b, c, d, e, f, g, h})
+  end
+
+  def matches(tree, address) do
+    {tbm, ip} = Prefix.family_key(tree, Prefix.address!(address))
+    do_matches(tbm, ip)
+  end
+
+  @doc """
+  Returns the routes contained in `ip/masklen`, including the prefix itself,
+  in prefix order.
+  """
+  @spec more_specifics(t(), cidr()) :: [route()]
+  def more_specifics(tree, cidr) do
+    {ip, masklen} = Prefix.parse!(cidr)
+    do_more_specifics(family_tbm(tree, ip), ip, masklen)
+  end
+
+  @spec more_specifics(t(), :inet.ip_address() | raw_address(), masklen()) :: [route()]
+  def more_specifics(tree, ip, masklen)
+
+  def more_specifics(tree, {a, b, c, d}, masklen) do
+    do_more_specifics(tree.i4, {:inet4, a, b, c, d}, masklen)
+  end
+
+  def more_specifics(tree, {a, b, c, d, e, f, g, h}, masklen) do
+    do_more_specifics(tree.i6, {:inet6, a, b, c, d, e, f, g, h}, masklen)
+  end
+
+  def more_specifics(tree, <<_::32>> = ip, masklen) do
+    do_more_specifics(tree.i4, ip, masklen)
+  end
+
+  def more_specifics(tree, <<_::128>> = ip, masklen) do
+    do_more_specifics(tree.i6, ip, masklen)
+  end
+
+  @spec match(t(), cidr()) :: map() | nil
+  def match(tree, cidr) do
+    {ip, masklen} = Prefix.parse!(cidr)
+    exact_match(family_tbm(tree, ip), ip, masklen)
+  end
+
+  @spec match(t(), :inet.ip_address() | raw_address(), masklen()) :: map() | nil
   def match(tree, ip, masklen)
 
   def match(tree, {a, b, c, d}, masklen) do
-    exact_match(tree, tree.i4, {:inet4, a, b, c, d}, masklen)
+    exact_match(tree.i4, {:inet4, a, b, c, d}, masklen)
   end
 
   def match(tree, {a, b, c, d, e, f, g, h}, masklen) do
-    exact_match(tree, tree.i6, {:inet6, a, b, c, d, e, f, g, h}, masklen)
+    exact_match(tree.i6, {:inet6, a, b, c, d, e, f, g, h}, masklen)
+  end
+
+  def match(tree, <<_::32>> = ip, masklen) do
+    exact_match(tree.i4, ip, masklen)
+  end
+
+  def match(tree, <<_::128>> = ip, masklen) do
+    exact_match(tree.i6, ip, masklen)
   end
 
-  @spec reachable?(t(), :inet.ip_address()) :: boolean()
+  @spec reachable?(t(), address()) :: boolean()
   def reachable?(tree, ip)
 
   def reachable?(tree, {a, b, c, d}) do
-    longest_match?(tree, tree.i4, {:inet4, a, b, c, d})
+    longest_match?(tree.i4, {:inet4, a, b, c, d})
   end
 
   def reachable?(tree, {a, b, c, d, e, f, g, h}) do
-    longest_match?(tree, tree.i6, {:inet6, a, b, c, d, e, f, g, h})
+    longest_match?(tree.i6, {:inet6, a, b, c, d, e, f, g, h})
   end
 
-  @spec reachable?(t(), :inet.ip_address(), masklen()) :: boolean()
+  def reachable?(tree, address) do
+    {tbm, ip} = Prefix.family_key(tree, Prefix.address!(address))
+    longest_match?(tbm, ip)
+  end
+
+  @spec reachable?(t(), :inet.ip_address() | raw_address(), masklen()) :: boolean()
   def reachable?(tree, ip, masklen)
 
   def reachable?(tree, {a, b, c, d}, masklen) do
-    exact_match?(tree, tree.i4, {:inet4, a, b, c, d}, masklen)
+    exact_match?(tree.i4, {:inet4, a, b, c, d}, masklen)
   end
 
   def reachable?(tree, {a, b, c, d, e, f, g, h}, masklen) do
-    exact_match?(tree, tree.i6, {:inet6, a, b, c, d, e, f, g, h}, masklen)
+    exact_match?(tree.i6, {:inet6, a, b, c, d, e, f, g, h}, masklen)
+  end
+
+  def reachable?(tree, <<_::32>> = ip, masklen) do
+    exact_match?(tree.i4, ip, masklen)
+  end
+
+  def reachable?(tree, <<_::128>> = ip, masklen) do
+    exact_match?(tree.i6, ip, masklen)
+  end
+
+  @type route() :: {:inet.ip_address() | cidr(), masklen(), any()}
+
+  @doc "Returns every route of the table, IPv4 first, each family in prefix order."
+  @spec to_list(t()) :: [route()]
+  def to_list(tree) do
+    to_list(tree.i4, :inet4) ++ to_list(tree.i6, :inet6)
+  end
+
+  @doc """
+  Returns the addresses the table routes as ranges of consecutive addresses
+  with the same `lookup/2` value, IPv4 first, each family in order.
+
+  Adjacent prefixes with the same value make a single range, and a prefix
+  with more specifics of another value is split around them.
+  """
+  @spec ranges(t()) :: [{first :: :inet.ip_address(), last :: :inet.ip_address(), any()}]
+  def ranges(tree) do
+    ranges(tree.i4, :inet4) ++ ranges(tree.i6, :inet6)
+  end
+
+  @doc "Folds `fun` over every route of the table, in `to_list/1` order."
+  @spec fold(t(), acc, (route(), acc -> acc)) :: acc when acc: any()
+  def fold(tree, acc, fun) do
+    Enum.reduce(to_list(tree), acc, fun)
+  end
+
+  @doc """
+  Lazily streams every route of the table, in `to_list/1` order.
+
+  Routes are fetched `chunk_size` at a time, so huge tables do not block a
+  scheduler. Adding or removing a prefix while the stream is consumed makes
+  it raise.
+  """
+  @spec stream(t(), pos_integer()) :: Enumerable.t()
+  def stream(tree, chunk_size \\ 1000) do
+    Stream.concat(stream(tree.i4, :inet4, chunk_size), stream(tree.i6, :inet6, chunk_size))
+  end
+
+  @doc """
+  Serializes the table, routes and options, into a binary `load/1` restores.
+
+  The format is versioned and checksummed, dumps can be written to disk and
+  loaded by later releases.
+  """
+  @spec dump(t()) :: binary()
+  def dump(tree) do
+    i4 = TreeBitmap.dump(tree.i4)
+    <<byte_size(i4)::64, i4::binary, TreeBitmap.dump(tree.i6)::binary>>
+  end
+
+  @doc "Rebuilds a table from `dump/1` output."
+  @spec load(binary()) :: {:ok, t()} | {:error, :invalid_dump | :unsupported_version | :checksum_mismatch}
+  def load(<<size::64, i4::binary-size(size), i6::binary>>) do
+    with {:ok, i4} <- TreeBitmap.load(i4, :inet4),
+         {:ok, i6} <- TreeBitmap.load(i6, :inet6) do
+      {:ok, link(i4, i6)}
+    end
+  end
+
+  def load(_), do: {:error, :invalid_dump}
+
+  @typedoc "The value kept for a prefix both tables route: `:left`, `:right` or `{left, right}` with `:pair`."
+  @type merge() :: :left | :right | :pair
+
+  @doc """
+  Returns a new table with the routes of both tables.
+
+  Options:
+
+    * `:merge` - the value of a prefix both tables hold, see `t:merge/0`.
+      Defaults to `:left`.
+
+  The new table has the options of `left`. Both tables are walked together
+  rather than copied route by route.
+  """
+  @spec union(t(), t(), keyword()) :: t()
+  def union(left, right, opts \\ []) do
+    merge = Keyword.get(opts, :merge, :left)
+    link(TreeBitmap.union(left.i4, right.i4, merge), TreeBitmap.union(left.i6, right.i6, merge))
+  end
+
+  @doc """
+  Returns a new table covering the address space both tables cover.
+
+  Overlapping prefixes do not have to be identical: `10.0.0.0/8` and
+  `10.1.0.0/16` intersect as `10.1.0.0/16`. Looking an address up in the
+  new table matches what looking it up in both tables does, the value being
+  chosen with the `:merge` option as in `union/3` (the `:pair` of the values
+  both tables match).
+  """
+  @spec intersection(t(), t(), keyword()) :: t()
+  def intersection(left, right, opts \\ []) do
+    merge = Keyword.get(opts, :merge, :left)
+    link(TreeBitmap.intersection(left.i4, right.i4, merge), TreeBitmap.intersection(left.i6, right.i6, merge))
+  end
+
+  @doc """
+  Returns a new table covering the address space `left` covers and `right`
+  does not, with the values of `left`.
+
+  Routes of `left` containing routes of `right` are split into the fewest
+  prefixes around them: `10.0.0.0/8` minus `10.0.0.0/9` is `10.128.0.0/9`.
+  """
+  @spec difference(t(), t()) :: t()
+  def difference(left, right) do
+    link(TreeBitmap.difference(left.i4, right.i4), TreeBitmap.difference(left.i6, right.i6))
+  end
+
+  @doc """
+  Returns a new table with fewer routes giving the same `lookup/2` result for
+  every address.
+
+  Sibling prefixes with the same value are merged, `192.0.2.0/25` and
+  `192.0.2.128/25` becoming `192.0.2.0/24`, and routes with the value of the
+  route covering them are dropped. Used to build compact ACLs or announcement
+  lists out of per-host routes.
+  """
+  @spec aggregate(t()) :: t()
+  def aggregate(tree) do
+    link(TreeBitmap.aggregate(tree.i4), TreeBitmap.aggregate(tree.i6))
+  end
+
+  @type change() ::
+          {:added, :inet.ip_address() | cidr(), masklen(), any()}
+          | {:withdrawn, :inet.ip_address() | cidr(), masklen(), any()}
+          | {:changed, :inet.ip_address() | cidr(), masklen(), any(), any()}
+
+  @doc """
+  Returns the routes added, withdrawn or whose value changed from `old` to
+  `new`, IPv4 first, each family in prefix order as in `to_list/1`.
+
+  Paired with `clone/1`, previews the changes made to a working copy before
+  they are applied:
+
+      candidate = RoutingTable.clone(running)
+      RoutingTable.add(candidate, "192.0.2.0/24", :blackhole)
+      RoutingTable.diff(running, candidate)
+      #=> [{:added, {192, 0, 2, 0}, 24, :blackhole}]
+
+  The parts of the tables that did not change are skipped a trie node at a
+  time, so diffing full tables that differ by a few routes is fast.
+  """
+  @spec diff(t(), t()) :: [change()]
+  def diff(old, new) do
+    TreeBitmap.diff(old.i4, new.i4, :inet4) ++ TreeBitmap.diff(old.i6, new.i6, :inet6)
+  end
+
+  @doc """
+  Imports the routes of an MRT RIB dump, as published by RouteViews or RIPE
+  RIS, given as a binary or as `{:file, path}`. Compressed dumps must be
+  decompressed first.
+
+  `TABLE_DUMP_V2` unicast RIBs and legacy `TABLE_DUMP` records are read,
+  other records are skipped. Each prefix gets the list of its RIB entries as
+  value, replacing any existing one. Entries are maps with the `:peer_ip`,
+  `:peer_as`, `:originated_at`, `:origin` (`:igp`, `:egp` or `:incomplete`),
+  `:as_path` (`AS_SET`s as nested lists), `:origin_as` and `:next_hop` keys.
+  The 2-byte AS paths of `TABLE_DUMP` records are merged with their
+  `AS4_PATH`, so 4-byte AS numbers replace `AS_TRANS` (23456).
+  """
+  @spec import_mrt(t(), binary() | {:file, Path.t()}) ::
+          {:ok, %{inet4: non_neg_integer(), inet6: non_neg_integer()}} | {:error, atom()}
+  def import_mrt(tree, source) do
+    with {:ok, {inet4, inet6}} <- TreeBitmap.import_mrt(tree.i4, tree.i6, source) do
+      {:ok, %{inet4: inet4, inet6: inet6}}
+    end
   end
 
   @type tree_memory() :: {nodes :: non_neg_integer(), results :: non_neg_integer()}
-  @spec memory(t()) :: %{inet4: tree_memory(), inet6: tree_memory(), ets: non_neg_integer()}
+  @spec memory(t()) :: %{inet4: tree_memory(), inet6: tree_memory()}
   def memory(tree) do
-    %{inet4: TreeBitmap.memory(tree.i4), inet6: TreeBitmap.memory(tree.i6), ets: :ets.info(tree.ets, :memory)}
+    %{inet4: TreeBitmap.memory(tree.i4), inet6: TreeBitmap.memory(tree.i6)}
   end
 
-  @spec length(t()) :: %{inet4: non_neg_integer(), inet6: non_neg_integer(), ets: non_neg_integer()}
+  @spec length(t()) :: %{inet4: non_neg_integer(), inet6: non_neg_integer()}
   def length(tree) do
-    %{inet4: TreeBitmap.length(tree.i4), inet6: TreeBitmap.length(tree.i6), ets: :ets.info(tree.ets, :size)}
-  end
-
-  defp add(tree, tbm, ip, masklen, value) do
-    id = case :ets.match(tree.ets, {:'$1', :_, value}) do
-      [[id]] ->
-        :ets.update_counter(tree.ets, id, 1)
-        id
-      [] ->
-        id = :ets.update_counter(tree.ets, {__MODULE__, :counter}, 1, {{__MODULE__, :counter}, -1, 0})
-        :ets.insert(tree.ets, {id, 1, value})
-        id
-    end
-    {:ok, prev_id} = TreeBitmap.add(tbm, ip, masklen, id)
-    prev = if prev_id do
-      [{^prev_id, _refc, value}] = :ets.lookup(tree.ets, prev_id)
-      refc = :ets.update_counter(tree.ets, id, -1)
-      if refc < 1, do: :ets.delete(tree.ets, prev_id)
-      value
-    end
+    %{inet4: TreeBitmap.length(tree.i4), inet6: TreeBitmap.length(tree.i6)}
+  end
+
+  defp link(i4, i6) do
+    :ok = TreeBitmap.set_inet4(i6, i4)
+    %__MODULE__{i4: i4, i6: i6}
+  end
+
+  defp do_add(tbm, ip, masklen, value) do
+    {:ok, prev} = Prefix.check!(TreeBitmap.add(tbm, ip, masklen, value), ip, masklen)
     prev
   end
 
-  defp remove(tree, tbm, ip, masklen) do
-    {:ok, id} = TreeBitmap.remove(tbm, ip, masklen)
-    prev = if id do
-      [{^id, _refc, value}] = :ets.lookup(tree.ets, id)
-      refc = :ets.update_counter(tree.ets, id, -1)
-      if refc < 1, do: :ets.delete(tree.ets, id)
-      value
-    end
+  defp do_add_many(_tbm, []), do: []
+
+  defp do_add_many(tbm, routes) do
+    {routes, entries} = Enum.unzip(routes)
+    for {route, {:error, reason}} <- Enum.zip(routes, TreeBitmap.add_many(tbm, entries)), do: {route, reason}
+  end
+
+  defp do_remove(tbm, ip, masklen) do
+    {:ok, prev} = Prefix.check!(TreeBitmap.remove(tbm, ip, masklen), ip, masklen)
     prev
   end
 
-  defp longest_match(tree, tbm, ip) do
+  defp longest_match(tbm, ip) do
     case TreeBitmap.longest_match(tbm, ip) do
-      {:ok, prefix, masklen, id} ->
-        [{^id, _refc, value}] = :ets.lookup(tree.ets, id)
-        %{prefix: to_inet(prefix), len: masklen, value: value}
-      {:ok, nil} ->
-        nil
+      {:ok, prefix, masklen, value} -> to_match({prefix, masklen, value})
+      {:ok, nil} -> nil
+    end
+  end
+
+  defp longest_match_many(_tbm, []), do: []
+
+  defp longest_match_many(tbm, ips) do
+    for result <- TreeBitmap.longest_match_many(tbm, ips), do: to_match(result)
+  end
+
+  defp to_match({prefix, masklen, value}), do: %{prefix: Prefix.to_inet(prefix), len: masklen, value: value}
+  defp to_match(nil), do: nil
+
+  defp do_matches(tbm, ip) do
+    for {prefix, masklen, value} <- TreeBitmap.matches(tbm, ip) do
+      %{prefix: Prefix.to_inet(prefix), len: masklen, value: value}
+    end
+  end
+
+  defp do_more_specifics(tbm, ip, masklen) do
+    {:ok, routes} = Prefix.check!(TreeBitmap.more_specifics(tbm, ip, masklen), ip, masklen)
+    for {prefix, masklen, value} <- routes, do: {Prefix.to_inet(prefix), masklen, value}
+  end
+
+  defp exact_match(tbm, ip, masklen) do
+    {:ok, value} = Prefix.check!(TreeBitmap.exact_match(tbm, ip, masklen), ip, masklen)
+    value
+  end
+
+  defp to_list(tbm, family) do
+    for {prefix, masklen, value} <- TreeBitmap.to_list(tbm, family) do
+      {Prefix.to_inet(prefix), masklen, value}
     end
   end
 
-  defp exact_match(tree, tbm, ip, masklen) do
-    case TreeBitmap.exact_match(tbm, ip, masklen) do
-      {:ok, nil} ->
-        nil
-      {:ok, id} ->
-        [{^id, _refc, value}] = :ets.lookup(tree.ets, id)
-        value
+  defp ranges(tbm, family) do
+    for {first, last, value} <- TreeBitmap.ranges(tbm, family) do
+      {Prefix.to_inet(first), Prefix.to_inet(last), value}
     end
   end
 
-  defp longest_match?(_, tbm, ip) do
+  defp stream(tbm, family, chunk_size) do
+    Stream.resource(
+      fn -> TreeBitmap.iter(tbm, family) end,
+      fn
+        nil ->
+          {:halt, nil}
+
+        cursor ->
+          case TreeBitmap.iter_next(cursor, chunk_size) do
+            {:ok, []} ->
+              {:halt, cursor}
+
+            {:ok, routes} ->
+              routes = for {prefix, masklen, value} <- routes, do: {Prefix.to_inet(prefix), masklen, value}
+              {routes, if(Kernel.length(routes) < chunk_size, do: nil, else: cursor)}
+
+            {:error, :invalidated} ->
+              raise RuntimeError, "routing table modified while streaming"
+          end
+      end,
+      fn _ -> :ok end
+    )
+  end
+
+  defp longest_match?(tbm, ip) do
 	  case TreeBitmap.longest_match(tbm, ip) do
       {:ok, nil} -> false
       {:ok, _, _, _} -> true
     end
   end
 
-  defp exact_match?(_, tbm, ip, masklen) do
-	  case TreeBitmap.exact_match(tbm, ip, masklen) do
+  defp exact_match?(tbm, ip, masklen) do
+	  case Prefix.check!(TreeBitmap.exact_match(tbm, ip, masklen), ip, masklen) do
       {:ok, nil} -> false
       {:ok, _} -> true
     end
   end
 
-  defp to_inet({:inet4, a, b, c, d}), do: {a, b, c, d}
-  defp to_inet({:inet6, a, b, c, d, e, f, g, h}), do: {a, b, c, d, e, f, g, h}
+  defp family_tbm(tree, {:inet4, _, _, _, _}), do: tree.i4
+  defp family_tbm(tree, {:inet6, _, _, _, _, _, _, _, _}), do: tree.i6
+
+  defp to_candidate({source, distance, metric, value}),
+    do: %{source: source, distance: distance, metric: metric, value: value}
 
+  defp to_candidate(nil), do: nil
 end
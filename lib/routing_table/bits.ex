@@ -0,0 +1,118 @@
+defmodule RoutingTable.Bits do
+  alias RoutingTable.{Prefix, TreeBitmap}
+  defstruct [:tbm]
+
+  @opaque t() :: %__MODULE__{}
+  @typedoc "A prefix of at most 128 bits, its length is the prefix length."
+  @type prefix() :: bitstring()
+  @type route() :: {prefix(), RoutingTable.masklen(), any()}
+
+  @moduledoc """
+  A table keyed by arbitrary bitstrings rather than IP addresses, using the
+  same longest prefix match engine: MPLS labels, MAC address blocks, E.164
+  number prefixes...
+
+  Prefixes are bitstrings (`<<0x00, 0x1B, 0x63>>`, `<<0b1010::4>>`), or a
+  binary and a prefix length taking its first bits. Lookup keys are
+  bitstrings of any length.
+
+  ```elixir
+  table = RoutingTable.Bits.new()
+  RoutingTable.Bits.add(table, <<0x00, 0x1B, 0x63>>, :apple)
+  %{len: 24, value: :apple} = RoutingTable.Bits.lookup(table, <<0x00, 0x1B, 0x63, 0x84, 0x45, 0xE6>>)
+  ```
+  """
+
+  @doc "Creates a new table. The `:strict` option of `RoutingTable.new/1` applies."
+  @spec new(keyword()) :: t()
+  def new(opts \\ []) do
+    %__MODULE__{tbm: TreeBitmap.new_with_options(opts)}
+  end
+
+  @spec add(t(), prefix(), any()) :: nil | any()
+  def add(table, prefix, value) do
+    {key, masklen} = to_key(prefix)
+    add(table, key, masklen, value)
+  end
+
+  @doc "Adds the first `masklen` bits of `key` as a prefix, returns the value it replaced or `nil`."
+  @spec add(t(), binary(), RoutingTable.masklen(), any()) :: nil | any()
+  def add(table, key, masklen, value) do
+    {:ok, prev} = Prefix.check!(TreeBitmap.bits_add(table.tbm, key, masklen, value), key, masklen, &inspect/1)
+    prev
+  end
+
+  @spec remove(t(), prefix()) :: nil | any()
+  def remove(table, prefix) do
+    {key, masklen} = to_key(prefix)
+    remove(table, key, masklen)
+  end
+
+  @spec remove(t(), binary(), RoutingTable.masklen()) :: nil | any()
+  def remove(table, key, masklen) do
+    {:ok, prev} = Prefix.check!(TreeBitmap.bits_remove(table.tbm, key, masklen), key, masklen, &inspect/1)
+    prev
+  end
+
+  @spec match(t(), prefix()) :: nil | any()
+  def match(table, prefix) do
+    {key, masklen} = to_key(prefix)
+    match(table, key, masklen)
+  end
+
+  @spec match(t(), binary(), RoutingTable.masklen()) :: nil | any()
+  def match(table, key, masklen) do
+    {:ok, value} = Prefix.check!(TreeBitmap.bits_exact_match(table.tbm, key, masklen), key, masklen, &inspect/1)
+    value
+  end
+
+  @doc "Returns the longest prefix of `key`, or `nil`."
+  @spec lookup(t(), bitstring()) :: %{prefix: prefix(), len: RoutingTable.masklen(), value: any()} | nil
+  def lookup(table, key) do
+    {bytes, bits} = to_key(key)
+
+    case TreeBitmap.bits_longest_match(table.tbm, bytes, bits) do
+      {:ok, prefix, masklen, value} -> %{prefix: to_prefix(prefix, masklen), len: masklen, value: value}
+      {:ok, nil} -> nil
+    end
+  end
+
+  @doc "Returns every route of the table, in prefix order."
+  @spec to_list(t()) :: [route()]
+  def to_list(table) do
+    for {prefix, masklen, value} <- TreeBitmap.bits_to_list(table.tbm) do
+      {to_prefix(prefix, masklen), masklen, value}
+    end
+  end
+
+  @spec length(t()) :: non_neg_integer()
+  def length(table), do: TreeBitmap.length(table.tbm)
+
+  @spec memory(t()) :: RoutingTable.tree_memory()
+  def memory(table), do: TreeBitmap.memory(table.tbm)
+
+  @doc "Returns an independent copy of the table, see `RoutingTable.clone/1`."
+  @spec clone(t()) :: t()
+  def clone(table), do: %__MODULE__{tbm: TreeBitmap.clone(table.tbm)}
+
+  @doc "Serializes the table into a binary `load/1` restores, see `RoutingTable.dump/1`."
+  @spec dump(t()) :: binary()
+  def dump(table), do: TreeBitmap.dump(table.tbm)
+
+  @spec load(binary()) :: {:ok, t()} | {:error, atom()}
+  def load(dump) do
+    # keys take up to 128 bits, as IPv6 prefixes
+    with {:ok, tbm} <- TreeBitmap.load(dump, :inet6), do: {:ok, %__MODULE__{tbm: tbm}}
+  end
+
+  defp to_key(prefix) when is_bitstring(prefix) do
+    bits = bit_size(prefix)
+    pad = rem(8 - rem(bits, 8), 8)
+    {<<prefix::bitstring, 0::size(pad)>>, bits}
+  end
+
+  defp to_prefix(bytes, masklen) do
+    <<prefix::bitstring-size(masklen), _::bitstring>> = bytes
+    prefix
+  end
+end
@@ -0,0 +1,118 @@
+defmodule RoutingTable.IPSet do
+  alias RoutingTable.{Prefix, TreeBitmap}
+  defstruct [:i4, :i6, :opts]
+
+  @opaque t() :: %__MODULE__{}
+
+  @moduledoc """
+  A set of IPv4 and IPv6 prefixes without values, for membership only:
+  blocklists, allowlists, bogons...
+
+  Sets use the same trie as `RoutingTable` but store nothing for their
+  prefixes, so they take no more memory than the trie nodes.
+
+  ```elixir
+  bogons = RoutingTable.IPSet.new()
+  RoutingTable.IPSet.add(bogons, "10.0.0.0/8")
+  RoutingTable.IPSet.add(bogons, "192.168.0.0/16")
+  true = RoutingTable.IPSet.contains?(bogons, "10.1.2.3")
+  %{inet4: 16_842_752, inet6: 0} = RoutingTable.IPSet.cover_count(bogons)
+  ```
+  """
+
+  @doc "Creates a new set. The `:strict` and `:prefix_format` options of `RoutingTable.new/1` apply."
+  @spec new(keyword()) :: t()
+  def new(opts \\ []) do
+    %__MODULE__{i4: TreeBitmap.set_new(opts), i6: TreeBitmap.set_new(opts), opts: opts}
+  end
+
+  @doc "Adds a prefix, returns `false` when it was already in the set."
+  @spec add(t(), RoutingTable.cidr()) :: boolean()
+  def add(set, cidr) do
+    {ip, masklen} = Prefix.parse!(cidr)
+    add(set, ip, masklen)
+  end
+
+  @spec add(t(), :inet.ip_address() | RoutingTable.raw_address(), RoutingTable.masklen()) :: boolean()
+  def add(set, ip, masklen) do
+    {resource, ip} = Prefix.family_key(set, ip)
+    {:ok, added} = Prefix.check!(TreeBitmap.set_add(resource, ip, masklen), ip, masklen)
+    added
+  end
+
+  @doc "Removes a prefix, returns whether it was in the set."
+  @spec remove(t(), RoutingTable.cidr()) :: boolean()
+  def remove(set, cidr) do
+    {ip, masklen} = Prefix.parse!(cidr)
+    remove(set, ip, masklen)
+  end
+
+  @spec remove(t(), :inet.ip_address() | RoutingTable.raw_address(), RoutingTable.masklen()) :: boolean()
+  def remove(set, ip, masklen) do
+    {resource, ip} = Prefix.family_key(set, ip)
+    {:ok, removed} = Prefix.check!(TreeBitmap.set_remove(resource, ip, masklen), ip, masklen)
+    removed
+  end
+
+  @doc "Is `ip`, a `t:RoutingTable.address/0`, covered by a prefix of the set?"
+  @spec contains?(t(), RoutingTable.address()) :: boolean()
+  def contains?(set, ip) do
+    {resource, ip} = Prefix.family_key(set, Prefix.address!(ip))
+    TreeBitmap.set_contains(resource, ip)
+  end
+
+  @doc """
+  Returns the number of addresses covered by the set in each family.
+  Addresses covered by several prefixes are counted once.
+  """
+  @spec cover_count(t()) :: %{inet4: non_neg_integer(), inet6: non_neg_integer()}
+  def cover_count(set) do
+    %{
+      inet4: :binary.decode_unsigned(TreeBitmap.set_cover_count(set.i4, :inet4)),
+      inet6: :binary.decode_unsigned(TreeBitmap.set_cover_count(set.i6, :inet6))
+    }
+  end
+
+  @doc """
+  Returns a new set of the addresses of `parent` not covered by `set`, as
+  the fewest prefixes: the free space of an allocation, the address space a
+  blocklist leaves reachable...
+  """
+  @spec complement(t(), RoutingTable.cidr()) :: t()
+  def complement(set, parent) do
+    {ip, masklen} = Prefix.parse!(parent)
+    complement(set, ip, masklen)
+  end
+
+  @spec complement(t(), :inet.ip_address() | RoutingTable.raw_address(), RoutingTable.masklen()) :: t()
+  def complement(set, ip, masklen) do
+    {resource, ip} = Prefix.family_key(set, ip)
+    {:ok, complement} = Prefix.check!(TreeBitmap.set_complement(resource, ip, masklen), ip, masklen)
+
+    case ip do
+      {:inet4, _, _, _, _} -> %__MODULE__{set | i4: complement, i6: TreeBitmap.set_new(set.opts)}
+      {:inet6, _, _, _, _, _, _, _, _} -> %__MODULE__{set | i4: TreeBitmap.set_new(set.opts), i6: complement}
+    end
+  end
+
+  @doc "Returns every prefix of the set, IPv4 first, each family in prefix order."
+  @spec to_list(t()) :: [{:inet.ip_address() | RoutingTable.cidr(), RoutingTable.masklen()}]
+  def to_list(set) do
+    to_list(set.i4, :inet4) ++ to_list(set.i6, :inet6)
+  end
+
+  @spec length(t()) :: %{inet4: non_neg_integer(), inet6: non_neg_integer()}
+  def length(set) do
+    %{inet4: TreeBitmap.set_length(set.i4), inet6: TreeBitmap.set_length(set.i6)}
+  end
+
+  @doc "Returns the bytes used by the trie nodes of each address family."
+  @spec memory(t()) :: %{inet4: non_neg_integer(), inet6: non_neg_integer()}
+  def memory(set) do
+    %{inet4: TreeBitmap.set_memory(set.i4), inet6: TreeBitmap.set_memory(set.i6)}
+  end
+
+  defp to_list(resource, family) do
+    for {prefix, masklen} <- TreeBitmap.set_to_list(resource, family), do: {Prefix.to_inet(prefix), masklen}
+  end
+end
@@ -0,0 +1,126 @@
+defmodule RoutingTable.MAC do
+  alias RoutingTable.{Prefix, TreeBitmap}
+  defstruct [:eui48, :eui64]
+
+  @opaque t() :: %__MODULE__{}
+  @typedoc "An EUI-48 or EUI-64 address, as a tuple of bytes."
+  @type address() ::
+          {byte(), byte(), byte(), byte(), byte(), byte()}
+          | {byte(), byte(), byte(), byte(), byte(), byte(), byte(), byte()}
+  @typedoc "An address, or its colon or hyphen separated hex string (`\"00:1b:63:84:45:e6\"`)."
+  @type mac() :: address() | String.t()
+  @typedoc "A prefix string such as `\"00:1b:63:00:00:00/24\"`, or a full address."
+  @type cidr() :: String.t()
+  @type masklen() :: 0..64
+  @type route() :: {address(), masklen(), any()}
+
+  @moduledoc """
+  A table of MAC address blocks, keyed by EUI-48 and EUI-64 prefixes.
+
+  EUI-48 and EUI-64 prefixes are kept apart, as IPv4 and IPv6 ones in
+  `RoutingTable`: an address only matches prefixes of its own kind.
+  Registry blocks such as OUIs cover both kinds, `import_oui/2` adds them
+  as each.
+
+  ```elixir
+  table = RoutingTable.MAC.new()
+  {:ok, _count} = RoutingTable.MAC.import_oui(table, {:file, "oui.csv"})
+  %{len: 24, value: %{organization: "Apple, Inc."}} = RoutingTable.MAC.lookup(table, "00:1b:63:84:45:e6")
+  ```
+  """
+
+  @doc "Creates a new table. The `:strict` option of `RoutingTable.new/1` applies."
+  @spec new(keyword()) :: t()
+  def new(opts \\ []) do
+    %__MODULE__{eui48: TreeBitmap.new_with_options(opts), eui64: TreeBitmap.new_with_options(opts)}
+  end
+
+  @spec add(t(), cidr(), any()) :: nil | any()
+  def add(table, cidr, value) do
+    {mac, masklen} = parse_prefix!(cidr)
+    add(table, mac, masklen, value)
+  end
+
+  @doc "Adds a prefix, returns the value it replaced or `nil`."
+  @spec add(t(), mac(), masklen(), any()) :: nil | any()
+  def add(table, mac, masklen, value) do
+    {:ok, prev} = Prefix.check!(TreeBitmap.mac_add(table.eui48, table.eui64, mac, masklen, value), mac, masklen, &inspect/1)
+    prev
+  end
+
+  @spec remove(t(), cidr()) :: nil | any()
+  def remove(table, cidr) do
+    {mac, masklen} = parse_prefix!(cidr)
+    remove(table, mac, masklen)
+  end
+
+  @spec remove(t(), mac(), masklen()) :: nil | any()
+  def remove(table, mac, masklen) do
+    {:ok, prev} = Prefix.check!(TreeBitmap.mac_remove(table.eui48, table.eui64, mac, masklen), mac, masklen, &inspect/1)
+    prev
+  end
+
+  @spec match(t(), cidr()) :: nil | any()
+  def match(table, cidr) do
+    {mac, masklen} = parse_prefix!(cidr)
+    match(table, mac, masklen)
+  end
+
+  @spec match(t(), mac(), masklen()) :: nil | any()
+  def match(table, mac, masklen) do
+    {:ok, value} = Prefix.check!(TreeBitmap.mac_exact_match(table.eui48, table.eui64, mac, masklen), mac, masklen, &inspect/1)
+    value
+  end
+
+  @doc "Returns the longest prefix covering `mac`, or `nil`."
+  @spec lookup(t(), mac()) :: %{prefix: address(), len: masklen(), value: any()} | nil
+  def lookup(table, mac) do
+    case TreeBitmap.mac_longest_match(table.eui48, table.eui64, mac) do
+      {:ok, prefix, masklen, value} -> %{prefix: prefix, len: masklen, value: value}
+      {:ok, nil} -> nil
+    end
+  end
+
+  @doc "Returns every EUI-48 prefix of the table then every EUI-64 one, each in prefix order."
+  @spec to_list(t()) :: [route()]
+  def to_list(table), do: TreeBitmap.mac_to_list(table.eui48, table.eui64)
+
+  @doc """
+  Adds the blocks of an IEEE registry CSV file (MA-L `oui.csv`, MA-M
+  `mam.csv`, MA-S `oui36.csv` or `iab.csv`), given as a binary or as
+  `{:file, path}`. CID assignments are skipped.
+
+  Each block is added as an EUI-48 and as an EUI-64 prefix, with a map with
+  the `:registry`, `:organization` and `:address` keys as value, replacing
+  any existing one. Returns the number of blocks added, the table is left
+  unchanged on errors.
+  """
+  @spec import_oui(t(), binary() | {:file, Path.t()}) :: {:ok, non_neg_integer()} | {:error, atom()}
+  def import_oui(table, source), do: TreeBitmap.import_oui(table.eui48, table.eui64, source)
+
+  @spec length(t()) :: %{eui48: non_neg_integer(), eui64: non_neg_integer()}
+  def length(table) do
+    %{eui48: TreeBitmap.length(table.eui48), eui64: TreeBitmap.length(table.eui64)}
+  end
+
+  @spec memory(t()) :: %{eui48: RoutingTable.tree_memory(), eui64: RoutingTable.tree_memory()}
+  def memory(table) do
+    %{eui48: TreeBitmap.memory(table.eui48), eui64: TreeBitmap.memory(table.eui64)}
+  end
+
+  defp parse_prefix!(cidr) do
+    case String.split(cidr, "/") do
+      [mac] ->
+        {mac, mac |> String.split([":", "-"]) |> Kernel.length() |> Kernel.*(8)}
+
+      [mac, masklen] ->
+        case Integer.parse(masklen) do
+          {masklen, ""} -> {mac, masklen}
+          _ -> raise ArgumentError, "invalid prefix #{inspect(cidr)}"
+        end
+
+      _ ->
+        raise ArgumentError, "invalid prefix #{inspect(cidr)}"
+    end
+  end
+end
@@ -0,0 +1,61 @@
+defmodule RoutingTable.Prefix do
+  alias RoutingTable.TreeBitmap
+  @moduledoc false
+
+  # Prefix parsing and conversions shared by the table modules.
+
+  # The resource of the family of `ip` among the `:i4` and `:i6` fields of
+  # `tables`, and `ip` as the NIFs take it.
+  def family_key(tables, {a, b, c, d}), do: {tables.i4, {:inet4, a, b, c, d}}
+  def family_key(tables, {a, b, c, d, e, f, g, h}), do: {tables.i6, {:inet6, a, b, c, d, e, f, g, h}}
+  def family_key(tables, {:inet4, _, _, _, _} = ip), do: {tables.i4, ip}
+  def family_key(tables, {:inet6, _, _, _, _, _, _, _, _} = ip), do: {tables.i6, ip}
+  def family_key(tables, <<_::32>> = ip), do: {tables.i4, ip}
+  def family_key(tables, <<_::128>> = ip), do: {tables.i6, ip}
+
+  def parse!(cidr) do
+    case TreeBitmap.parse_prefix(cidr) do
+      {:ok, ip, masklen} -> {ip, masklen}
+      {:error, reason} -> raise ArgumentError, "invalid prefix #{inspect(cidr)}: #{reason}"
+    end
+  end
+
+  # An address given as a tuple, an address string, or a
+  # `{:raw, t:RoutingTable.raw_address/0}`: a bare binary is always a string,
+  # some 16-byte strings (`"2001:db8::dead:1"`) are also valid raw addresses.
+  def address!({:raw, <<_::32>> = ip}), do: ip
+  def address!({:raw, <<_::128>> = ip}), do: ip
+  def address!({:raw, raw}), do: raise(ArgumentError, "invalid raw address #{inspect(raw)}")
+
+  def address!(address) when is_binary(address) do
+    if String.contains?(address, "/") do
+      raise ArgumentError, "invalid address #{inspect(address)}: expected an address, not a prefix"
+    end
+
+    elem(parse!(address), 0)
+  end
+
+  def address!(ip), do: ip
+
+  # Raises on a NIF error about `prefix/masklen`, else returns `result`.
+  def check!(result, prefix, masklen, format \\ &format_ip/1)
+
+  def check!({:error, reason}, prefix, masklen, format) do
+    raise ArgumentError, "invalid prefix #{format.(prefix)}/#{masklen}: #{reason}"
+  end
+
+  def check!(result, _prefix, _masklen, _format), do: result
+
+  def format_ip(<<a, b, c, d>>), do: format_ip({:inet4, a, b, c, d})
+
+  def format_ip(<<a::16, b::16, c::16, d::16, e::16, f::16, g::16, h::16>>),
+    do: format_ip({:inet6, a, b, c, d, e, f, g, h})
+
+  def format_ip({:inet4, _, _, _, _} = ip), do: :inet.ntoa(to_inet(ip))
+  def format_ip({:inet6, _, _, _, _, _, _, _, _} = ip), do: :inet.ntoa(to_inet(ip))
+  def format_ip(ip), do: inspect(ip)
+
+  def to_inet({:inet4, a, b, c, d}), do: {a, b, c, d}
+  def to_inet({:inet6, a, b, c, d, e, f, g, h}), do: {a, b, c, d, e, f, g, h}
+  def to_inet(cidr) when is_binary(cidr), do: cidr
+end
@@ -3,12 +3,68 @@ defmodule RoutingTable.TreeBitmap do
   @moduledoc false
 
   def new(), do: :erlang.nif_error(:nif_not_loaded)
+  def new_with_options(_), do: :erlang.nif_error(:nif_not_loaded)
   def new_with_capacity(_), do: :erlang.nif_error(:nif_not_loaded)
+  def clone(_), do: :erlang.nif_error(:nif_not_loaded)
+  def set_inet4(_, _), do: :erlang.nif_error(:nif_not_loaded)
   def length(_), do: :erlang.nif_error(:nif_not_loaded)
   def add(_, _, _, _), do: :erlang.nif_error(:nif_not_loaded)
+  def add_many(_, _), do: :erlang.nif_error(:nif_not_loaded)
+  def add_range(_, _, _, _), do: :erlang.nif_error(:nif_not_loaded)
   def longest_match(_, _), do: :erlang.nif_error(:nif_not_loaded)
+  def longest_match_hashed(_, _, _), do: :erlang.nif_error(:nif_not_loaded)
+  def longest_match_many(_, _), do: :erlang.nif_error(:nif_not_loaded)
+  def longest_match_packed(_, _, _), do: :erlang.nif_error(:nif_not_loaded)
+  def resolve(_, _, _, _, _, _), do: :erlang.nif_error(:nif_not_loaded)
+  def matches(_, _), do: :erlang.nif_error(:nif_not_loaded)
+  def more_specifics(_, _, _), do: :erlang.nif_error(:nif_not_loaded)
   def exact_match(_, _, _), do: :erlang.nif_error(:nif_not_loaded)
   def remove(_, _, _), do: :erlang.nif_error(:nif_not_loaded)
+  def add_route(_, _, _, _), do: :erlang.nif_error(:nif_not_loaded)
+  def remove_route(_, _, _, _), do: :erlang.nif_error(:nif_not_loaded)
+  def routes(_, _, _), do: :erlang.nif_error(:nif_not_loaded)
+  def add_ecmp(_, _, _, _), do: :erlang.nif_error(:nif_not_loaded)
+  def to_list(_, _), do: :erlang.nif_error(:nif_not_loaded)
+  def ranges(_, _), do: :erlang.nif_error(:nif_not_loaded)
+  def iter(_, _), do: :erlang.nif_error(:nif_not_loaded)
+  def iter_next(_, _), do: :erlang.nif_error(:nif_not_loaded)
+  def bits_add(_, _, _, _), do: :erlang.nif_error(:nif_not_loaded)
+  def bits_remove(_, _, _), do: :erlang.nif_error(:nif_not_loaded)
+  def bits_exact_match(_, _, _), do: :erlang.nif_error(:nif_not_loaded)
+  def bits_longest_match(_, _, _), do: :erlang.nif_error(:nif_not_loaded)
+  def bits_to_list(_), do: :erlang.nif_error(:nif_not_loaded)
+  def mac_add(_, _, _, _, _), do: :erlang.nif_error(:nif_not_loaded)
+  def mac_remove(_, _, _, _), do: :erlang.nif_error(:nif_not_loaded)
+  def mac_exact_match(_, _, _, _), do: :erlang.nif_error(:nif_not_loaded)
+  def mac_longest_match(_, _, _), do: :erlang.nif_error(:nif_not_loaded)
+  def mac_to_list(_, _), do: :erlang.nif_error(:nif_not_loaded)
+  def parse_prefix(_), do: :erlang.nif_error(:nif_not_loaded)
+  def dump(_), do: :erlang.nif_error(:nif_not_loaded)
+  def load(_, _), do: :erlang.nif_error(:nif_not_loaded)
+  def union(_, _, _), do: :erlang.nif_error(:nif_not_loaded)
+  def intersection(_, _, _), do: :erlang.nif_error(:nif_not_loaded)
+  def difference(_, _), do: :erlang.nif_error(:nif_not_loaded)
+  def diff(_, _, _), do: :erlang.nif_error(:nif_not_loaded)
+  def aggregate(_), do: :erlang.nif_error(:nif_not_loaded)
+  def import_mrt(_, _, _), do: :erlang.nif_error(:nif_not_loaded)
+  def import_oui(_, _, _), do: :erlang.nif_error(:nif_not_loaded)
   def memory(_), do: :erlang.nif_error(:nif_not_loaded)
+  def vrf_new(_), do: :erlang.nif_error(:nif_not_loaded)
+  def vrf_add(_, _, _, _, _), do: :erlang.nif_error(:nif_not_loaded)
+  def vrf_remove(_, _, _, _), do: :erlang.nif_error(:nif_not_loaded)
+  def vrf_clear(_, _), do: :erlang.nif_error(:nif_not_loaded)
+  def vrf_longest_match(_, _, _), do: :erlang.nif_error(:nif_not_loaded)
+  def vrf_exact_match(_, _, _, _), do: :erlang.nif_error(:nif_not_loaded)
+  def vrf_set_fallback(_, _, _), do: :erlang.nif_error(:nif_not_loaded)
+  def vrf_memory(_), do: :erlang.nif_error(:nif_not_loaded)
+  def set_new(_), do: :erlang.nif_error(:nif_not_loaded)
+  def set_add(_, _, _), do: :erlang.nif_error(:nif_not_loaded)
+  def set_remove(_, _, _), do: :erlang.nif_error(:nif_not_loaded)
+  def set_contains(_, _), do: :erlang.nif_error(:nif_not_loaded)
+  def set_cover_count(_, _), do: :erlang.nif_error(:nif_not_loaded)
+  def set_complement(_, _, _), do: :erlang.nif_error(:nif_not_loaded)
+  def set_to_list(_, _), do: :erlang.nif_error(:nif_not_loaded)
+  def set_length(_), do: :erlang.nif_error(:nif_not_loaded)
+  def set_memory(_), do: :erlang.nif_error(:nif_not_loaded)
 
 end
@@ -0,0 +1,139 @@
+defmodule RoutingTable.VRF do
+  alias RoutingTable.{Prefix, TreeBitmap}
+  defstruct [:i4, :i6]
+
+  @opaque t() :: %__MODULE__{}
+  @typedoc "A VRF id, as a 32-bit unsigned integer."
+  @type vrf() :: non_neg_integer()
+
+  @moduledoc """
+  Many isolated routing tables (VRFs) in a single table.
+
+  All VRFs share the same two NIF resources and the same trie allocator
+  pools, so hundreds of VRFs cost little more than their routes. Routes
+  of a VRF are never visible from another, unless a fallback is set with
+  `set_fallback/3`.
+
+  ```elixir
+  vrfs = RoutingTable.VRF.new()
+  RoutingTable.VRF.add(vrfs, 0, "0.0.0.0/0", :internet)
+  RoutingTable.VRF.add(vrfs, 100, "10.0.0.0/8", :customer_a)
+  nil = RoutingTable.VRF.lookup(vrfs, 100, {192, 0, 2, 1})
+  :ok = RoutingTable.VRF.set_fallback(vrfs, 100, 0)
+  %{vrf: 0, value: :internet} = RoutingTable.VRF.lookup(vrfs, 100, {192, 0, 2, 1})
+  ```
+  """
+
+  @doc "Creates a new table, taking the options of `RoutingTable.new/1`."
+  @spec new(keyword()) :: t()
+  def new(opts \\ []) do
+    %__MODULE__{i4: TreeBitmap.vrf_new(opts), i6: TreeBitmap.vrf_new(opts)}
+  end
+
+  @doc "Adds a route to `vrf`, returns the value it replaced or `nil`."
+  @spec add(t(), vrf(), RoutingTable.cidr(), any()) :: nil | any()
+  def add(vrfs, vrf, cidr, value) do
+    {ip, masklen} = Prefix.parse!(cidr)
+    add(vrfs, vrf, ip, masklen, value)
+  end
+
+  @spec add(t(), vrf(), :inet.ip_address() | RoutingTable.raw_address(), RoutingTable.masklen(), any()) ::
+          nil | any()
+  def add(vrfs, vrf, ip, masklen, value) do
+    {resource, ip} = Prefix.family_key(vrfs, ip)
+    {:ok, prev} = Prefix.check!(TreeBitmap.vrf_add(resource, vrf, ip, masklen, value), ip, masklen)
+    prev
+  end
+
+  @doc "Removes a route from `vrf`, returns its value or `nil`."
+  @spec remove(t(), vrf(), RoutingTable.cidr()) :: nil | any()
+  def remove(vrfs, vrf, cidr) do
+    {ip, masklen} = Prefix.parse!(cidr)
+    remove(vrfs, vrf, ip, masklen)
+  end
+
+  @spec remove(t(), vrf(), :inet.ip_address() | RoutingTable.raw_address(), RoutingTable.masklen()) ::
+          nil | any()
+  def remove(vrfs, vrf, ip, masklen) do
+    {resource, ip} = Prefix.family_key(vrfs, ip)
+    {:ok, prev} = Prefix.check!(TreeBitmap.vrf_remove(resource, vrf, ip, masklen), ip, masklen)
+    prev
+  end
+
+  @doc "Removes every route of `vrf`, returns how many there were."
+  @spec delete(t(), vrf()) :: non_neg_integer()
+  def delete(vrfs, vrf) do
+    TreeBitmap.vrf_clear(vrfs.i4, vrf) + TreeBitmap.vrf_clear(vrfs.i6, vrf)
+  end
+
+  @doc """
+  Returns the most specific route of `vrf` covering `ip`, a
+  `t:RoutingTable.address/0`.
+
+  When `vrf` has no matching route, its fallback VRF is searched, and so on.
+  `:vrf` is the VRF the route was found in.
+  """
+  @spec lookup(t(), vrf(), RoutingTable.address()) :: map() | nil
+  def lookup(vrfs, vrf, ip) do
+    {resource, ip} = Prefix.family_key(vrfs, Prefix.address!(ip))
+
+    case TreeBitmap.vrf_longest_match(resource, vrf, ip) do
+      {:ok, found, prefix, masklen, value} -> %{vrf: found, prefix: Prefix.to_inet(prefix), len: masklen, value: value}
+      {:ok, nil} -> nil
+    end
+  end
+
+  @doc "Returns the value of a route of `vrf`, without fallback, or `nil`."
+  @spec match(t(), vrf(), RoutingTable.cidr()) :: nil | any()
+  def match(vrfs, vrf, cidr) do
+    {ip, masklen} = Prefix.parse!(cidr)
+    match(vrfs, vrf, ip, masklen)
+  end
+
+  @spec match(t(), vrf(), :inet.ip_address() | RoutingTable.raw_address(), RoutingTable.masklen()) ::
+          nil | any()
+  def match(vrfs, vrf, ip, masklen) do
+    {resource, ip} = Prefix.family_key(vrfs, ip)
+    {:ok, value} = Prefix.check!(TreeBitmap.vrf_exact_match(resource, vrf, ip, masklen), ip, masklen)
+    value
+  end
+
+  @doc """
+  Leaks routes of `fallback` into `vrf`: lookups in `vrf` matching no route
+  are made in `fallback` instead. Fallbacks chain, cycles end the lookup.
+  `nil` removes the fallback.
+  """
+  @spec set_fallback(t(), vrf(), vrf() | nil) :: :ok
+  def set_fallback(vrfs, vrf, fallback) do
+    :ok = TreeBitmap.vrf_set_fallback(vrfs.i4, vrf, fallback)
+    :ok = TreeBitmap.vrf_set_fallback(vrfs.i6, vrf, fallback)
+  end
+
+  @type vrf_memory() :: %{routes: non_neg_integer(), results: non_neg_integer()}
+
+  @doc """
+  Returns the memory used by each address family, and by each VRF holding
+  routes.
+
+  Trie nodes are shared by all VRFs and only reported by family. VRF
+  results count the routes and their values, both families included.
+  """
+  @spec memory(t()) :: %{
+          inet4: RoutingTable.tree_memory(),
+          inet6: RoutingTable.tree_memory(),
+          vrfs: %{vrf() => vrf_memory()}
+        }
+  def memory(vrfs) do
+    {n4, r4, v4} = TreeBitmap.vrf_memory(vrfs.i4)
+    {n6, r6, v6} = TreeBitmap.vrf_memory(vrfs.i6)
+
+    by_vrf =
+      Enum.reduce(v4 ++ v6, %{}, fn {vrf, routes, results}, acc ->
+        Map.update(acc, vrf, %{routes: routes, results: results}, fn usage ->
+          %{routes: usage.routes + routes, results: usage.results + results}
+        end)
+      end)
+
+    %{inet4: {n4, r4}, inet6: {n6, r6}, vrfs: by_vrf}
+  end
+end
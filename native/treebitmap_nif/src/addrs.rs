@@ -1,11 +1,16 @@
-use crate::nibbles::{Nibbles, NibblesV4, NibblesV6};
-use rustler::{NifRecord, NifUntaggedEnum};
+use crate::nibbles::{Nibbles, NibblesMac, NibblesV4, NibblesV6};
+use rustler::{
+    types::tuple::{get_tuple, make_tuple},
+    Binary, Decoder, Encoder, Env, Error, NifRecord, NifResult, NifUnitEnum, Term,
+};
+use std::convert::TryInto;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
 
 pub trait Maskable {
     fn mask(self, masklen: u32) -> Self;
 }
 
-#[derive(Debug, NifRecord, Copy, Clone)]
+#[derive(Debug, NifRecord, Copy, Clone, PartialEq, Eq, Hash)]
 #[tag = "inet4"]
 pub struct TupleV4 {
     pub a: u8,
@@ -27,6 +32,15 @@ impl TupleV4 {
     pub fn octets(&self) -> [u8; 4] {
         [self.a, self.b, self.c, self.d]
     }
+
+    /// Rebuilds an address from a nibble path, missing nibbles are zero.
+    pub fn from_nibbles(nibbles: &[u8]) -> Self {
+        let mut num: u32 = 0;
+        for i in 0..8 {
+            num = num << 4 | *nibbles.get(i).unwrap_or(&0) as u32;
+        }
+        TupleV4::from(num)
+    }
 }
 
 impl Maskable for TupleV4 {
@@ -59,7 +73,7 @@ impl ::std::convert::From<TupleV4> for u32 {
     }
 }
 
-#[derive(Debug, NifRecord, Copy, Clone)]
+#[derive(Debug, NifRecord, Copy, Clone, PartialEq, Eq, Hash)]
 #[tag = "inet6"]
 pub struct TupleV6 {
     pub a1: u16,
@@ -87,6 +101,20 @@ impl TupleV6 {
         }
     }
 
+    pub fn from_octets(bytes: [u8; 16]) -> Self {
+        let segment = |i: usize| (bytes[i * 2] as u16) << 8 | (bytes[i * 2 + 1] as u16);
+        Self::new(
+            segment(0),
+            segment(1),
+            segment(2),
+            segment(3),
+            segment(4),
+            segment(5),
+            segment(6),
+            segment(7),
+        )
+    }
+
     fn octets(&self) -> [u8; 16] {
         [
             (self.a1 >> 8) as u8,
@@ -108,7 +136,7 @@ impl TupleV6 {
         ]
     }
 
-    fn segments(&self) -> [u16; 8] {
+    pub fn segments(&self) -> [u16; 8] {
         let bytes = self.octets();
         [
             (bytes[0] as u16) << 8 | (bytes[1] as u16),
@@ -121,13 +149,26 @@ impl TupleV6 {
             (bytes[14] as u16) << 8 | (bytes[15] as u16),
         ]
     }
+
+    /// Rebuilds an address from a nibble path, missing nibbles are zero.
+    pub fn from_nibbles(nibbles: &[u8]) -> Self {
+        let mut ret: [u16; 8] = [0; 8];
+        for (i, segment) in ret.iter_mut().enumerate() {
+            for j in 0..4 {
+                *segment = *segment << 4 | *nibbles.get(i * 4 + j).unwrap_or(&0) as u16;
+            }
+        }
+        Self::new(
+            ret[0], ret[1], ret[2], ret[3], ret[4], ret[5], ret[6], ret[7],
+        )
+    }
 }
 
 impl Maskable for TupleV6 {
     fn mask(self, masklen: u32) -> Self {
         debug_assert!(masklen <= 128);
         let mut ret = self.segments();
-        for i in ((masklen + 15) / 16)..8 {
+        for i in masklen.div_ceil(16)..8 {
             ret[i as usize] = 0;
         }
         if masklen % 16 != 0 {
@@ -151,12 +192,157 @@ impl ::std::convert::From<TupleV6> for Nibbles {
     }
 }
 
-#[derive(NifUntaggedEnum, Copy, Clone)]
+#[derive(NifUnitEnum, Copy, Clone)]
+pub enum AddrFamily {
+    Inet4,
+    Inet6,
+}
+
+impl AddrFamily {
+    /// Length of an address in bytes.
+    pub fn octets(&self) -> usize {
+        match self {
+            AddrFamily::Inet4 => 4,
+            AddrFamily::Inet6 => 16,
+        }
+    }
+}
+
+/// An address, decoded from an ```{:inet4, ...}``` / ```{:inet6, ...}```
+/// record or from a 4 or 16 bytes binary in network byte order. Always
+/// encoded as a record.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
 pub enum AddrTuple {
     V4(TupleV4),
     V6(TupleV6),
 }
 
+impl<'a> Decoder<'a> for AddrTuple {
+    fn decode(term: Term<'a>) -> NifResult<Self> {
+        if let Ok(v4) = term.decode::<TupleV4>() {
+            return Ok(AddrTuple::V4(v4));
+        }
+        if let Ok(v6) = term.decode::<TupleV6>() {
+            return Ok(AddrTuple::V6(v6));
+        }
+        let bytes: Binary = term.decode()?;
+        let family = match bytes.len() {
+            4 => AddrFamily::Inet4,
+            16 => AddrFamily::Inet6,
+            _ => return Err(Error::BadArg),
+        };
+        AddrTuple::from_packed(family, &bytes).ok_or(Error::BadArg)
+    }
+}
+
+impl Encoder for AddrTuple {
+    fn encode<'a>(&self, env: Env<'a>) -> Term<'a> {
+        match self {
+            AddrTuple::V4(v4) => v4.encode(env),
+            AddrTuple::V6(v6) => v6.encode(env),
+        }
+    }
+}
+
+impl AddrTuple {
+    pub fn family(&self) -> AddrFamily {
+        match self {
+            AddrTuple::V4(_) => AddrFamily::Inet4,
+            AddrTuple::V6(_) => AddrFamily::Inet6,
+        }
+    }
+
+    /// Decodes an address packed in network byte order, 4 bytes for IPv4 and
+    /// 16 for IPv6.
+    pub fn from_packed(family: AddrFamily, bytes: &[u8]) -> Option<Self> {
+        match family {
+            AddrFamily::Inet4 => {
+                let bytes: [u8; 4] = bytes.try_into().ok()?;
+                Some(AddrTuple::V4(TupleV4::from(u32::from_be_bytes(bytes))))
+            }
+            AddrFamily::Inet6 => Some(AddrTuple::V6(TupleV6::from_octets(bytes.try_into().ok()?))),
+        }
+    }
+
+    /// Parses ```addr/masklen```, or a bare address as a host prefix. The
+    /// mask length is not checked against the address family.
+    pub fn parse_cidr(s: &str) -> Option<(Self, u32)> {
+        let (addr, masklen) = match s.split_once('/') {
+            Some((addr, masklen)) => (addr, Some(masklen)),
+            None => (s, None),
+        };
+        let ip = match addr.parse::<IpAddr>().ok()? {
+            IpAddr::V4(v4) => AddrTuple::V4(TupleV4::from(u32::from(v4))),
+            IpAddr::V6(v6) => AddrTuple::V6(TupleV6::from_octets(v6.octets())),
+        };
+        let masklen = match masklen {
+            Some(masklen) if !masklen.is_empty() && masklen.bytes().all(|b| b.is_ascii_digit()) => {
+                masklen.parse().ok()?
+            }
+            Some(_) => return None,
+            None => ip.max_masklen(),
+        };
+        Some((ip, masklen))
+    }
+
+    /// Formats the prefix in canonical CIDR notation (RFC 5952 for IPv6).
+    pub fn to_cidr(self, masklen: u32) -> String {
+        match self {
+            AddrTuple::V4(v4) => format!("{}/{}", Ipv4Addr::from(u32::from(v4)), masklen),
+            AddrTuple::V6(v6) => format!("{}/{}", Ipv6Addr::from(v6.octets()), masklen),
+        }
+    }
+
+    /// Decodes an ```:inet.ip_address()``` tuple, as found in values.
+    pub fn from_inet(term: Term) -> Option<Self> {
+        let elements = get_tuple(term).ok()?;
+        match elements.len() {
+            4 => {
+                let octets: Vec<u8> = elements
+                    .iter()
+                    .map(|e| e.decode())
+                    .collect::<NifResult<_>>()
+                    .ok()?;
+                Self::from_packed(AddrFamily::Inet4, &octets)
+            }
+            8 => {
+                let segments: Vec<u16> = elements
+                    .iter()
+                    .map(|e| e.decode())
+                    .collect::<NifResult<_>>()
+                    .ok()?;
+                let octets: Vec<u8> = segments.iter().flat_map(|s| s.to_be_bytes()).collect();
+                Self::from_packed(AddrFamily::Inet6, &octets)
+            }
+            _ => None,
+        }
+    }
+
+    /// Encodes the address as an ```:inet.ip_address()``` tuple.
+    pub fn encode_inet(self, env: Env) -> Term {
+        let elements: Vec<Term> = match self {
+            AddrTuple::V4(v4) => v4.octets().iter().map(|o| o.encode(env)).collect(),
+            AddrTuple::V6(v6) => v6.segments().iter().map(|s| s.encode(env)).collect(),
+        };
+        make_tuple(env, &elements)
+    }
+
+    /// Length of the address in bits.
+    pub fn max_masklen(&self) -> u32 {
+        match self {
+            AddrTuple::V4(_) => 32,
+            AddrTuple::V6(_) => 128,
+        }
+    }
+
+    pub fn from_nibbles(family: AddrFamily, nibbles: &[u8]) -> Self {
+        match family {
+            AddrFamily::Inet4 => AddrTuple::V4(TupleV4::from_nibbles(nibbles)),
+            AddrFamily::Inet6 => AddrTuple::V6(TupleV6::from_nibbles(nibbles)),
+        }
+    }
+}
+
 impl Maskable for AddrTuple {
     fn mask(self, masklen: u32) -> Self {
         match self {
@@ -166,6 +352,61 @@ impl Maskable for AddrTuple {
     }
 }
 
+/// Range arithmetic, on addresses as integers: IPv4 ones in the low 32 bits.
+impl AddrTuple {
+    pub fn to_bits(self) -> u128 {
+        match self {
+            AddrTuple::V4(v4) => u32::from(v4) as u128,
+            AddrTuple::V6(v6) => u128::from_be_bytes(v6.octets()),
+        }
+    }
+
+    pub fn from_bits(family: AddrFamily, bits: u128) -> Self {
+        match family {
+            AddrFamily::Inet4 => AddrTuple::V4(TupleV4::from(bits as u32)),
+            AddrFamily::Inet6 => AddrTuple::V6(TupleV6::from_octets(bits.to_be_bytes())),
+        }
+    }
+
+    /// The last address of the prefix ```self/masklen```.
+    pub fn last(self, masklen: u32) -> Self {
+        let bits = self.to_bits() | host_mask(self.max_masklen() - masklen);
+        Self::from_bits(self.family(), bits)
+    }
+}
+
+/// The ```size``` low bits set.
+fn host_mask(size: u32) -> u128 {
+    u128::MAX.checked_shr(128 - size).unwrap_or(0)
+}
+
+/// The fewest prefixes covering the addresses from ```first``` to ```last```
+/// included, in order. ```None``` if the addresses are of different families
+/// or out of order.
+pub fn range_to_prefixes(first: AddrTuple, last: AddrTuple) -> Option<Vec<(AddrTuple, u32)>> {
+    let bits = first.max_masklen();
+    let (mut start, end) = (first.to_bits(), last.to_bits());
+    if last.max_masklen() != bits || start > end {
+        return None;
+    }
+    let mut prefixes = Vec::new();
+    loop {
+        // the largest block aligned on ```start``` that does not go past
+        // ```end```
+        let fits = match (end - start).checked_add(1) {
+            Some(count) => 127 - count.leading_zeros(),
+            None => 128,
+        };
+        let size = start.trailing_zeros().min(fits).min(bits);
+        prefixes.push((AddrTuple::from_bits(first.family(), start), bits - size));
+        let block_end = start + host_mask(size);
+        if block_end == end {
+            return Some(prefixes);
+        }
+        start = block_end + 1;
+    }
+}
+
 impl ::std::convert::From<AddrTuple> for Nibbles {
     fn from(a: AddrTuple) -> Nibbles {
         match a {
@@ -174,3 +415,261 @@ impl ::std::convert::From<AddrTuple> for Nibbles {
         }
     }
 }
+
+/// A way IPv6 addresses embed IPv4 ones.
+#[derive(NifUnitEnum, Debug, Copy, Clone, PartialEq)]
+pub enum Ipv4Embedding {
+    /// ```::ffff:0:0/96```, as handed by dual-stack sockets.
+    Mapped,
+    /// ```::/96```, the deprecated IPv4-compatible addresses, except ```::```
+    /// and ```::1```.
+    Compatible,
+    /// ```64:ff9b::/96```, the NAT64 well-known prefix (RFC 6052).
+    Nat64,
+    /// ```2002::/16```, 6to4 addresses with the IPv4 address in bits 16 to 47.
+    SixToFour,
+}
+
+impl Ipv4Embedding {
+    pub const ALL: [Ipv4Embedding; 4] = [
+        Ipv4Embedding::Mapped,
+        Ipv4Embedding::Compatible,
+        Ipv4Embedding::Nat64,
+        Ipv4Embedding::SixToFour,
+    ];
+
+    /// The bytes in front of the IPv4 address.
+    fn prefix(self) -> &'static [u8] {
+        match self {
+            Ipv4Embedding::Mapped => &[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0xff, 0xff],
+            Ipv4Embedding::Compatible => &[0; 12],
+            Ipv4Embedding::Nat64 => &[0, 0x64, 0xff, 0x9b, 0, 0, 0, 0, 0, 0, 0, 0],
+            Ipv4Embedding::SixToFour => &[0x20, 0x02],
+        }
+    }
+
+    /// Returns the IPv4 address ```ip``` embeds this way.
+    pub fn extract(self, ip: TupleV6) -> Option<TupleV4> {
+        let octets = ip.octets();
+        let prefix = self.prefix();
+        if !octets.starts_with(prefix) {
+            return None;
+        }
+        let v4: [u8; 4] = octets[prefix.len()..prefix.len() + 4].try_into().unwrap();
+        let v4 = TupleV4::from(u32::from_be_bytes(v4));
+        if self == Ipv4Embedding::Compatible && u32::from(v4) <= 1 {
+            return None;
+        }
+        Some(v4)
+    }
+
+    /// Returns the IPv6 prefix embedding ```prefix/masklen```.
+    pub fn embed(self, prefix: TupleV4, masklen: u32) -> (TupleV6, u32) {
+        let mut octets = [0; 16];
+        let len = self.prefix().len();
+        octets[..len].copy_from_slice(self.prefix());
+        octets[len..len + 4].copy_from_slice(&prefix.octets());
+        (TupleV6::from_octets(octets), len as u32 * 8 + masklen)
+    }
+}
+
+/// A MAC address, decoded from a tuple of 6 (EUI-48) or 8 (EUI-64) bytes, or
+/// from a string of colon or hyphen separated hex bytes.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum MacAddr {
+    Eui48([u8; 6]),
+    Eui64([u8; 8]),
+}
+
+impl<'a> Decoder<'a> for MacAddr {
+    fn decode(term: Term<'a>) -> NifResult<Self> {
+        if let Ok(s) = term.decode::<&str>() {
+            return MacAddr::parse(s).ok_or(Error::BadArg);
+        }
+        let octets: Vec<u8> = get_tuple(term)?
+            .iter()
+            .map(|e| e.decode())
+            .collect::<NifResult<_>>()?;
+        MacAddr::from_octets(&octets).ok_or(Error::BadArg)
+    }
+}
+
+impl MacAddr {
+    /// Builds an EUI-48 from 6 bytes, or an EUI-64 from 8.
+    pub fn from_octets(octets: &[u8]) -> Option<Self> {
+        match octets.len() {
+            6 => Some(MacAddr::Eui48(octets.try_into().unwrap())),
+            8 => Some(MacAddr::Eui64(octets.try_into().unwrap())),
+            _ => None,
+        }
+    }
+
+    /// Parses ```aa:bb:cc:dd:ee:ff``` or ```aa-bb-cc-dd-ee-ff```, and the
+    /// EUI-64 equivalents.
+    pub fn parse(s: &str) -> Option<Self> {
+        let separator = if s.contains('-') { '-' } else { ':' };
+        let octets: Vec<u8> = s
+            .split(separator)
+            .map(|octet| match octet.len() {
+                2 => u8::from_str_radix(octet, 16).ok(),
+                _ => None,
+            })
+            .collect::<Option<_>>()?;
+        Self::from_octets(&octets)
+    }
+
+    pub fn octets(&self) -> &[u8] {
+        match self {
+            MacAddr::Eui48(octets) => octets,
+            MacAddr::Eui64(octets) => octets,
+        }
+    }
+
+    /// Length of the address in bits.
+    pub fn max_masklen(&self) -> u32 {
+        self.octets().len() as u32 * 8
+    }
+
+    /// Rebuilds a prefix of an address of ```len``` bytes from a nibble path.
+    pub fn from_nibbles(len: usize, nibbles: &[u8], masklen: u32) -> Self {
+        let mut octets = [0u8; 8];
+        for (i, octet) in octets.iter_mut().enumerate() {
+            let high = *nibbles.get(i * 2).unwrap_or(&0);
+            let low = *nibbles.get(i * 2 + 1).unwrap_or(&0);
+            *octet = high << 4 | low;
+        }
+        Self::from_octets(&octets[..len]).unwrap().mask(masklen)
+    }
+}
+
+impl Encoder for MacAddr {
+    fn encode<'a>(&self, env: Env<'a>) -> Term<'a> {
+        let elements: Vec<Term> = self.octets().iter().map(|o| o.encode(env)).collect();
+        make_tuple(env, &elements)
+    }
+}
+
+impl Maskable for MacAddr {
+    fn mask(self, masklen: u32) -> Self {
+        debug_assert!(masklen <= self.max_masklen());
+        let mut octets = self.octets().to_vec();
+        for (i, octet) in octets.iter_mut().enumerate() {
+            let bits = masklen.saturating_sub(i as u32 * 8).min(8);
+            *octet &= !(0xffu16 >> bits) as u8;
+        }
+        Self::from_octets(&octets).unwrap()
+    }
+}
+
+impl ::std::convert::From<MacAddr> for Nibbles {
+    fn from(a: MacAddr) -> Nibbles {
+        let mut n: [u8; 16] = [0; 16];
+        let octets = a.octets();
+        for (i, byte) in octets.iter().enumerate() {
+            n[i * 2] = byte >> 4;
+            n[i * 2 + 1] = byte & 0xf;
+        }
+        Nibbles::Mac(NibblesMac {
+            n,
+            len: octets.len() * 2,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn v6(s: &str) -> TupleV6 {
+        match AddrTuple::parse_cidr(s) {
+            Some((AddrTuple::V6(ip), 128)) => ip,
+            _ => panic!("{}", s),
+        }
+    }
+
+    #[test]
+    fn range_to_prefixes() {
+        let cidrs = |first: &str, last: &str| -> Option<Vec<String>> {
+            let (first, _) = AddrTuple::parse_cidr(first).unwrap();
+            let (last, _) = AddrTuple::parse_cidr(last).unwrap();
+            let prefixes = super::range_to_prefixes(first, last)?;
+            for (prefix, masklen) in &prefixes {
+                assert_eq!(prefix.mask(*masklen), *prefix);
+            }
+            Some(
+                prefixes
+                    .into_iter()
+                    .map(|(prefix, masklen)| prefix.to_cidr(masklen))
+                    .collect(),
+            )
+        };
+        assert_eq!(
+            cidrs("192.0.2.0", "192.0.2.255").unwrap(),
+            vec!["192.0.2.0/24"]
+        );
+        assert_eq!(
+            cidrs("10.0.0.1", "10.0.0.10").unwrap(),
+            vec![
+                "10.0.0.1/32",
+                "10.0.0.2/31",
+                "10.0.0.4/30",
+                "10.0.0.8/31",
+                "10.0.0.10/32"
+            ]
+        );
+        assert_eq!(
+            cidrs("0.0.0.0", "255.255.255.255").unwrap(),
+            vec!["0.0.0.0/0"]
+        );
+        assert_eq!(
+            cidrs("::", "ffff:ffff:ffff:ffff:ffff:ffff:ffff:ffff").unwrap(),
+            vec!["::/0"]
+        );
+        assert_eq!(
+            cidrs("2001:db8::", "2001:db8:1:ffff:ffff:ffff:ffff:ffff").unwrap(),
+            vec!["2001:db8::/47"]
+        );
+        assert_eq!(cidrs("10.0.0.1", "10.0.0.1").unwrap(), vec!["10.0.0.1/32"]);
+        assert_eq!(cidrs("10.0.0.2", "10.0.0.1"), None);
+        assert_eq!(cidrs("10.0.0.1", "::1"), None);
+        let (prefix, _) = AddrTuple::parse_cidr("192.0.2.0").unwrap();
+        assert_eq!(prefix.last(25).to_cidr(32), "192.0.2.127/32");
+    }
+
+    #[test]
+    fn ipv4_embeddings() {
+        let ip = TupleV4::from(0x0a01_0203);
+        let cases = [
+            (
+                Ipv4Embedding::Mapped,
+                "::ffff:10.1.2.3",
+                "::ffff:10.0.0.0",
+                104,
+            ),
+            (Ipv4Embedding::Compatible, "::10.1.2.3", "::10.0.0.0", 104),
+            (
+                Ipv4Embedding::Nat64,
+                "64:ff9b::10.1.2.3",
+                "64:ff9b::10.0.0.0",
+                104,
+            ),
+            (
+                Ipv4Embedding::SixToFour,
+                "2002:a01:203::1",
+                "2002:a00::",
+                24,
+            ),
+        ];
+        for (embedding, address, prefix, masklen) in cases {
+            for other in Ipv4Embedding::ALL {
+                let extracted = other.extract(v6(address));
+                assert_eq!(extracted, Some(ip).filter(|_| other == embedding));
+            }
+            let prefix = (v6(prefix), masklen);
+            assert_eq!(embedding.embed(ip.mask(8), 8), prefix);
+        }
+        assert_eq!(Ipv4Embedding::Compatible.extract(v6("::1")), None);
+        assert_eq!(Ipv4Embedding::Compatible.extract(v6("::")), None);
+        assert_eq!(Ipv4Embedding::Mapped.extract(v6("2001:db8::1")), None);
+    }
+}
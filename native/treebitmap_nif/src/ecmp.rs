@@ -0,0 +1,96 @@
+/// A stable key for a member, from its external term format: selections
+/// do not change across restarts.
+pub fn member_key(bytes: &[u8]) -> u64 {
+    // FNV-1a
+    let mut key = 0xcbf2_9ce4_8422_2325u64;
+    for byte in bytes {
+        key ^= *byte as u64;
+        key = key.wrapping_mul(0x0100_0000_01b3);
+    }
+    mix(key)
+}
+
+/// Returns the index of the ECMP group member selected for ```hash```,
+/// ```None``` when there are no members.
+///
+/// Members are picked by rendezvous hashing: each member scores the hash,
+/// the highest score wins. Removing a member only moves the flows it was
+/// selected for, and adding one only takes its share from the others.
+pub fn pick<I: IntoIterator<Item = u64>>(keys: I, hash: u64) -> Option<usize> {
+    let hash = mix(hash);
+    let mut best: Option<(usize, u64)> = None;
+    for (index, key) in keys.into_iter().enumerate() {
+        let score = mix(hash ^ key);
+        if best.is_none_or(|(_, best_score)| score > best_score) {
+            best = Some((index, score));
+        }
+    }
+    best.map(|(index, _)| index)
+}
+
+// splitmix64 finalizer
+fn mix(mut x: u64) -> u64 {
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    x ^ (x >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn spread() {
+        let keys: Vec<u64> = (0u8..4).map(|i| member_key(&[i])).collect();
+        let mut counts = [0; 4];
+        for hash in 0..40_000 {
+            counts[pick(keys.iter().copied(), hash).unwrap()] += 1;
+        }
+        for count in counts {
+            assert!((9_000..11_000).contains(&count), "{:?}", counts);
+        }
+        assert_eq!(pick(std::iter::empty(), 1), None);
+    }
+
+    #[test]
+    fn removal_only_remaps_its_share() {
+        let keys: Vec<u64> = (0u8..5).map(|i| member_key(&[i])).collect();
+        let remaining: Vec<u64> = keys.iter().copied().filter(|k| *k != keys[2]).collect();
+        for hash in 0..10_000 {
+            let before = keys[pick(keys.iter().copied(), hash).unwrap()];
+            let after = remaining[pick(remaining.iter().copied(), hash).unwrap()];
+            if before != keys[2] {
+                assert_eq!(before, after);
+            }
+        }
+    }
+
+    #[test]
+    fn addition_only_takes_its_share() {
+        let keys: Vec<u64> = (0u8..5).map(|i| member_key(&[i])).collect();
+        let fewer = &keys[..4];
+        let mut taken = 0;
+        for hash in 0..10_000 {
+            let before = fewer[pick(fewer.iter().copied(), hash).unwrap()];
+            let after = keys[pick(keys.iter().copied(), hash).unwrap()];
+            if after == keys[4] {
+                taken += 1;
+            } else {
+                assert_eq!(before, after);
+            }
+        }
+        assert!((1_500..2_500).contains(&taken), "{}", taken);
+    }
+
+    #[test]
+    fn member_order_does_not_matter() {
+        let keys: Vec<u64> = (0u8..4).map(|i| member_key(&[i])).collect();
+        let reversed: Vec<u64> = keys.iter().rev().copied().collect();
+        for hash in 0..1_000 {
+            assert_eq!(
+                keys[pick(keys.iter().copied(), hash).unwrap()],
+                reversed[pick(reversed.iter().copied(), hash).unwrap()]
+            );
+        }
+    }
+}
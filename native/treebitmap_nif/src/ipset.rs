@@ -0,0 +1,156 @@
+use crate::addrs::{AddrFamily, AddrTuple};
+use crate::nibbles::Nibbles;
+use crate::setops;
+use crate::tree_bitmap::TreeBitmap;
+
+/// Prefixes of an address family without values, for membership only: the
+/// trie stores no results at all.
+pub struct IpSet {
+    tree: TreeBitmap<()>,
+}
+
+impl IpSet {
+    pub fn new() -> Self {
+        IpSet {
+            tree: TreeBitmap::new(),
+        }
+    }
+
+    /// Adds a prefix, ```ip``` must be masked. Returns whether it was not in
+    /// the set yet.
+    pub fn insert(&mut self, ip: AddrTuple, masklen: u32) -> bool {
+        self.tree
+            .insert(Nibbles::from(ip).as_ref(), masklen, ())
+            .is_none()
+    }
+
+    /// Removes a prefix, returns whether it was in the set.
+    pub fn remove(&mut self, ip: AddrTuple, masklen: u32) -> bool {
+        self.tree
+            .remove(Nibbles::from(ip).as_ref(), masklen)
+            .is_some()
+    }
+
+    /// Is ```ip``` covered by a prefix of the set?
+    pub fn contains(&self, ip: AddrTuple) -> bool {
+        self.tree
+            .longest_match(Nibbles::from(ip).as_ref())
+            .is_some()
+    }
+
+    /// The prefixes of the set, by address and a prefix before the ones it
+    /// contains.
+    pub fn prefixes(&self, family: AddrFamily) -> Vec<(AddrTuple, u32)> {
+        self.tree
+            .iter()
+            .map(|(nibbles, masklen, _)| (AddrTuple::from_nibbles(family, &nibbles), masklen))
+            .collect()
+    }
+
+    /// The number of addresses of ```family``` covered by the set, counted
+    /// once however many prefixes cover them. ```None``` when the set holds
+    /// ```::/0```, as 2^128 does not fit.
+    pub fn cover_count(&self, family: AddrFamily) -> Option<u128> {
+        let bits = family.octets() as u32 * 8;
+        let mut count: u128 = 0;
+        // the last address of the outermost prefix counted so far; prefixes
+        // come by address, so the ones starting up to it are inside it
+        let mut covered: Option<u128> = None;
+        for (prefix, masklen) in self.prefixes(family) {
+            if covered.is_some_and(|last| prefix.to_bits() <= last) {
+                continue;
+            }
+            covered = Some(prefix.last(masklen).to_bits());
+            count = count.checked_add(1u128.checked_shl(bits - masklen)?)?;
+        }
+        Some(count)
+    }
+
+    /// The addresses of ```parent/masklen``` not covered by the set, as the
+    /// fewest prefixes. ```parent``` must be masked.
+    pub fn complement(&self, parent: AddrTuple, masklen: u32) -> IpSet {
+        let mut whole = TreeBitmap::new();
+        whole.insert(Nibbles::from(parent).as_ref(), masklen, ());
+        IpSet {
+            tree: setops::difference(&whole, &self.tree),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.tree.len()
+    }
+
+    /// Returns the bytes used by trie nodes, results take none.
+    pub fn mem_usage(&self) -> usize {
+        self.tree.mem_usage().0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn prefix(s: &str) -> (AddrTuple, u32) {
+        AddrTuple::parse_cidr(s).unwrap()
+    }
+
+    fn set(prefixes: &[&str]) -> IpSet {
+        let mut set = IpSet::new();
+        for s in prefixes {
+            let (ip, masklen) = prefix(s);
+            set.insert(ip, masklen);
+        }
+        set
+    }
+
+    #[test]
+    fn cover_count() {
+        let bogons = set(&["10.0.0.0/8", "10.1.0.0/16", "192.168.0.0/16", "192.0.2.1"]);
+        assert_eq!(
+            bogons.cover_count(AddrFamily::Inet4),
+            Some((1 << 24) + (1 << 16) + 1)
+        );
+        assert!(bogons.contains(prefix("10.1.2.3").0));
+        assert!(!bogons.contains(prefix("192.0.2.2").0));
+        assert_eq!(
+            set(&["0.0.0.0/0"]).cover_count(AddrFamily::Inet4),
+            Some(1 << 32)
+        );
+        assert_eq!(
+            set(&["2001:db8::/32", "2001:db8:1::/48"]).cover_count(AddrFamily::Inet6),
+            Some(1 << 96)
+        );
+        // ```128.0.0.0/1``` is stored before the longer prefixes of the root
+        assert_eq!(
+            set(&["10.0.0.0/8", "128.0.0.0/1"]).cover_count(AddrFamily::Inet4),
+            Some((1 << 31) + (1 << 24))
+        );
+        assert_eq!(
+            set(&["128.0.0.0/1", "0.0.0.0/2"]).cover_count(AddrFamily::Inet4),
+            Some((1 << 31) + (1 << 30))
+        );
+        assert_eq!(set(&["::/0"]).cover_count(AddrFamily::Inet6), None);
+        assert_eq!(IpSet::new().cover_count(AddrFamily::Inet6), Some(0));
+    }
+
+    #[test]
+    fn complement() {
+        let used = set(&["10.0.0.0/9", "10.192.0.0/10", "192.0.2.0/24"]);
+        let (parent, masklen) = prefix("10.0.0.0/8");
+        let free: Vec<String> = used
+            .complement(parent, masklen)
+            .prefixes(AddrFamily::Inet4)
+            .into_iter()
+            .map(|(ip, masklen)| ip.to_cidr(masklen))
+            .collect();
+        assert_eq!(free, vec!["10.128.0.0/10"]);
+        let (parent, masklen) = prefix("10.0.0.0/9");
+        assert_eq!(used.complement(parent, masklen).len(), 0);
+        let (parent, masklen) = prefix("172.16.0.0/12");
+        assert_eq!(
+            used.complement(parent, masklen)
+                .cover_count(AddrFamily::Inet4),
+            Some(1 << 20)
+        );
+    }
+}
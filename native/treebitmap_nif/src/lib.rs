@@ -1,64 +1,323 @@
 mod addrs;
+mod ecmp;
+mod ipset;
+mod mrt;
 mod nibbles;
+mod oui;
+mod serialize;
+mod setops;
+mod table;
 mod tree_bitmap;
+mod value;
+mod vrf;
 
-use addrs::{AddrTuple, Maskable};
+use addrs::{AddrFamily, AddrTuple, MacAddr, Maskable};
+use ipset::IpSet;
 use nibbles::Nibbles;
-use rustler::{resource::ResourceArc, types::tuple::make_tuple, Encoder, Env, NifResult, Term};
-use std::sync::Mutex;
-use tree_bitmap::TreeBitmap;
+use rustler::{
+    resource::ResourceArc, types::tuple::make_tuple, Atom, Binary, Encoder, Env, Error, NifResult,
+    OwnedBinary, OwnedEnv, Term,
+};
+use setops::Merge;
+use std::borrow::Cow;
+use std::fs;
+use std::io;
+use std::sync::{Mutex, RwLock, RwLockReadGuard};
+use table::{Table, TableOptions};
+use tree_bitmap::Cursor;
+use value::{Member, OwnedTermSlot, Route, Slot};
+use vrf::VrfTable;
 
 mod atoms {
     rustler::atoms! {
         ok,
-        nil
+        nil,
+        error,
+        invalidated,
+        invalid_prefix,
+        invalid_mrt,
+        invalid_oui,
+        unreachable,
+        routing_loop,
+        max_depth,
+        file,
+        enoent,
+        eacces,
+        eio,
+        badarg,
+        added,
+        withdrawn,
+        changed,
+        invalid_range
     }
 }
 
+/// Lookups only take the lock for reading and proceed in parallel, writers
+/// get exclusive access.
+///
+/// Publishing copy-on-write snapshots would keep readers running during
+/// writes, but every add or remove would then copy the whole tree, which is
+/// not an option for full tables that see continuous updates.
 struct TableResource {
-    pub tree: Mutex<TreeBitmap<u32>>,
+    pub table: RwLock<Table>,
+    pub options: TableOptions,
+    /// The IPv4 table addresses embedding IPv4 ones are looked up in, see
+    /// ```TableOptions::embedded_ipv4```.
+    pub inet4: RwLock<Option<ResourceArc<TableResource>>>,
+}
+
+impl TableResource {
+    fn new(table: Table, options: TableOptions) -> Self {
+        TableResource {
+            table: RwLock::new(table),
+            options,
+            inet4: RwLock::new(None),
+        }
+    }
+
+    fn inet4(&self) -> Option<ResourceArc<TableResource>> {
+        self.inet4.read().unwrap().clone()
+    }
+}
+
+/// A table locked for lookups, along with its linked IPv4 table when it
+/// normalizes embedded IPv4 addresses. Always locked after the table itself.
+struct LookupTables<'a> {
+    table: RwLockReadGuard<'a, Table>,
+    inet4: Option<RwLockReadGuard<'a, Table>>,
+    options: TableOptions,
+}
+
+impl<'a> LookupTables<'a> {
+    fn read(table_resource: &'a TableResource, inet4: Option<&'a TableResource>) -> Self {
+        let table = table_resource.table.read().unwrap();
+        let inet4 = inet4
+            .filter(|_| table_resource.options.embedded_ipv4 != 0)
+            .map(|inet4| inet4.table.read().unwrap());
+        LookupTables {
+            table,
+            inet4,
+            options: table_resource.options,
+        }
+    }
+
+    /// Looks ```ip``` up, also in the IPv4 table when it embeds an IPv4
+    /// address. Routes found there are returned as prefixes of the embedding,
+    /// and win over IPv6 routes unless these are more specific.
+    fn lookup(&self, ip: AddrTuple) -> Option<(AddrTuple, u32, &Slot)> {
+        let found = lookup(&self.table, ip);
+        if let (Some(inet4), Some((ip4, embedding))) = (&self.inet4, self.options.extract_ipv4(ip))
+        {
+            if let Some((prefix, masklen, slot)) = lookup(inet4, AddrTuple::V4(ip4)) {
+                let prefix = match prefix {
+                    AddrTuple::V4(prefix) => prefix,
+                    AddrTuple::V6(_) => unreachable!(),
+                };
+                let (prefix, masklen) = embedding.embed(prefix, masklen);
+                if found.is_none_or(|(_, found_masklen, _)| masklen >= found_masklen) {
+                    return Some((AddrTuple::V6(prefix), masklen, slot));
+                }
+            }
+        }
+        found
+    }
+
+    /// Every route covering ```ip```, from the least to the most specific,
+    /// with those of the IPv4 table when it embeds an IPv4 address.
+    fn matches(&self, ip: AddrTuple) -> Vec<(AddrTuple, u32, &Slot)> {
+        let mut covering: Vec<(AddrTuple, u32, &Slot)> = self
+            .table
+            .tree
+            .matches(Nibbles::from(ip).as_ref())
+            .into_iter()
+            .map(|(masklen, slot)| (ip.mask(masklen), masklen, slot))
+            .collect();
+        if let (Some(inet4), Some((ip4, embedding))) = (&self.inet4, self.options.extract_ipv4(ip))
+        {
+            covering.extend(
+                inet4
+                    .tree
+                    .matches(Nibbles::from(AddrTuple::V4(ip4)).as_ref())
+                    .into_iter()
+                    .map(|(masklen, slot)| {
+                        let (prefix, masklen) = embedding.embed(ip4.mask(masklen), masklen);
+                        (AddrTuple::V6(prefix), masklen, slot)
+                    }),
+            );
+            covering.sort_by_key(|(_, masklen, _)| *masklen);
+        }
+        covering
+    }
+}
+
+/// The routes of many VRFs, see ```VrfTable```. Locked as a whole, as
+/// ```TableResource```.
+struct VrfResource {
+    pub table: RwLock<VrfTable>,
+    pub options: TableOptions,
+}
+
+/// Prefixes without values, see ```IpSet```. Locked as a whole, as
+/// ```TableResource```.
+struct SetResource {
+    pub set: RwLock<IpSet>,
+    pub options: TableOptions,
+}
+
+/// Resumable iteration over a table.
+///
+/// A cursor is bound to the table version it was created at: once a prefix
+/// is added to or removed from the table, ```iter_next``` returns
+/// ```{:error, :invalidated}``` and a new cursor must be created. Replacing
+/// the value of an existing prefix does not invalidate cursors, the new value
+/// is returned if the cursor has not passed the prefix yet.
+struct CursorResource {
+    pub table: ResourceArc<TableResource>,
+    pub family: AddrFamily,
+    pub cursor: Mutex<(u64, Cursor)>,
 }
 
 #[rustler::nif]
 fn new() -> NifResult<ResourceArc<TableResource>> {
-    let tree = TreeBitmap::new();
-    let resource = ResourceArc::new(TableResource {
-        tree: Mutex::new(tree),
-    });
+    let table = Table::new();
+    let resource = ResourceArc::new(TableResource::new(table, TableOptions::default()));
+    Ok(resource)
+}
+
+#[rustler::nif]
+fn new_with_options(options: TableOptions) -> NifResult<ResourceArc<TableResource>> {
+    let table = Table::new();
+    let resource = ResourceArc::new(TableResource::new(table, options));
     Ok(resource)
 }
 
 #[rustler::nif]
 fn new_with_capacity(n: usize) -> NifResult<ResourceArc<TableResource>> {
-    let tree = TreeBitmap::with_capacity(n);
-    let resource = ResourceArc::new(TableResource {
-        tree: Mutex::new(tree),
-    });
+    let table = Table::with_capacity(n);
+    let resource = ResourceArc::new(TableResource::new(table, TableOptions::default()));
     Ok(resource)
 }
 
+/// Returns an independent copy of the table, with the same options.
+#[rustler::nif(schedule = "DirtyCpu")]
+fn clone(table_resource: ResourceArc<TableResource>) -> ResourceArc<TableResource> {
+    let table = table_resource.table.read().unwrap().clone();
+    ResourceArc::new(TableResource::new(table, table_resource.options))
+}
+
+/// Links ```table``` to the IPv4 table its lookups of addresses embedding an
+/// IPv4 address go to, see ```TableOptions::embedded_ipv4```.
+#[rustler::nif]
+fn set_inet4(
+    table_resource: ResourceArc<TableResource>,
+    inet4: ResourceArc<TableResource>,
+) -> Atom {
+    *table_resource.inet4.write().unwrap() = Some(inet4);
+    atoms::ok()
+}
+
 #[rustler::nif]
 fn length(table_resource: ResourceArc<TableResource>) -> NifResult<usize> {
-    let tree = table_resource.tree.lock().unwrap();
-    Ok(tree.len())
+    let table = table_resource.table.read().unwrap();
+    Ok(table.tree.len())
 }
 
 #[rustler::nif]
-fn add(
-    env: Env,
+fn add<'a>(
+    env: Env<'a>,
     table_resource: ResourceArc<TableResource>,
     ip: AddrTuple,
     masklen: u32,
-    value: u32,
-) -> Term {
-    let mut tree = table_resource.tree.lock().unwrap();
-    if let Some(value) = tree.insert(Nibbles::from(ip).as_ref(), masklen, value) {
+    value: Term<'a>,
+) -> Term<'a> {
+    let ip = match table_resource.options.check_prefix(ip, masklen) {
+        Ok(prefix) => prefix,
+        Err(reason) => return make_tuple(env, &[atoms::error().encode(env), reason.encode(env)]),
+    };
+    let mut table = table_resource.table.write().unwrap();
+    let value = OwnedTermSlot::new(value);
+    if let Some(value) = table.insert(Nibbles::from(ip).as_ref(), masklen, value) {
         make_tuple(env, &[atoms::ok().encode(env), value.encode(env)])
     } else {
         make_tuple(env, &[atoms::ok().encode(env), atoms::nil().encode(env)])
     }
 }
 
+/// Adds ```[{ip, masklen, value}]``` with a single lock acquisition, and
+/// returns the result of each entry in order, as ```add``` would.
+#[rustler::nif(schedule = "DirtyCpu")]
+fn add_many<'a>(
+    env: Env<'a>,
+    table_resource: ResourceArc<TableResource>,
+    entries: Vec<Term<'a>>,
+) -> Term<'a> {
+    let entries: Vec<Result<(AddrTuple, u32, OwnedTermSlot), Atom>> = entries
+        .into_iter()
+        .map(|entry| {
+            let (ip, masklen, value): (AddrTuple, u32, Term) =
+                entry.decode().map_err(|_| atoms::badarg())?;
+            let ip = table_resource.options.check_prefix(ip, masklen)?;
+            Ok((ip, masklen, OwnedTermSlot::new(value)))
+        })
+        .collect();
+
+    let mut table = table_resource.table.write().unwrap();
+    let results: Vec<Result<Option<Slot>, Atom>> = entries
+        .into_iter()
+        .map(|entry| {
+            let (ip, masklen, value) = entry?;
+            Ok(table.insert(Nibbles::from(ip).as_ref(), masklen, value))
+        })
+        .collect();
+    drop(table);
+    results.encode(env)
+}
+
+/// Adds ```value``` for the addresses from ```first``` to ```last``` included,
+/// as the fewest prefixes covering them. Returns ```{:ok, [{prefix,
+/// masklen}]}``` with the prefixes added, in order.
+#[rustler::nif]
+fn add_range<'a>(
+    env: Env<'a>,
+    table_resource: ResourceArc<TableResource>,
+    first: AddrTuple,
+    last: AddrTuple,
+    value: Term<'a>,
+) -> Term<'a> {
+    let prefixes = match addrs::range_to_prefixes(first, last) {
+        Some(prefixes) => prefixes,
+        None => {
+            return make_tuple(
+                env,
+                &[
+                    atoms::error().encode(env),
+                    atoms::invalid_range().encode(env),
+                ],
+            )
+        }
+    };
+    let value = OwnedTermSlot::new(value);
+    let mut table = table_resource.table.write().unwrap();
+    for (prefix, masklen) in &prefixes {
+        table.insert(Nibbles::from(*prefix).as_ref(), *masklen, value.clone());
+    }
+    drop(table);
+    let options = table_resource.options;
+    let prefixes: Vec<Term> = prefixes
+        .into_iter()
+        .map(|(prefix, masklen)| {
+            make_tuple(
+                env,
+                &[
+                    options.encode_prefix(env, prefix, masklen),
+                    masklen.encode(env),
+                ],
+            )
+        })
+        .collect();
+    make_tuple(env, &[atoms::ok().encode(env), prefixes.encode(env)])
+}
+
 #[rustler::nif]
 fn remove(
     env: Env,
@@ -66,24 +325,140 @@ fn remove(
     ip: AddrTuple,
     masklen: u32,
 ) -> Term {
-    let mut tree = table_resource.tree.lock().unwrap();
-    if let Some(value) = tree.remove(Nibbles::from(ip).as_ref(), masklen) {
+    let ip = match table_resource.options.check_prefix(ip, masklen) {
+        Ok(prefix) => prefix,
+        Err(reason) => return make_tuple(env, &[atoms::error().encode(env), reason.encode(env)]),
+    };
+    let mut table = table_resource.table.write().unwrap();
+    if let Some(value) = table.remove(Nibbles::from(ip).as_ref(), masklen) {
         make_tuple(env, &[atoms::ok().encode(env), value.encode(env)])
     } else {
         make_tuple(env, &[atoms::ok().encode(env), atoms::nil().encode(env)])
     }
 }
 
+/// Adds ```{source, distance, metric, value}``` to the candidate routes of
+/// a prefix. Returns ```{:ok, previous}```, the route it replaced from the
+/// same source or ```nil```.
+#[rustler::nif]
+fn add_route<'a>(
+    env: Env<'a>,
+    table_resource: ResourceArc<TableResource>,
+    ip: AddrTuple,
+    masklen: u32,
+    route: Term<'a>,
+) -> NifResult<Term<'a>> {
+    let (source, distance, metric, value): (Term, u32, u32, Term) = route.decode()?;
+    let ip = match table_resource.options.check_prefix(ip, masklen) {
+        Ok(prefix) => prefix,
+        Err(reason) => {
+            return Ok(make_tuple(
+                env,
+                &[atoms::error().encode(env), reason.encode(env)],
+            ))
+        }
+    };
+    let route = Route {
+        source: OwnedTermSlot::new(source),
+        distance,
+        metric,
+        value: OwnedTermSlot::new(value),
+    };
+    let mut table = table_resource.table.write().unwrap();
+    let prev = table.add_route(Nibbles::from(ip).as_ref(), masklen, route);
+    Ok(make_tuple(
+        env,
+        &[atoms::ok().encode(env), prev.encode(env)],
+    ))
+}
+
+/// Removes the route from ```source``` of a prefix. Returns ```{:ok, route}```,
+/// or ```{:ok, nil}``` when there was none.
+#[rustler::nif]
+fn remove_route<'a>(
+    env: Env<'a>,
+    table_resource: ResourceArc<TableResource>,
+    ip: AddrTuple,
+    masklen: u32,
+    source: Term<'a>,
+) -> Term<'a> {
+    let ip = match table_resource.options.check_prefix(ip, masklen) {
+        Ok(prefix) => prefix,
+        Err(reason) => return make_tuple(env, &[atoms::error().encode(env), reason.encode(env)]),
+    };
+    let source = OwnedTermSlot::new(source);
+    let mut table = table_resource.table.write().unwrap();
+    let route = table.remove_route(Nibbles::from(ip).as_ref(), masklen, &source);
+    make_tuple(env, &[atoms::ok().encode(env), route.encode(env)])
+}
+
+/// Returns the candidate routes of a prefix by order of preference, the first
+/// one being selected. A prefix with a plain value has no candidates.
+#[rustler::nif]
+fn routes(
+    env: Env,
+    table_resource: ResourceArc<TableResource>,
+    ip: AddrTuple,
+    masklen: u32,
+) -> Term {
+    let ip = match table_resource.options.check_prefix(ip, masklen) {
+        Ok(prefix) => prefix,
+        Err(reason) => return make_tuple(env, &[atoms::error().encode(env), reason.encode(env)]),
+    };
+    let table = table_resource.table.read().unwrap();
+    let routes = match table.tree.exact_match(Nibbles::from(ip).as_ref(), masklen) {
+        Some(Slot::Routes(routes)) => routes.as_slice(),
+        _ => &[],
+    };
+    make_tuple(env, &[atoms::ok().encode(env), routes.encode(env)])
+}
+
+/// Sets a prefix to an ECMP group of ```members```, replacing its value or
+/// candidate routes. Returns ```{:ok, previous}``` as ```add``` does.
+#[rustler::nif]
+fn add_ecmp<'a>(
+    env: Env<'a>,
+    table_resource: ResourceArc<TableResource>,
+    ip: AddrTuple,
+    masklen: u32,
+    members: Vec<Term<'a>>,
+) -> NifResult<Term<'a>> {
+    if members.is_empty() {
+        return Err(Error::BadArg);
+    }
+    let ip = match table_resource.options.check_prefix(ip, masklen) {
+        Ok(prefix) => prefix,
+        Err(reason) => {
+            return Ok(make_tuple(
+                env,
+                &[atoms::error().encode(env), reason.encode(env)],
+            ))
+        }
+    };
+    let members = members
+        .into_iter()
+        .map(|member| Member::new(OwnedTermSlot::new(member)))
+        .collect();
+    let mut table = table_resource.table.write().unwrap();
+    let prev = table.insert_slot(Nibbles::from(ip).as_ref(), masklen, Slot::Ecmp(members));
+    Ok(make_tuple(
+        env,
+        &[atoms::ok().encode(env), prev.encode(env)],
+    ))
+}
+
 #[rustler::nif]
 fn longest_match(env: Env, table_resource: ResourceArc<TableResource>, ip: AddrTuple) -> Term {
-    let tree = table_resource.tree.lock().unwrap();
-    if let Some((bits_matched, value)) = tree.longest_match(Nibbles::from(ip).as_ref()) {
-        let prefix = ip.mask(bits_matched);
+    let inet4 = table_resource.inet4();
+    let tables = LookupTables::read(&table_resource, inet4.as_deref());
+    if let Some((prefix, bits_matched, value)) = tables.lookup(ip) {
         make_tuple(
             env,
             &[
                 atoms::ok().encode(env),
-                prefix.encode(env),
+                table_resource
+                    .options
+                    .encode_prefix(env, prefix, bits_matched),
                 bits_matched.encode(env),
                 value.encode(env),
             ],
@@ -93,6 +468,191 @@ fn longest_match(env: Env, table_resource: ResourceArc<TableResource>, ip: AddrT
     }
 }
 
+/// Same as ```longest_match```, returning the ECMP group member selected for the
+/// flow ```hash``` rather than the whole group. Other values are returned
+/// as is.
+#[rustler::nif]
+fn longest_match_hashed(
+    env: Env,
+    table_resource: ResourceArc<TableResource>,
+    ip: AddrTuple,
+    hash: u64,
+) -> Term {
+    let inet4 = table_resource.inet4();
+    let tables = LookupTables::read(&table_resource, inet4.as_deref());
+    if let Some((prefix, bits_matched, slot)) = tables.lookup(ip) {
+        make_tuple(
+            env,
+            &[
+                atoms::ok().encode(env),
+                table_resource
+                    .options
+                    .encode_prefix(env, prefix, bits_matched),
+                bits_matched.encode(env),
+                slot.select(hash).encode(env),
+            ],
+        )
+    } else {
+        make_tuple(env, &[atoms::ok().encode(env), atoms::nil().encode(env)])
+    }
+}
+
+/// Looks up each of ```ips``` with a single lock acquisition. Returns
+/// ```{prefix, masklen, value}``` or ```nil``` for each address, in order.
+#[rustler::nif(schedule = "DirtyCpu")]
+fn longest_match_many(
+    env: Env,
+    table_resource: ResourceArc<TableResource>,
+    ips: Vec<AddrTuple>,
+) -> Term {
+    let inet4 = table_resource.inet4();
+    let tables = LookupTables::read(&table_resource, inet4.as_deref());
+    let results: Vec<Option<Term>> = ips
+        .into_iter()
+        .map(|ip| {
+            tables
+                .lookup(ip)
+                .map(|route| table_resource.options.encode_route(env, route))
+        })
+        .collect();
+    results.encode(env)
+}
+
+/// Same as ```longest_match_many```, with the addresses of ```family```
+/// packed in network byte order in a binary.
+#[rustler::nif(schedule = "DirtyCpu")]
+fn longest_match_packed<'a>(
+    env: Env<'a>,
+    table_resource: ResourceArc<TableResource>,
+    family: AddrFamily,
+    ips: Binary<'a>,
+) -> NifResult<Term<'a>> {
+    if ips.len() % family.octets() != 0 {
+        return Err(Error::BadArg);
+    }
+    let inet4 = table_resource.inet4();
+    let tables = LookupTables::read(&table_resource, inet4.as_deref());
+    let results: Vec<Option<Term>> = ips
+        .chunks(family.octets())
+        .map(|bytes| tables.lookup(AddrTuple::from_packed(family, bytes).unwrap()))
+        .map(|route| route.map(|route| table_resource.options.encode_route(env, route)))
+        .collect();
+    Ok(results.encode(env))
+}
+
+fn lookup(table: &Table, ip: AddrTuple) -> Option<(AddrTuple, u32, &Slot)> {
+    let (bits_matched, value) = table.tree.longest_match(Nibbles::from(ip).as_ref())?;
+    Some((ip.mask(bits_matched), bits_matched, value))
+}
+
+/// Resolves ```ip``` recursively: looks it up in ```tables```, then as long
+/// as the value is an ```:inet``` address, looks that next hop up in the
+/// ```via``` tables, or the same tables when they are ```nil```. Next hops may be
+/// of another family than the route.
+///
+/// Returns ```{:ok, chain}``` once a value is not an address (a directly
+/// connected route), or ```{:error, reason, chain}``` with ```:unreachable```
+/// when a lookup finds no route, ```:routing_loop``` when a route is reached
+/// twice, or ```:max_depth``` after ```max_depth``` lookups. The chain holds
+/// the ```{prefix, masklen, value}``` of each lookup in order. Each lookup
+/// takes the table lock on its own.
+#[rustler::nif]
+fn resolve(
+    env: Env,
+    table_v4: ResourceArc<TableResource>,
+    table_v6: ResourceArc<TableResource>,
+    via_v4: Option<ResourceArc<TableResource>>,
+    via_v6: Option<ResourceArc<TableResource>>,
+    ip: AddrTuple,
+    max_depth: usize,
+) -> Term {
+    let via_v4 = via_v4.unwrap_or_else(|| table_v4.clone());
+    let via_v6 = via_v6.unwrap_or_else(|| table_v6.clone());
+    let mut chain: Vec<Term> = Vec::new();
+    let mut seen: Vec<(*const TableResource, AddrTuple, u32)> = Vec::new();
+    let mut ip = ip;
+    let reason = loop {
+        if chain.len() == max_depth {
+            break atoms::max_depth();
+        }
+        let table_resource = match (ip.family(), chain.is_empty()) {
+            (AddrFamily::Inet4, true) => &table_v4,
+            (AddrFamily::Inet6, true) => &table_v6,
+            (AddrFamily::Inet4, false) => &via_v4,
+            (AddrFamily::Inet6, false) => &via_v6,
+        };
+        let inet4 = table_resource.inet4();
+        let tables = LookupTables::read(table_resource, inet4.as_deref());
+        let (prefix, masklen, slot) = match tables.lookup(ip) {
+            Some(route) => route,
+            None => break atoms::unreachable(),
+        };
+        let value = slot.encode(env);
+        chain.push(make_tuple(
+            env,
+            &[
+                table_resource.options.encode_prefix(env, prefix, masklen),
+                masklen.encode(env),
+                value,
+            ],
+        ));
+        let key = (&**table_resource as *const TableResource, prefix, masklen);
+        if seen.contains(&key) {
+            break atoms::routing_loop();
+        }
+        seen.push(key);
+        match AddrTuple::from_inet(value) {
+            Some(next_hop) => ip = next_hop,
+            None => return make_tuple(env, &[atoms::ok().encode(env), chain.encode(env)]),
+        }
+    };
+    make_tuple(
+        env,
+        &[
+            atoms::error().encode(env),
+            reason.encode(env),
+            chain.encode(env),
+        ],
+    )
+}
+
+#[rustler::nif]
+fn matches(env: Env, table_resource: ResourceArc<TableResource>, ip: AddrTuple) -> Term {
+    let inet4 = table_resource.inet4();
+    let tables = LookupTables::read(&table_resource, inet4.as_deref());
+    let list: Vec<Term> = tables
+        .matches(ip)
+        .into_iter()
+        .map(|route| table_resource.options.encode_route(env, route))
+        .collect();
+    list.encode(env)
+}
+
+#[rustler::nif(schedule = "DirtyCpu")]
+fn more_specifics(
+    env: Env,
+    table_resource: ResourceArc<TableResource>,
+    ip: AddrTuple,
+    masklen: u32,
+) -> Term {
+    let ip = match table_resource.options.check_prefix(ip, masklen) {
+        Ok(prefix) => prefix,
+        Err(reason) => return make_tuple(env, &[atoms::error().encode(env), reason.encode(env)]),
+    };
+    let table = table_resource.table.read().unwrap();
+    let list: Vec<Term> = table
+        .tree
+        .iter_more_specifics(Nibbles::from(ip).as_ref(), masklen)
+        .map(|(nibbles, masklen, value)| {
+            let prefix = AddrTuple::from_nibbles(ip.family(), &nibbles);
+            table_resource
+                .options
+                .encode_route(env, (prefix, masklen, value))
+        })
+        .collect();
+    make_tuple(env, &[atoms::ok().encode(env), list.encode(env)])
+}
+
 #[rustler::nif]
 fn exact_match(
     env: Env,
@@ -100,18 +660,889 @@ fn exact_match(
     ip: AddrTuple,
     masklen: u32,
 ) -> Term {
-    let tree = table_resource.tree.lock().unwrap();
-    if let Some(value) = tree.exact_match(Nibbles::from(ip).as_ref(), masklen) {
+    let ip = match table_resource.options.check_prefix(ip, masklen) {
+        Ok(prefix) => prefix,
+        Err(reason) => return make_tuple(env, &[atoms::error().encode(env), reason.encode(env)]),
+    };
+    let table = table_resource.table.read().unwrap();
+    if let Some(value) = table.tree.exact_match(Nibbles::from(ip).as_ref(), masklen) {
         make_tuple(env, &[atoms::ok().encode(env), value.encode(env)])
     } else {
         make_tuple(env, &[atoms::ok().encode(env), atoms::nil().encode(env)])
     }
 }
 
+#[rustler::nif(schedule = "DirtyCpu")]
+fn to_list(env: Env, table_resource: ResourceArc<TableResource>, family: AddrFamily) -> Term {
+    let table = table_resource.table.read().unwrap();
+    let list: Vec<Term> = table
+        .tree
+        .iter()
+        .map(|(nibbles, masklen, value)| {
+            let prefix = AddrTuple::from_nibbles(family, &nibbles);
+            table_resource
+                .options
+                .encode_route(env, (prefix, masklen, value))
+        })
+        .collect();
+    list.encode(env)
+}
+
+/// Returns ```[{first, last, value}]```, the ranges of consecutive addresses
+/// with the same longest match value, in order. See ```Table::ranges```.
+#[rustler::nif(schedule = "DirtyCpu")]
+fn ranges(env: Env, table_resource: ResourceArc<TableResource>, family: AddrFamily) -> Term {
+    let table = table_resource.table.read().unwrap();
+    let list: Vec<Term> = table
+        .ranges(family)
+        .into_iter()
+        .map(|(first, last, value)| {
+            make_tuple(
+                env,
+                &[first.encode(env), last.encode(env), value.encode(env)],
+            )
+        })
+        .collect();
+    list.encode(env)
+}
+
+#[rustler::nif]
+fn iter(
+    table_resource: ResourceArc<TableResource>,
+    family: AddrFamily,
+) -> ResourceArc<CursorResource> {
+    let table = table_resource.table.read().unwrap();
+    let version = table.version;
+    let cursor = table.tree.cursor();
+    drop(table);
+    ResourceArc::new(CursorResource {
+        table: table_resource,
+        family,
+        cursor: Mutex::new((version, cursor)),
+    })
+}
+
+#[rustler::nif]
+fn iter_next(env: Env, cursor_resource: ResourceArc<CursorResource>, n: usize) -> Term {
+    let table = cursor_resource.table.table.read().unwrap();
+    let mut cursor = cursor_resource.cursor.lock().unwrap();
+    let (version, ref mut cursor) = *cursor;
+    if version != table.version {
+        return make_tuple(
+            env,
+            &[atoms::error().encode(env), atoms::invalidated().encode(env)],
+        );
+    }
+    let mut list = Vec::with_capacity(n);
+    while list.len() < n {
+        match cursor.next(&table.tree) {
+            Some((nibbles, masklen, value)) => {
+                let prefix = AddrTuple::from_nibbles(cursor_resource.family, &nibbles);
+                list.push(
+                    cursor_resource
+                        .table
+                        .options
+                        .encode_route(env, (prefix, masklen, value)),
+                );
+            }
+            None => break,
+        }
+    }
+    make_tuple(env, &[atoms::ok().encode(env), list.encode(env)])
+}
+
+/// Same as ```add```, for a table keyed by bitstrings: the prefix is the first
+/// ```masklen``` bits of ```key```.
+#[rustler::nif]
+fn bits_add<'a>(
+    env: Env<'a>,
+    table_resource: ResourceArc<TableResource>,
+    key: Binary<'a>,
+    masklen: u32,
+    value: Term<'a>,
+) -> Term<'a> {
+    let nibbles = match table_resource.options.check_bits(&key, masklen) {
+        Ok(nibbles) => nibbles,
+        Err(reason) => return make_tuple(env, &[atoms::error().encode(env), reason.encode(env)]),
+    };
+    let mut table = table_resource.table.write().unwrap();
+    let prev = table.insert(&nibbles, masklen, OwnedTermSlot::new(value));
+    make_tuple(env, &[atoms::ok().encode(env), prev.encode(env)])
+}
+
+#[rustler::nif]
+fn bits_remove<'a>(
+    env: Env<'a>,
+    table_resource: ResourceArc<TableResource>,
+    key: Binary<'a>,
+    masklen: u32,
+) -> Term<'a> {
+    let nibbles = match table_resource.options.check_bits(&key, masklen) {
+        Ok(nibbles) => nibbles,
+        Err(reason) => return make_tuple(env, &[atoms::error().encode(env), reason.encode(env)]),
+    };
+    let mut table = table_resource.table.write().unwrap();
+    let prev = table.remove(&nibbles, masklen);
+    make_tuple(env, &[atoms::ok().encode(env), prev.encode(env)])
+}
+
+#[rustler::nif]
+fn bits_exact_match<'a>(
+    env: Env<'a>,
+    table_resource: ResourceArc<TableResource>,
+    key: Binary<'a>,
+    masklen: u32,
+) -> Term<'a> {
+    let nibbles = match table_resource.options.check_bits(&key, masklen) {
+        Ok(nibbles) => nibbles,
+        Err(reason) => return make_tuple(env, &[atoms::error().encode(env), reason.encode(env)]),
+    };
+    let table = table_resource.table.read().unwrap();
+    let value = table.tree.exact_match(&nibbles, masklen);
+    make_tuple(env, &[atoms::ok().encode(env), value.encode(env)])
+}
+
+/// Returns the longest prefix of the first ```bits``` bits of ```key```, as
+/// ```{:ok, prefix, masklen, value}```.
+#[rustler::nif]
+fn bits_longest_match<'a>(
+    env: Env<'a>,
+    table_resource: ResourceArc<TableResource>,
+    key: Binary<'a>,
+    bits: u32,
+) -> NifResult<Term<'a>> {
+    if bits as usize > key.len() * 8 {
+        return Err(Error::BadArg);
+    }
+    let nibbles = nibbles::from_bytes(&key[..bits.div_ceil(8) as usize], bits);
+    let table = table_resource.table.read().unwrap();
+    // the trie reads missing nibbles as zeros, longer prefixes must not match
+    let found = table
+        .tree
+        .matches(&nibbles)
+        .into_iter()
+        .rev()
+        .find(|(masklen, _)| *masklen <= bits);
+    Ok(match found {
+        Some((masklen, value)) => make_tuple(
+            env,
+            &[
+                atoms::ok().encode(env),
+                encode_bytes(env, &nibbles::to_bytes(&nibbles, masklen)),
+                masklen.encode(env),
+                value.encode(env),
+            ],
+        ),
+        None => make_tuple(env, &[atoms::ok().encode(env), atoms::nil().encode(env)]),
+    })
+}
+
+/// Returns ```[{prefix, masklen, value}]``` in prefix order, prefixes being
+/// packed in ```ceil(masklen / 8)``` bytes.
+#[rustler::nif(schedule = "DirtyCpu")]
+fn bits_to_list(env: Env, table_resource: ResourceArc<TableResource>) -> Term {
+    let table = table_resource.table.read().unwrap();
+    let list: Vec<Term> = table
+        .tree
+        .iter()
+        .map(|(nibbles, masklen, value)| {
+            make_tuple(
+                env,
+                &[
+                    encode_bytes(env, &nibbles::to_bytes(&nibbles, masklen)),
+                    masklen.encode(env),
+                    value.encode(env),
+                ],
+            )
+        })
+        .collect();
+    list.encode(env)
+}
+
+fn encode_bytes<'a>(env: Env<'a>, bytes: &[u8]) -> Term<'a> {
+    let mut binary = OwnedBinary::new(bytes.len()).unwrap();
+    binary.as_mut_slice().copy_from_slice(bytes);
+    binary.release(env).encode(env)
+}
+
+/// The table of the family of ```mac```: EUI-48 and EUI-64 prefixes are
+/// kept in tables of their own, as IPv4 and IPv6 ones.
+fn mac_table<'r>(
+    eui48: &'r ResourceArc<TableResource>,
+    eui64: &'r ResourceArc<TableResource>,
+    mac: &MacAddr,
+) -> &'r ResourceArc<TableResource> {
+    match mac {
+        MacAddr::Eui48(_) => eui48,
+        MacAddr::Eui64(_) => eui64,
+    }
+}
+
+#[rustler::nif]
+fn mac_add<'a>(
+    env: Env<'a>,
+    eui48: ResourceArc<TableResource>,
+    eui64: ResourceArc<TableResource>,
+    mac: MacAddr,
+    masklen: u32,
+    value: Term<'a>,
+) -> Term<'a> {
+    let table_resource = mac_table(&eui48, &eui64, &mac);
+    let mac = match table_resource.options.check_mac(mac, masklen) {
+        Ok(prefix) => prefix,
+        Err(reason) => return make_tuple(env, &[atoms::error().encode(env), reason.encode(env)]),
+    };
+    let mut table = table_resource.table.write().unwrap();
+    let prev = table.insert(
+        Nibbles::from(mac).as_ref(),
+        masklen,
+        OwnedTermSlot::new(value),
+    );
+    make_tuple(env, &[atoms::ok().encode(env), prev.encode(env)])
+}
+
+#[rustler::nif]
+fn mac_remove(
+    env: Env,
+    eui48: ResourceArc<TableResource>,
+    eui64: ResourceArc<TableResource>,
+    mac: MacAddr,
+    masklen: u32,
+) -> Term {
+    let table_resource = mac_table(&eui48, &eui64, &mac);
+    let mac = match table_resource.options.check_mac(mac, masklen) {
+        Ok(prefix) => prefix,
+        Err(reason) => return make_tuple(env, &[atoms::error().encode(env), reason.encode(env)]),
+    };
+    let mut table = table_resource.table.write().unwrap();
+    let prev = table.remove(Nibbles::from(mac).as_ref(), masklen);
+    make_tuple(env, &[atoms::ok().encode(env), prev.encode(env)])
+}
+
+#[rustler::nif]
+fn mac_exact_match(
+    env: Env,
+    eui48: ResourceArc<TableResource>,
+    eui64: ResourceArc<TableResource>,
+    mac: MacAddr,
+    masklen: u32,
+) -> Term {
+    let table_resource = mac_table(&eui48, &eui64, &mac);
+    let mac = match table_resource.options.check_mac(mac, masklen) {
+        Ok(prefix) => prefix,
+        Err(reason) => return make_tuple(env, &[atoms::error().encode(env), reason.encode(env)]),
+    };
+    let table = table_resource.table.read().unwrap();
+    let value = table.tree.exact_match(Nibbles::from(mac).as_ref(), masklen);
+    make_tuple(env, &[atoms::ok().encode(env), value.encode(env)])
+}
+
+/// Returns the longest prefix covering ```mac``` among the prefixes of its
+/// family as ```{:ok, prefix, masklen, value}```.
+#[rustler::nif]
+fn mac_longest_match(
+    env: Env,
+    eui48: ResourceArc<TableResource>,
+    eui64: ResourceArc<TableResource>,
+    mac: MacAddr,
+) -> Term {
+    let table = mac_table(&eui48, &eui64, &mac).table.read().unwrap();
+    match table.tree.longest_match(Nibbles::from(mac).as_ref()) {
+        Some((masklen, value)) => make_tuple(
+            env,
+            &[
+                atoms::ok().encode(env),
+                mac.mask(masklen).encode(env),
+                masklen.encode(env),
+                value.encode(env),
+            ],
+        ),
+        None => make_tuple(env, &[atoms::ok().encode(env), atoms::nil().encode(env)]),
+    }
+}
+
+/// Returns ```[{prefix, masklen, value}]```, the EUI-48 prefixes then the
+/// EUI-64 ones, each in prefix order.
+#[rustler::nif(schedule = "DirtyCpu")]
+fn mac_to_list(
+    env: Env,
+    eui48: ResourceArc<TableResource>,
+    eui64: ResourceArc<TableResource>,
+) -> Term {
+    let mut list: Vec<Term> = Vec::new();
+    for (table_resource, octets) in [(&eui48, 6), (&eui64, 8)] {
+        let table = table_resource.table.read().unwrap();
+        list.extend(table.tree.iter().map(|(nibbles, masklen, value)| {
+            let prefix = MacAddr::from_nibbles(octets, &nibbles, masklen);
+            make_tuple(
+                env,
+                &[prefix.encode(env), masklen.encode(env), value.encode(env)],
+            )
+        }));
+    }
+    list.encode(env)
+}
+
+/// Adds the MAC address blocks of an IEEE registry CSV file, given as a
+/// binary or as ```{:file, path}```, with
+/// ```%{registry: ..., organization: ..., address: ...}``` values. A block
+/// covers EUI-48 and EUI-64 addresses alike, and is added to both tables.
+///
+/// Returns ```{:ok, count}``` with the number of blocks, or
+/// ```{:error, reason}``` without changing the tables.
+#[rustler::nif(schedule = "DirtyCpu")]
+fn import_oui<'a>(
+    env: Env<'a>,
+    eui48: ResourceArc<TableResource>,
+    eui64: ResourceArc<TableResource>,
+    source: Term<'a>,
+) -> NifResult<Term<'a>> {
+    let bytes = match read_source(source)? {
+        Ok(bytes) => bytes,
+        Err(reason) => {
+            return Ok(make_tuple(
+                env,
+                &[atoms::error().encode(env), reason.encode(env)],
+            ))
+        }
+    };
+    let assignments = match std::str::from_utf8(&bytes).map(oui::parse) {
+        Ok(Ok(assignments)) => assignments,
+        _ => {
+            return Ok(make_tuple(
+                env,
+                &[atoms::error().encode(env), atoms::invalid_oui().encode(env)],
+            ))
+        }
+    };
+
+    let mut owned_env = OwnedEnv::new();
+    let entries: Vec<(MacAddr, u32, OwnedTermSlot)> = assignments
+        .iter()
+        .map(|assignment| {
+            let value = owned_env.run(|env| OwnedTermSlot::new(assignment.encode(env)));
+            owned_env.clear();
+            (assignment.prefix, assignment.masklen, value)
+        })
+        .collect();
+    let count = entries.len();
+    let mut table48 = eui48.table.write().unwrap();
+    let mut table64 = eui64.table.write().unwrap();
+    for (prefix, masklen, value) in entries {
+        // blocks are at most 48 bits long: the EUI-48 nibbles cover the EUI-64 ones
+        let nibbles = Nibbles::from(prefix);
+        table48.insert(nibbles.as_ref(), masklen, value.clone());
+        table64.insert(nibbles.as_ref(), masklen, value);
+    }
+    Ok(make_tuple(
+        env,
+        &[atoms::ok().encode(env), count.encode(env)],
+    ))
+}
+
+/// Parses a ```"addr/masklen"``` or ```"addr"``` string into
+/// ```{:ok, prefix, masklen}```. The prefix is not validated against any
+/// table options.
+#[rustler::nif]
+fn parse_prefix<'a>(env: Env<'a>, cidr: Binary<'a>) -> Term<'a> {
+    // binaries that are not UTF-8 are invalid prefixes like any other
+    let parsed = std::str::from_utf8(cidr.as_slice())
+        .ok()
+        .and_then(AddrTuple::parse_cidr);
+    match parsed {
+        Some((ip, masklen)) => make_tuple(
+            env,
+            &[atoms::ok().encode(env), ip.encode(env), masklen.encode(env)],
+        ),
+        None => make_tuple(
+            env,
+            &[
+                atoms::error().encode(env),
+                atoms::invalid_prefix().encode(env),
+            ],
+        ),
+    }
+}
+
+/// Serializes the table and its options, see ```serialize::dump```.
+#[rustler::nif(schedule = "DirtyCpu")]
+fn dump(env: Env, table_resource: ResourceArc<TableResource>) -> NifResult<Binary> {
+    let table = table_resource.table.read().unwrap();
+    let bytes = serialize::dump(&table, table_resource.options);
+    drop(table);
+    let mut binary = OwnedBinary::new(bytes.len()).ok_or(Error::RaiseAtom("enomem"))?;
+    binary.as_mut_slice().copy_from_slice(&bytes);
+    Ok(binary.release(env))
+}
+
+/// Rebuilds a table of ```family``` prefixes from ```dump``` output, as
+/// ```{:ok, table}``` or ```{:error, reason}```.
+#[rustler::nif(schedule = "DirtyCpu")]
+fn load<'a>(env: Env<'a>, bytes: Binary<'a>, family: AddrFamily) -> Term<'a> {
+    match serialize::load(&bytes, family) {
+        Ok((table, options)) => {
+            let resource = ResourceArc::new(TableResource::new(table, options));
+            make_tuple(env, &[atoms::ok().encode(env), resource.encode(env)])
+        }
+        Err(reason) => make_tuple(env, &[atoms::error().encode(env), reason.encode(env)]),
+    }
+}
+
+/// Returns a table of the prefixes of both tables, with the options of
+/// ```left```. See ```setops::union```.
+#[rustler::nif(schedule = "DirtyCpu")]
+fn union(
+    left: ResourceArc<TableResource>,
+    right: ResourceArc<TableResource>,
+    merge: Merge,
+) -> ResourceArc<TableResource> {
+    let tree = read_both(&left, &right, |left, right| {
+        setops::union(&left.tree, &right.tree, &mut merge_slots(merge))
+    });
+    ResourceArc::new(TableResource::new(Table::from_tree(tree), left.options))
+}
+
+/// Returns a table of the address space covered by both tables, with the
+/// options of ```left```. See ```setops::intersection```.
+#[rustler::nif(schedule = "DirtyCpu")]
+fn intersection(
+    left: ResourceArc<TableResource>,
+    right: ResourceArc<TableResource>,
+    merge: Merge,
+) -> ResourceArc<TableResource> {
+    let tree = read_both(&left, &right, |left, right| {
+        setops::intersection(&left.tree, &right.tree, &mut merge_slots(merge))
+    });
+    ResourceArc::new(TableResource::new(Table::from_tree(tree), left.options))
+}
+
+/// Returns a table of the address space covered by ```left``` and not by
+/// ```right```, with the options of ```left```. See ```setops::difference```.
+#[rustler::nif(schedule = "DirtyCpu")]
+fn difference(
+    left: ResourceArc<TableResource>,
+    right: ResourceArc<TableResource>,
+) -> ResourceArc<TableResource> {
+    let tree = read_both(&left, &right, |left, right| {
+        setops::difference(&left.tree, &right.tree)
+    });
+    ResourceArc::new(TableResource::new(Table::from_tree(tree), left.options))
+}
+
+/// Returns a table with the same longest matches as ```table``` and fewer
+/// prefixes, with the options of ```table```. See ```TreeBitmap::aggregate```.
+#[rustler::nif(schedule = "DirtyCpu")]
+fn aggregate(table_resource: ResourceArc<TableResource>) -> ResourceArc<TableResource> {
+    let tree = table_resource.table.read().unwrap().tree.aggregate();
+    ResourceArc::new(TableResource::new(
+        Table::from_tree(tree),
+        table_resource.options,
+    ))
+}
+
+/// Returns the changes from ```old_table``` to ```new_table``` in prefix order, as
+/// ```{:added, prefix, masklen, value}```, ```{:withdrawn, prefix, masklen,
+/// value}``` or ```{:changed, prefix, masklen, old_value, new_value}```.
+/// Prefixes are encoded with the options of ```new_table```.
+#[rustler::nif(schedule = "DirtyCpu")]
+fn diff(
+    env: Env,
+    old_table: ResourceArc<TableResource>,
+    new_table: ResourceArc<TableResource>,
+    family: AddrFamily,
+) -> Term {
+    let options = new_table.options;
+    let events: Vec<Term> = read_both(&old_table, &new_table, |old_table, new_table| {
+        old_table
+            .tree
+            .diff(&new_table.tree)
+            .into_iter()
+            .map(|change| {
+                let prefix = AddrTuple::from_nibbles(family, &change.nibbles);
+                let prefix = options.encode_prefix(env, prefix, change.masklen);
+                let masklen = change.masklen.encode(env);
+                match (change.old, change.new) {
+                    (None, Some(value)) => make_tuple(
+                        env,
+                        &[
+                            atoms::added().encode(env),
+                            prefix,
+                            masklen,
+                            value.encode(env),
+                        ],
+                    ),
+                    (Some(value), None) => make_tuple(
+                        env,
+                        &[
+                            atoms::withdrawn().encode(env),
+                            prefix,
+                            masklen,
+                            value.encode(env),
+                        ],
+                    ),
+                    (old_value, new_value) => make_tuple(
+                        env,
+                        &[
+                            atoms::changed().encode(env),
+                            prefix,
+                            masklen,
+                            old_value.encode(env),
+                            new_value.encode(env),
+                        ],
+                    ),
+                }
+            })
+            .collect()
+    });
+    events.encode(env)
+}
+
+/// Runs ```f``` with both tables locked for reading, always in the same order
+/// so concurrent calls with swapped tables cannot deadlock behind writers.
+fn read_both<R>(
+    left: &TableResource,
+    right: &TableResource,
+    f: impl FnOnce(&Table, &Table) -> R,
+) -> R {
+    if std::ptr::eq(left, right) {
+        let table = left.table.read().unwrap();
+        return f(&table, &table);
+    }
+    if (left as *const TableResource) < (right as *const TableResource) {
+        let left = left.table.read().unwrap();
+        let right = right.table.read().unwrap();
+        f(&left, &right)
+    } else {
+        let right = right.table.read().unwrap();
+        let left = left.table.read().unwrap();
+        f(&left, &right)
+    }
+}
+
+fn merge_slots(merge: Merge) -> impl FnMut(&Slot, &Slot) -> Slot {
+    let mut owned_env = OwnedEnv::new();
+    move |left, right| match merge {
+        Merge::Left => left.clone(),
+        Merge::Right => right.clone(),
+        Merge::Pair => {
+            let value = owned_env.run(|env| {
+                OwnedTermSlot::new(make_tuple(env, &[left.encode(env), right.encode(env)]))
+            });
+            owned_env.clear();
+            Slot::Value(value)
+        }
+    }
+}
+
+/// Imports the RIB entries of an MRT dump, given as a binary or as
+/// ```{:file, path}```: IPv4 prefixes into ```table_v4```, IPv6 ones into
+/// ```table_v6```. Each prefix gets the list of its RIB entries as value,
+/// replacing any previous one. Returns ```{:ok, {ipv4_count, ipv6_count}}```.
+#[rustler::nif(schedule = "DirtyCpu")]
+fn import_mrt<'a>(
+    env: Env<'a>,
+    table_v4: ResourceArc<TableResource>,
+    table_v6: ResourceArc<TableResource>,
+    source: Term<'a>,
+) -> NifResult<Term<'a>> {
+    let bytes = match read_source(source)? {
+        Ok(bytes) => bytes,
+        Err(reason) => {
+            return Ok(make_tuple(
+                env,
+                &[atoms::error().encode(env), reason.encode(env)],
+            ))
+        }
+    };
+    let ribs = match mrt::parse(&bytes) {
+        Ok(ribs) => ribs,
+        Err(_) => {
+            return Ok(make_tuple(
+                env,
+                &[atoms::error().encode(env), atoms::invalid_mrt().encode(env)],
+            ))
+        }
+    };
+
+    // terms are only built to be copied into their slots, clear them as we go
+    let mut owned_env = OwnedEnv::new();
+    let (mut v4, mut v6) = (Vec::new(), Vec::new());
+    for rib in ribs {
+        let value = owned_env.run(|env| OwnedTermSlot::new(rib.entries.encode(env)));
+        owned_env.clear();
+        match rib.prefix.family() {
+            AddrFamily::Inet4 => v4.push((rib.prefix, rib.masklen, value)),
+            AddrFamily::Inet6 => v6.push((rib.prefix, rib.masklen, value)),
+        }
+    }
+    let counts = (v4.len(), v6.len());
+    for (table_resource, entries) in [(table_v4, v4), (table_v6, v6)] {
+        let mut table = table_resource.table.write().unwrap();
+        for (prefix, masklen, value) in entries {
+            table.insert(Nibbles::from(prefix).as_ref(), masklen, value);
+        }
+    }
+    Ok(make_tuple(
+        env,
+        &[atoms::ok().encode(env), counts.encode(env)],
+    ))
+}
+
+/// Reads an import source: a binary, or ```{:file, path}```. File errors are
+/// returned as ```:enoent```, ```:eacces``` or ```:eio```.
+fn read_source(source: Term) -> NifResult<Result<Cow<[u8]>, Atom>> {
+    if let Ok(binary) = source.decode::<Binary>() {
+        return Ok(Ok(Cow::Borrowed(binary.as_slice())));
+    }
+    let (tag, path): (Atom, String) = source.decode()?;
+    if tag != atoms::file() {
+        return Err(Error::BadArg);
+    }
+    Ok(match fs::read(path) {
+        Ok(contents) => Ok(Cow::Owned(contents)),
+        Err(err) => Err(match err.kind() {
+            io::ErrorKind::NotFound => atoms::enoent(),
+            io::ErrorKind::PermissionDenied => atoms::eacces(),
+            _ => atoms::eio(),
+        }),
+    })
+}
+
+#[rustler::nif]
+fn vrf_new(options: TableOptions) -> ResourceArc<VrfResource> {
+    ResourceArc::new(VrfResource {
+        table: RwLock::new(VrfTable::new()),
+        options,
+    })
+}
+
+#[rustler::nif]
+fn vrf_add<'a>(
+    env: Env<'a>,
+    vrf_resource: ResourceArc<VrfResource>,
+    vrf: u32,
+    ip: AddrTuple,
+    masklen: u32,
+    value: Term<'a>,
+) -> Term<'a> {
+    let ip = match vrf_resource.options.check_prefix(ip, masklen) {
+        Ok(prefix) => prefix,
+        Err(reason) => return make_tuple(env, &[atoms::error().encode(env), reason.encode(env)]),
+    };
+    let mut table = vrf_resource.table.write().unwrap();
+    let prev = table.insert(vrf, ip, masklen, OwnedTermSlot::new(value));
+    make_tuple(env, &[atoms::ok().encode(env), prev.encode(env)])
+}
+
+#[rustler::nif]
+fn vrf_remove(
+    env: Env,
+    vrf_resource: ResourceArc<VrfResource>,
+    vrf: u32,
+    ip: AddrTuple,
+    masklen: u32,
+) -> Term {
+    let ip = match vrf_resource.options.check_prefix(ip, masklen) {
+        Ok(prefix) => prefix,
+        Err(reason) => return make_tuple(env, &[atoms::error().encode(env), reason.encode(env)]),
+    };
+    let mut table = vrf_resource.table.write().unwrap();
+    let prev = table.remove(vrf, ip, masklen);
+    make_tuple(env, &[atoms::ok().encode(env), prev.encode(env)])
+}
+
+/// Removes every prefix of ```vrf```, returns how many there were.
+#[rustler::nif(schedule = "DirtyCpu")]
+fn vrf_clear(vrf_resource: ResourceArc<VrfResource>, vrf: u32) -> usize {
+    vrf_resource.table.write().unwrap().clear(vrf)
+}
+
+/// Returns ```{:ok, found_vrf, prefix, masklen, value}```, ```found_vrf```
+/// being ```vrf``` or the fallback VRF the route was found in.
+#[rustler::nif]
+fn vrf_longest_match(
+    env: Env,
+    vrf_resource: ResourceArc<VrfResource>,
+    vrf: u32,
+    ip: AddrTuple,
+) -> Term {
+    let table = vrf_resource.table.read().unwrap();
+    if let Some((found_vrf, bits_matched, value)) = table.longest_match(vrf, ip) {
+        let prefix = ip.mask(bits_matched);
+        make_tuple(
+            env,
+            &[
+                atoms::ok().encode(env),
+                found_vrf.encode(env),
+                vrf_resource
+                    .options
+                    .encode_prefix(env, prefix, bits_matched),
+                bits_matched.encode(env),
+                value.encode(env),
+            ],
+        )
+    } else {
+        make_tuple(env, &[atoms::ok().encode(env), atoms::nil().encode(env)])
+    }
+}
+
+#[rustler::nif]
+fn vrf_exact_match(
+    env: Env,
+    vrf_resource: ResourceArc<VrfResource>,
+    vrf: u32,
+    ip: AddrTuple,
+    masklen: u32,
+) -> Term {
+    let ip = match vrf_resource.options.check_prefix(ip, masklen) {
+        Ok(prefix) => prefix,
+        Err(reason) => return make_tuple(env, &[atoms::error().encode(env), reason.encode(env)]),
+    };
+    let table = vrf_resource.table.read().unwrap();
+    let value = table.exact_match(vrf, ip, masklen);
+    make_tuple(env, &[atoms::ok().encode(env), value.encode(env)])
+}
+
+/// Makes lookups in ```vrf``` that match no route fall back to
+/// ```fallback```, or stop doing so when ```nil```.
+#[rustler::nif]
+fn vrf_set_fallback(
+    vrf_resource: ResourceArc<VrfResource>,
+    vrf: u32,
+    fallback: Option<u32>,
+) -> Atom {
+    vrf_resource
+        .table
+        .write()
+        .unwrap()
+        .set_fallback(vrf, fallback);
+    atoms::ok()
+}
+
+/// Returns ```{nodes, results, [{vrf, prefixes, result_bytes}]}```: trie
+/// nodes are shared by all VRFs, results are also broken down per VRF.
+#[rustler::nif]
+fn vrf_memory(env: Env, vrf_resource: ResourceArc<VrfResource>) -> Term {
+    let table = vrf_resource.table.read().unwrap();
+    let (nodes, results) = table.mem_usage();
+    let vrfs: Vec<(u32, usize, usize)> = table
+        .usage()
+        .iter()
+        .map(|(vrf, usage)| (*vrf, usage.prefixes, usage.result_bytes()))
+        .collect();
+    (nodes, results, vrfs).encode(env)
+}
+
+#[rustler::nif]
+fn set_new(options: TableOptions) -> ResourceArc<SetResource> {
+    ResourceArc::new(SetResource {
+        set: RwLock::new(IpSet::new()),
+        options,
+    })
+}
+
+/// Returns ```{:ok, added}```, ```added``` being false when the prefix was
+/// already in the set.
+#[rustler::nif]
+fn set_add(env: Env, set_resource: ResourceArc<SetResource>, ip: AddrTuple, masklen: u32) -> Term {
+    let ip = match set_resource.options.check_prefix(ip, masklen) {
+        Ok(prefix) => prefix,
+        Err(reason) => return make_tuple(env, &[atoms::error().encode(env), reason.encode(env)]),
+    };
+    let added = set_resource.set.write().unwrap().insert(ip, masklen);
+    make_tuple(env, &[atoms::ok().encode(env), added.encode(env)])
+}
+
+/// Returns ```{:ok, removed}```.
+#[rustler::nif]
+fn set_remove(
+    env: Env,
+    set_resource: ResourceArc<SetResource>,
+    ip: AddrTuple,
+    masklen: u32,
+) -> Term {
+    let ip = match set_resource.options.check_prefix(ip, masklen) {
+        Ok(prefix) => prefix,
+        Err(reason) => return make_tuple(env, &[atoms::error().encode(env), reason.encode(env)]),
+    };
+    let removed = set_resource.set.write().unwrap().remove(ip, masklen);
+    make_tuple(env, &[atoms::ok().encode(env), removed.encode(env)])
+}
+
+#[rustler::nif]
+fn set_contains(set_resource: ResourceArc<SetResource>, ip: AddrTuple) -> bool {
+    set_resource.set.read().unwrap().contains(ip)
+}
+
+/// Returns the number of addresses covered as an unsigned big-endian binary,
+/// as counts of IPv6 addresses do not fit 64 bits.
+#[rustler::nif(schedule = "DirtyCpu")]
+fn set_cover_count(env: Env, set_resource: ResourceArc<SetResource>, family: AddrFamily) -> Term {
+    let count = set_resource.set.read().unwrap().cover_count(family);
+    let bytes = match count {
+        Some(count) => count.to_be_bytes().to_vec(),
+        // 2^128
+        None => [&[1][..], &[0; 16]].concat(),
+    };
+    encode_bytes(env, &bytes)
+}
+
+/// Returns ```{:ok, set}```, a new set of the addresses of ```parent/masklen```
+/// not covered by ```set_resource```, with its options.
+#[rustler::nif(schedule = "DirtyCpu")]
+fn set_complement(
+    env: Env,
+    set_resource: ResourceArc<SetResource>,
+    parent: AddrTuple,
+    masklen: u32,
+) -> Term {
+    let parent = match set_resource.options.check_prefix(parent, masklen) {
+        Ok(prefix) => prefix,
+        Err(reason) => return make_tuple(env, &[atoms::error().encode(env), reason.encode(env)]),
+    };
+    let complement = set_resource.set.read().unwrap().complement(parent, masklen);
+    let resource = ResourceArc::new(SetResource {
+        set: RwLock::new(complement),
+        options: set_resource.options,
+    });
+    make_tuple(env, &[atoms::ok().encode(env), resource.encode(env)])
+}
+
+/// Returns ```[{prefix, masklen}]``` in prefix order.
+#[rustler::nif(schedule = "DirtyCpu")]
+fn set_to_list(env: Env, set_resource: ResourceArc<SetResource>, family: AddrFamily) -> Term {
+    let set = set_resource.set.read().unwrap();
+    let list: Vec<Term> = set
+        .prefixes(family)
+        .into_iter()
+        .map(|(prefix, masklen)| {
+            make_tuple(
+                env,
+                &[
+                    set_resource.options.encode_prefix(env, prefix, masklen),
+                    masklen.encode(env),
+                ],
+            )
+        })
+        .collect();
+    list.encode(env)
+}
+
+#[rustler::nif]
+fn set_length(set_resource: ResourceArc<SetResource>) -> usize {
+    set_resource.set.read().unwrap().len()
+}
+
+/// Returns the bytes used by trie nodes, a set stores nothing else.
+#[rustler::nif]
+fn set_memory(set_resource: ResourceArc<SetResource>) -> usize {
+    set_resource.set.read().unwrap().mem_usage()
+}
+
 #[rustler::nif]
 fn memory(env: Env, table_resource: ResourceArc<TableResource>) -> Term {
-    let tree = table_resource.tree.lock().unwrap();
-    let (nodes, results) = tree.mem_usage();
+    let table = table_resource.table.read().unwrap();
+    let (nodes, results) = table.mem_usage();
     make_tuple(env, &[nodes.encode(env), results.encode(env)])
 }
 
@@ -119,18 +1550,78 @@ rustler::init!(
     "Elixir.RoutingTable.TreeBitmap",
     [
         new,
+        new_with_options,
         new_with_capacity,
+        clone,
+        set_inet4,
         length,
         add,
+        add_many,
+        add_range,
         remove,
+        add_route,
+        remove_route,
+        routes,
+        add_ecmp,
         longest_match,
+        longest_match_hashed,
+        longest_match_many,
+        longest_match_packed,
+        resolve,
+        matches,
+        more_specifics,
         exact_match,
-        memory
+        to_list,
+        ranges,
+        iter,
+        iter_next,
+        bits_add,
+        bits_remove,
+        bits_exact_match,
+        bits_longest_match,
+        bits_to_list,
+        mac_add,
+        mac_remove,
+        mac_exact_match,
+        mac_longest_match,
+        mac_to_list,
+        parse_prefix,
+        dump,
+        load,
+        union,
+        intersection,
+        difference,
+        diff,
+        aggregate,
+        import_mrt,
+        import_oui,
+        memory,
+        vrf_new,
+        vrf_add,
+        vrf_remove,
+        vrf_clear,
+        vrf_longest_match,
+        vrf_exact_match,
+        vrf_set_fallback,
+        vrf_memory,
+        set_new,
+        set_add,
+        set_remove,
+        set_contains,
+        set_cover_count,
+        set_complement,
+        set_to_list,
+        set_length,
+        set_memory
     ],
     load = on_load
 );
 
+#[allow(non_local_definitions)]
 fn on_load(env: Env, _info: Term) -> bool {
     rustler::resource!(TableResource, env);
+    rustler::resource!(CursorResource, env);
+    rustler::resource!(VrfResource, env);
+    rustler::resource!(SetResource, env);
     true
 }
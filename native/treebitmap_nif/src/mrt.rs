@@ -0,0 +1,549 @@
+//! MRT routing information export format reader (RFC 6396), limited to the
+//! RIB dumps published by route collectors: ```TABLE_DUMP_V2```
+//! ```RIB_IPV4_UNICAST``` / ```RIB_IPV6_UNICAST``` and legacy ```TABLE_DUMP```
+//! records. Other records are skipped.
+
+use crate::addrs::{AddrFamily, AddrTuple, Maskable};
+use rustler::{Encoder, Env, Term};
+use std::collections::HashMap;
+use std::convert::TryInto;
+
+mod atoms {
+    rustler::atoms! {
+        peer_ip,
+        peer_as,
+        originated_at,
+        origin,
+        as_path,
+        origin_as,
+        next_hop,
+        igp,
+        egp,
+        incomplete,
+        nil
+    }
+}
+
+const TABLE_DUMP: u16 = 12;
+const TABLE_DUMP_V2: u16 = 13;
+
+const AFI_IPV4: u16 = 1;
+const AFI_IPV6: u16 = 2;
+
+const PEER_INDEX_TABLE: u16 = 1;
+const RIB_IPV4_UNICAST: u16 = 2;
+const RIB_IPV6_UNICAST: u16 = 4;
+
+const ATTR_ORIGIN: u8 = 1;
+const ATTR_AS_PATH: u8 = 2;
+const ATTR_NEXT_HOP: u8 = 3;
+const ATTR_MP_REACH_NLRI: u8 = 14;
+const ATTR_AS4_PATH: u8 = 17;
+
+const AS_SET: u8 = 1;
+const AS_SEQUENCE: u8 = 2;
+
+#[derive(Debug, PartialEq)]
+pub enum Error {
+    Truncated,
+    MissingPeerIndex,
+    UnknownPeer(u16),
+    InvalidPrefix,
+}
+
+/// A prefix and the routes the collector's peers sent for it.
+pub struct Rib {
+    pub prefix: AddrTuple,
+    pub masklen: u32,
+    pub entries: Vec<RibEntry>,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct RibEntry {
+    pub peer_ip: AddrTuple,
+    pub peer_as: u32,
+    pub originated_at: u32,
+    pub origin: Option<u8>,
+    pub as_path: Vec<Segment>,
+    pub next_hop: Option<AddrTuple>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Segment {
+    Sequence(Vec<u32>),
+    Set(Vec<u32>),
+}
+
+#[derive(Clone, Copy)]
+struct Peer {
+    ip: AddrTuple,
+    asn: u32,
+}
+
+/// Parses every RIB record of an MRT file, grouping entries by prefix in
+/// order of first appearance.
+pub fn parse(bytes: &[u8]) -> Result<Vec<Rib>, Error> {
+    let mut reader = Reader { bytes, pos: 0 };
+    let mut peers: Option<Vec<Peer>> = None;
+    let mut ribs: Vec<Rib> = Vec::new();
+    let mut index: HashMap<(AddrTuple, u32), usize> = HashMap::new();
+
+    while reader.pos < bytes.len() {
+        let _timestamp = reader.u32()?;
+        let mrt_type = reader.u16()?;
+        let subtype = reader.u16()?;
+        let len = reader.u32()? as usize;
+        let mut body = Reader {
+            bytes: reader.take(len)?,
+            pos: 0,
+        };
+        let (prefix, masklen, entries) = match (mrt_type, subtype) {
+            (TABLE_DUMP_V2, PEER_INDEX_TABLE) => {
+                peers = Some(parse_peer_index(&mut body)?);
+                continue;
+            }
+            (TABLE_DUMP_V2, RIB_IPV4_UNICAST) | (TABLE_DUMP_V2, RIB_IPV6_UNICAST) => {
+                let family = match subtype {
+                    RIB_IPV4_UNICAST => AddrFamily::Inet4,
+                    _ => AddrFamily::Inet6,
+                };
+                let peers = peers.as_deref().ok_or(Error::MissingPeerIndex)?;
+                parse_rib(&mut body, family, peers)?
+            }
+            (TABLE_DUMP, AFI_IPV4) => parse_table_dump(&mut body, AddrFamily::Inet4)?,
+            (TABLE_DUMP, AFI_IPV6) => parse_table_dump(&mut body, AddrFamily::Inet6)?,
+            _ => continue,
+        };
+        match index.get(&(prefix, masklen)) {
+            Some(&i) => ribs[i].entries.extend(entries),
+            None => {
+                index.insert((prefix, masklen), ribs.len());
+                ribs.push(Rib {
+                    prefix,
+                    masklen,
+                    entries,
+                });
+            }
+        }
+    }
+    Ok(ribs)
+}
+
+fn parse_peer_index(body: &mut Reader) -> Result<Vec<Peer>, Error> {
+    let _collector_id = body.take(4)?;
+    let view_name_len = body.u16()? as usize;
+    body.take(view_name_len)?;
+    let count = body.u16()?;
+    (0..count)
+        .map(|_| {
+            let peer_type = body.u8()?;
+            let _bgp_id = body.take(4)?;
+            let family = match peer_type & 1 {
+                0 => AddrFamily::Inet4,
+                _ => AddrFamily::Inet6,
+            };
+            let ip = body.addr(family)?;
+            let asn = match peer_type & 2 {
+                0 => body.u16()? as u32,
+                _ => body.u32()?,
+            };
+            Ok(Peer { ip, asn })
+        })
+        .collect()
+}
+
+fn parse_rib(
+    body: &mut Reader,
+    family: AddrFamily,
+    peers: &[Peer],
+) -> Result<(AddrTuple, u32, Vec<RibEntry>), Error> {
+    let _sequence = body.u32()?;
+    let masklen = body.u8()? as u32;
+    let prefix = body.prefix(family, masklen)?;
+    let count = body.u16()?;
+    let entries = (0..count)
+        .map(|_| {
+            let peer_index = body.u16()?;
+            let peer = *peers
+                .get(peer_index as usize)
+                .ok_or(Error::UnknownPeer(peer_index))?;
+            let originated_at = body.u32()?;
+            let len = body.u16()? as usize;
+            let mut attributes = Reader {
+                bytes: body.take(len)?,
+                pos: 0,
+            };
+            // ```TABLE_DUMP_V2``` always encodes AS numbers on 4 bytes
+            parse_attributes(&mut attributes, peer, originated_at, 4)
+        })
+        .collect::<Result<_, _>>()?;
+    Ok((prefix, masklen, entries))
+}
+
+fn parse_table_dump(
+    body: &mut Reader,
+    family: AddrFamily,
+) -> Result<(AddrTuple, u32, Vec<RibEntry>), Error> {
+    let _view = body.u16()?;
+    let _sequence = body.u16()?;
+    let prefix = body.addr(family)?;
+    let masklen = body.u8()? as u32;
+    if masklen > prefix.max_masklen() {
+        return Err(Error::InvalidPrefix);
+    }
+    let _status = body.u8()?;
+    let originated_at = body.u32()?;
+    let peer = Peer {
+        ip: body.addr(family)?,
+        asn: body.u16()? as u32,
+    };
+    let len = body.u16()? as usize;
+    let mut attributes = Reader {
+        bytes: body.take(len)?,
+        pos: 0,
+    };
+    let entry = parse_attributes(&mut attributes, peer, originated_at, 2)?;
+    Ok((prefix.mask(masklen), masklen, vec![entry]))
+}
+
+fn parse_attributes(
+    attributes: &mut Reader,
+    peer: Peer,
+    originated_at: u32,
+    asn_size: usize,
+) -> Result<RibEntry, Error> {
+    let mut entry = RibEntry {
+        peer_ip: peer.ip,
+        peer_as: peer.asn,
+        originated_at,
+        origin: None,
+        as_path: Vec::new(),
+        next_hop: None,
+    };
+    let mut as4_path = None;
+    while attributes.pos < attributes.bytes.len() {
+        let flags = attributes.u8()?;
+        let attr_type = attributes.u8()?;
+        let len = match flags & 0x10 {
+            0 => attributes.u8()? as usize,
+            _ => attributes.u16()? as usize,
+        };
+        let mut value = Reader {
+            bytes: attributes.take(len)?,
+            pos: 0,
+        };
+        match attr_type {
+            ATTR_ORIGIN => entry.origin = Some(value.u8()?),
+            ATTR_AS_PATH => entry.as_path = parse_as_path(&mut value, asn_size)?,
+            ATTR_NEXT_HOP if len == 4 => {
+                entry.next_hop = Some(value.addr(AddrFamily::Inet4)?);
+            }
+            ATTR_MP_REACH_NLRI => entry.next_hop = parse_mp_reach_next_hop(&mut value)?,
+            ATTR_AS4_PATH if asn_size == 2 => as4_path = Some(parse_as_path(&mut value, 4)?),
+            _ => {}
+        }
+    }
+    if let Some(as4_path) = as4_path {
+        entry.as_path = merge_as4_path(std::mem::take(&mut entry.as_path), as4_path);
+    }
+    Ok(entry)
+}
+
+fn parse_as_path(value: &mut Reader, asn_size: usize) -> Result<Vec<Segment>, Error> {
+    let mut segments = Vec::new();
+    while value.pos < value.bytes.len() {
+        let segment_type = value.u8()?;
+        let count = value.u8()?;
+        let asns = (0..count)
+            .map(|_| match asn_size {
+                2 => Ok(value.u16()? as u32),
+                _ => value.u32(),
+            })
+            .collect::<Result<_, _>>()?;
+        match segment_type {
+            AS_SET => segments.push(Segment::Set(asns)),
+            AS_SEQUENCE => segments.push(Segment::Sequence(asns)),
+            // confederation segments are local to the confederation
+            _ => {}
+        }
+    }
+    Ok(segments)
+}
+
+/// Rebuilds the path of a 2-byte AS speaker from ```AS_PATH```, where 4-byte
+/// AS numbers are ```AS_TRANS```, and ```AS4_PATH``` (RFC 6793 section
+/// 4.2.3): the leading AS of ```AS_PATH``` that ```AS4_PATH``` does not
+/// cover, then ```AS4_PATH```. An ```AS4_PATH``` longer than ```AS_PATH``` is
+/// ignored.
+fn merge_as4_path(as_path: Vec<Segment>, as4_path: Vec<Segment>) -> Vec<Segment> {
+    // an ```AS_SET``` counts as one AS
+    let count = |path: &[Segment]| -> usize {
+        path.iter()
+            .map(|segment| match segment {
+                Segment::Sequence(asns) => asns.len(),
+                Segment::Set(_) => 1,
+            })
+            .sum()
+    };
+    let (as_count, as4_count) = (count(&as_path), count(&as4_path));
+    if as_count < as4_count {
+        return as_path;
+    }
+    let mut leading = as_count - as4_count;
+    let mut merged = Vec::new();
+    for segment in as_path {
+        if leading == 0 {
+            break;
+        }
+        match segment {
+            Segment::Sequence(mut asns) => {
+                asns.truncate(leading);
+                leading -= asns.len();
+                merged.push(Segment::Sequence(asns));
+            }
+            set => {
+                leading -= 1;
+                merged.push(set);
+            }
+        }
+    }
+    merged.extend(as4_path);
+    merged
+}
+
+/// ```TABLE_DUMP_V2``` only keeps the next hop length and address of
+/// ```MP_REACH_NLRI```, ```TABLE_DUMP``` and some writers keep the whole
+/// attribute. An IPv6 global address may be followed by a link-local one.
+fn parse_mp_reach_next_hop(value: &mut Reader) -> Result<Option<AddrTuple>, Error> {
+    if value.bytes.first().map(|&len| len as usize + 1) != Some(value.bytes.len()) {
+        let _afi = value.u16()?;
+        let _safi = value.u8()?;
+    }
+    let len = value.u8()? as usize;
+    let next_hop = value.take(len)?;
+    Ok(match len {
+        4 => AddrTuple::from_packed(AddrFamily::Inet4, next_hop),
+        16 | 32 => AddrTuple::from_packed(AddrFamily::Inet6, &next_hop[..16]),
+        _ => None,
+    })
+}
+
+impl RibEntry {
+    /// The AS originating the route, unless the path ends with an
+    /// ```AS_SET``` of several AS.
+    pub fn origin_as(&self) -> Option<u32> {
+        match self.as_path.last()? {
+            Segment::Sequence(asns) => asns.last().copied(),
+            Segment::Set(asns) if asns.len() == 1 => Some(asns[0]),
+            Segment::Set(_) => None,
+        }
+    }
+}
+
+/// Encoded as a map with addresses as ```:inet``` tuples. The AS path is a
+/// list of AS numbers, with ```AS_SET``` segments as nested lists.
+impl Encoder for RibEntry {
+    fn encode<'a>(&self, env: Env<'a>) -> Term<'a> {
+        let origin = match self.origin {
+            Some(0) => atoms::igp().encode(env),
+            Some(1) => atoms::egp().encode(env),
+            Some(2) => atoms::incomplete().encode(env),
+            _ => atoms::nil().encode(env),
+        };
+        let as_path: Vec<Term> = self
+            .as_path
+            .iter()
+            .flat_map(|segment| match segment {
+                Segment::Sequence(asns) => asns.iter().map(|asn| asn.encode(env)).collect(),
+                Segment::Set(asns) => vec![asns.encode(env)],
+            })
+            .collect();
+        let keys = [
+            atoms::peer_ip().encode(env),
+            atoms::peer_as().encode(env),
+            atoms::originated_at().encode(env),
+            atoms::origin().encode(env),
+            atoms::as_path().encode(env),
+            atoms::origin_as().encode(env),
+            atoms::next_hop().encode(env),
+        ];
+        let values = [
+            self.peer_ip.encode_inet(env),
+            self.peer_as.encode(env),
+            self.originated_at.encode(env),
+            origin,
+            as_path.encode(env),
+            self.origin_as().encode(env),
+            match self.next_hop {
+                Some(next_hop) => next_hop.encode_inet(env),
+                None => atoms::nil().encode(env),
+            },
+        ];
+        Term::map_from_arrays(env, &keys, &values).unwrap()
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], Error> {
+        let end = self.pos.checked_add(n).ok_or(Error::Truncated)?;
+        let slice = self.bytes.get(self.pos..end).ok_or(Error::Truncated)?;
+        self.pos = end;
+        Ok(slice)
+    }
+
+    fn u8(&mut self) -> Result<u8, Error> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, Error> {
+        Ok(u16::from_be_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    fn u32(&mut self) -> Result<u32, Error> {
+        Ok(u32::from_be_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn addr(&mut self, family: AddrFamily) -> Result<AddrTuple, Error> {
+        let bytes = self.take(family.octets())?;
+        Ok(AddrTuple::from_packed(family, bytes).unwrap())
+    }
+
+    /// Reads a prefix truncated to ```ceil(masklen / 8)``` bytes, host bits
+    /// cleared.
+    fn prefix(&mut self, family: AddrFamily, masklen: u32) -> Result<AddrTuple, Error> {
+        let mut bytes = [0; 16];
+        if masklen as usize > family.octets() * 8 {
+            return Err(Error::InvalidPrefix);
+        }
+        let len = masklen.div_ceil(8) as usize;
+        bytes[..len].copy_from_slice(self.take(len)?);
+        let prefix = AddrTuple::from_packed(family, &bytes[..family.octets()]).unwrap();
+        Ok(prefix.mask(masklen))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::addrs::{TupleV4, TupleV6};
+
+    fn v4(a: u8, b: u8, c: u8, d: u8) -> AddrTuple {
+        AddrTuple::V4(TupleV4 { a, b, c, d })
+    }
+
+    fn v6(bytes: [u8; 16]) -> AddrTuple {
+        AddrTuple::V6(TupleV6::from_octets(bytes))
+    }
+
+    const DOC6: [u8; 16] = [0x20, 0x01, 0x0d, 0xb8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+
+    #[test]
+    fn table_dump_v2() {
+        let ribs = parse(include_bytes!("../tests/fixtures/table_dump_v2.mrt")).unwrap();
+        let prefixes: Vec<(AddrTuple, u32, usize)> = ribs
+            .iter()
+            .map(|rib| (rib.prefix, rib.masklen, rib.entries.len()))
+            .collect();
+        assert_eq!(
+            prefixes,
+            vec![
+                (v4(10, 0, 0, 0), 8, 2),
+                (v4(192, 0, 2, 0), 24, 1),
+                (v6(DOC6), 32, 1)
+            ]
+        );
+        assert_eq!(
+            ribs[0].entries[1],
+            RibEntry {
+                peer_ip: v6({
+                    let mut ip = DOC6;
+                    ip[15] = 1;
+                    ip
+                }),
+                peer_as: 4_200_000_000,
+                originated_at: 1_699_999_800,
+                origin: Some(2),
+                as_path: vec![Segment::Sequence(vec![4_200_000_000, 64501])],
+                next_hop: Some(v4(192, 0, 2, 99)),
+            }
+        );
+        assert_eq!(ribs[0].entries[0].origin_as(), Some(64500));
+        assert_eq!(ribs[1].entries[0].origin_as(), None);
+        let mut next_hop = DOC6;
+        next_hop[15] = 1;
+        assert_eq!(ribs[2].entries[0].next_hop, Some(v6(next_hop)));
+        assert_eq!(ribs[2].entries[0].origin_as(), Some(65551));
+    }
+
+    #[test]
+    fn table_dump() {
+        let ribs = parse(include_bytes!("../tests/fixtures/table_dump.mrt")).unwrap();
+        assert_eq!(ribs.len(), 3);
+        assert_eq!((ribs[0].prefix, ribs[0].masklen), (v4(10, 0, 0, 0), 8));
+        let peers: Vec<(u32, Option<u32>)> = ribs[0]
+            .entries
+            .iter()
+            .map(|entry| (entry.peer_as, entry.origin_as()))
+            .collect();
+        assert_eq!(peers, vec![(64496, Some(3356)), (64497, Some(174))]);
+        let mut next_hop = DOC6;
+        next_hop[15] = 2;
+        assert_eq!((ribs[1].prefix, ribs[1].masklen), (v6(DOC6), 32));
+        assert_eq!(ribs[1].entries[0].next_hop, Some(v6(next_hop)));
+        // 2-byte AS paths with an AS4_PATH
+        assert_eq!((ribs[2].prefix, ribs[2].masklen), (v4(192, 0, 2, 0), 24));
+        assert_eq!(
+            ribs[2].entries[0].as_path,
+            vec![
+                Segment::Sequence(vec![64498]),
+                Segment::Sequence(vec![4_200_000_000])
+            ]
+        );
+        assert_eq!(ribs[2].entries[0].origin_as(), Some(4_200_000_000));
+        assert_eq!(
+            ribs[2].entries[1].as_path,
+            vec![Segment::Sequence(vec![64499, 174])]
+        );
+    }
+
+    #[test]
+    fn as4_path() {
+        let (seq, set) = (Segment::Sequence, Segment::Set);
+        assert_eq!(
+            merge_as4_path(
+                vec![seq(vec![64496, 23456]), set(vec![23456, 64497])],
+                vec![set(vec![196608, 64497])]
+            ),
+            vec![seq(vec![64496, 23456]), set(vec![196608, 64497])]
+        );
+        assert_eq!(
+            merge_as4_path(vec![seq(vec![23456])], vec![seq(vec![196608])]),
+            vec![seq(vec![196608])]
+        );
+        assert_eq!(
+            merge_as4_path(vec![seq(vec![64496])], vec![seq(vec![1, 2])]),
+            vec![seq(vec![64496])]
+        );
+    }
+
+    #[test]
+    fn invalid() {
+        let bytes = include_bytes!("../tests/fixtures/table_dump_v2.mrt");
+        assert_eq!(
+            parse(&bytes[..bytes.len() - 1]).err(),
+            Some(Error::Truncated)
+        );
+        // the first record is the peer index table
+        let len = u32::from_be_bytes(bytes[8..12].try_into().unwrap()) as usize;
+        assert_eq!(
+            parse(&bytes[12 + len..]).err(),
+            Some(Error::MissingPeerIndex)
+        );
+        assert!(parse(&[]).unwrap().is_empty());
+    }
+}
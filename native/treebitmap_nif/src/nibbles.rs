@@ -5,9 +5,16 @@ pub struct NibblesV6 {
     pub n: [u8; 32],
 }
 
+/// Nibbles of an EUI-48 (12) or EUI-64 (16) MAC address.
+pub struct NibblesMac {
+    pub n: [u8; 16],
+    pub len: usize,
+}
+
 pub enum Nibbles {
     V4(NibblesV4),
     V6(NibblesV6),
+    Mac(NibblesMac),
 }
 
 impl AsRef<[u8]> for Nibbles {
@@ -15,6 +22,7 @@ impl AsRef<[u8]> for Nibbles {
         match self {
             Nibbles::V4(nib4) => nib4.as_ref(),
             Nibbles::V6(nib6) => nib6.as_ref(),
+            Nibbles::Mac(nib_mac) => nib_mac.as_ref(),
         }
     }
 }
@@ -30,3 +38,56 @@ impl AsRef<[u8]> for NibblesV6 {
         &self.n
     }
 }
+
+impl AsRef<[u8]> for NibblesMac {
+    fn as_ref(&self) -> &[u8] {
+        &self.n[..self.len]
+    }
+}
+
+/// Packs the first ```masklen``` bits of ```nibbles``` into
+/// ```ceil(masklen / 8)``` bytes.
+pub fn to_bytes(nibbles: &[u8], masklen: u32) -> Vec<u8> {
+    let mut bytes: Vec<u8> = (0..masklen.div_ceil(8) as usize)
+        .map(|i| {
+            let high = *nibbles.get(i * 2).unwrap_or(&0);
+            let low = *nibbles.get(i * 2 + 1).unwrap_or(&0);
+            high << 4 | low
+        })
+        .collect();
+    clear_host_bits(&mut bytes, masklen);
+    bytes
+}
+
+/// Unpacks bytes into nibbles. Host bits are cleared, the trie must never
+/// see them.
+pub fn from_bytes(bytes: &[u8], masklen: u32) -> Vec<u8> {
+    let mut bytes = bytes.to_vec();
+    clear_host_bits(&mut bytes, masklen);
+    bytes
+        .iter()
+        .flat_map(|byte| [byte >> 4, byte & 0xf])
+        .collect()
+}
+
+fn clear_host_bits(bytes: &mut [u8], masklen: u32) {
+    if masklen % 8 != 0 {
+        if let Some(last) = bytes.last_mut() {
+            *last &= !0 << (8 - masklen % 8);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bytes_roundtrip() {
+        let nibbles = [0xa, 0x4, 0x5, 0xf, 0x0];
+        assert_eq!(to_bytes(&nibbles, 12), vec![0xa4, 0x50]);
+        assert_eq!(from_bytes(&[0xa4, 0x5f], 12), vec![0xa, 0x4, 0x5, 0x0]);
+        assert_eq!(to_bytes(&nibbles, 0), Vec::<u8>::new());
+        assert_eq!(to_bytes(&[], 9), vec![0, 0]);
+    }
+}
@@ -0,0 +1,196 @@
+//! IEEE registration authority CSV reader, as published for the MA-L
+//! (```oui.csv```), MA-M, MA-S and IAB registries. CID assignments do not
+//! identify MAC address blocks and are skipped.
+
+use crate::addrs::MacAddr;
+use rustler::{Encoder, Env, Term};
+
+mod atoms {
+    rustler::atoms! {
+        registry,
+        organization,
+        address
+    }
+}
+
+const HEADER: [&str; 4] = [
+    "Registry",
+    "Assignment",
+    "Organization Name",
+    "Organization Address",
+];
+
+#[derive(Debug, PartialEq)]
+pub enum Error {
+    InvalidHeader,
+    /// A record, numbered from 1 after the header, is malformed.
+    InvalidRecord(usize),
+}
+
+/// A block of MAC addresses and the organization it is assigned to.
+#[derive(Debug, PartialEq)]
+pub struct Assignment {
+    pub prefix: MacAddr,
+    pub masklen: u32,
+    pub registry: String,
+    pub organization: String,
+    pub address: String,
+}
+
+impl Encoder for Assignment {
+    fn encode<'a>(&self, env: Env<'a>) -> Term<'a> {
+        let keys = [
+            atoms::registry().encode(env),
+            atoms::organization().encode(env),
+            atoms::address().encode(env),
+        ];
+        let values = [
+            self.registry.encode(env),
+            self.organization.encode(env),
+            self.address.encode(env),
+        ];
+        Term::map_from_arrays(env, &keys, &values).unwrap()
+    }
+}
+
+/// Parses a registry CSV file. The assignment is a prefix of as many bits
+/// as it has hex digits times 4, organization fields are trimmed.
+pub fn parse(text: &str) -> Result<Vec<Assignment>, Error> {
+    let mut records = records(text.trim_start_matches('\u{feff}'));
+    match records.next() {
+        Some(header) if header == HEADER => (),
+        _ => return Err(Error::InvalidHeader),
+    }
+    let mut assignments = Vec::new();
+    for (i, record) in records.enumerate() {
+        let invalid = Error::InvalidRecord(i + 1);
+        let (registry, assignment, organization, address) = match record.as_slice() {
+            [registry, assignment, organization, address] => {
+                (registry, assignment, organization, address)
+            }
+            [blank] if blank.is_empty() => continue,
+            _ => return Err(invalid),
+        };
+        match registry.as_str() {
+            "MA-L" | "MA-M" | "MA-S" | "IAB" => (),
+            _ => continue,
+        }
+        if assignment.is_empty()
+            || assignment.len() > 12
+            || !assignment.chars().all(|c| c.is_ascii_hexdigit())
+        {
+            return Err(invalid);
+        }
+        let mut octets = [0u8; 6];
+        for (i, digit) in assignment.chars().enumerate() {
+            let nibble = digit.to_digit(16).unwrap() as u8;
+            octets[i / 2] |= if i % 2 == 0 { nibble << 4 } else { nibble };
+        }
+        assignments.push(Assignment {
+            prefix: MacAddr::Eui48(octets),
+            masklen: assignment.len() as u32 * 4,
+            registry: registry.clone(),
+            organization: organization.trim().to_string(),
+            address: address.trim().to_string(),
+        });
+    }
+    Ok(assignments)
+}
+
+/// Splits RFC 4180 CSV into records: fields may be quoted, quotes in quoted
+/// fields are doubled, and quoted fields may span lines.
+fn records(text: &str) -> impl Iterator<Item = Vec<String>> + '_ {
+    let mut chars = text.chars().peekable();
+    std::iter::from_fn(move || {
+        chars.peek()?;
+        let mut record = Vec::new();
+        let mut field = String::new();
+        let mut quoted = false;
+        while let Some(c) = chars.next() {
+            match c {
+                '"' if quoted && chars.peek() == Some(&'"') => {
+                    chars.next();
+                    field.push('"');
+                }
+                '"' if quoted || field.is_empty() => quoted = !quoted,
+                ',' if !quoted => record.push(std::mem::take(&mut field)),
+                '\r' if !quoted && chars.peek() == Some(&'\n') => (),
+                '\n' if !quoted => break,
+                c => field.push(c),
+            }
+        }
+        record.push(field);
+        Some(record)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn registries() {
+        let assignments = parse(include_str!("../tests/fixtures/oui.csv")).unwrap();
+        let prefixes: Vec<(MacAddr, u32, &str)> = assignments
+            .iter()
+            .map(|a| (a.prefix, a.masklen, a.registry.as_str()))
+            .collect();
+        assert_eq!(
+            prefixes,
+            vec![
+                (MacAddr::Eui48([0x00, 0x1b, 0x63, 0, 0, 0]), 24, "MA-L"),
+                (MacAddr::Eui48([0x28, 0x6f, 0xb9, 0, 0, 0]), 24, "MA-L"),
+                (MacAddr::Eui48([0x70, 0xb3, 0xd5, 0xe0, 0, 0]), 28, "MA-M"),
+                (
+                    MacAddr::Eui48([0x70, 0xb3, 0xd5, 0xf2, 0xc0, 0]),
+                    36,
+                    "MA-S"
+                ),
+                (MacAddr::Eui48([0x00, 0x50, 0xc2, 0x12, 0x30, 0]), 36, "IAB"),
+            ]
+        );
+        assert_eq!(assignments[0].organization, "Apple, Inc.");
+        assert_eq!(
+            assignments[0].address,
+            "1 Infinite Loop Cupertino CA US 95014"
+        );
+        assert_eq!(
+            assignments[1].address,
+            "No.388 Ning Qiao Road,Jin Qiao Pudong Shanghai Shanghai   CN 201206"
+        );
+        assert_eq!(assignments[2].organization, "Acme \"Labs\" GmbH");
+        assert_eq!(assignments[3].address, "");
+    }
+
+    #[test]
+    fn invalid() {
+        let header = "Registry,Assignment,Organization Name,Organization Address\r\n";
+        assert_eq!(
+            parse("Registry,Assignment\n").err(),
+            Some(Error::InvalidHeader)
+        );
+        assert_eq!(parse("").err(), Some(Error::InvalidHeader));
+        assert_eq!(
+            parse(&format!("{}MA-L,001B63,Apple,Cupertino\r\n\r\n", header))
+                .unwrap()
+                .len(),
+            1
+        );
+        assert_eq!(
+            parse(&format!("{}MA-L,001B63,Apple\r\n", header)).err(),
+            Some(Error::InvalidRecord(1))
+        );
+        assert_eq!(
+            parse(&format!(
+                "{}MA-L,001B63,Apple,\r\nMA-L,00ZZ63,Bad,\r\n",
+                header
+            ))
+            .err(),
+            Some(Error::InvalidRecord(2))
+        );
+        assert_eq!(
+            parse(&format!("{}MA-L,,Nobody,\r\n", header)).err(),
+            Some(Error::InvalidRecord(1))
+        );
+    }
+}
@@ -0,0 +1,234 @@
+use crate::addrs::AddrFamily;
+use crate::nibbles;
+use crate::table::{PrefixFormat, Table, TableOptions};
+use crate::value::{Member, OwnedTermSlot, Route, Slot};
+use rustler::Atom;
+use std::convert::TryInto;
+
+mod atoms {
+    rustler::atoms! {
+        invalid_dump,
+        unsupported_version,
+        checksum_mismatch
+    }
+}
+
+/// Serialized table layout, all integers big endian:
+///
+/// + header: ```"RTBM"```, format version (u16), options (u8), entry count (u64)
+/// + each entry: masklen (u8), the prefix packed in ```ceil(masklen / 8)```
+///   bytes, then the slot kind (u8) and:
+///   + ```0```: a term
+///   + ```1```: the route count (u32) and for each route its source term,
+///     distance (u32), metric (u32) and value term
+///   + ```2```: the ECMP member count (u32) and a term per member
+/// + terms are stored as their length (u32) and external term format
+/// + CRC-32 (IEEE) of everything before it (u32)
+///
+/// Entries are stored rather than trie nodes so the format does not depend on
+/// the in-memory layout. Bump ```VERSION``` on any change, and keep loading
+/// the previous versions.
+const MAGIC: &[u8; 4] = b"RTBM";
+const VERSION: u16 = 3;
+
+// version 1 entries hold a term right after the prefix, with no slot kind
+const VERSION_1: u16 = 1;
+
+const SLOT_VALUE: u8 = 0;
+const SLOT_ROUTES: u8 = 1;
+const SLOT_ECMP: u8 = 2;
+
+const OPTION_STRICT: u8 = 1;
+const OPTION_PREFIX_CIDR: u8 = 2;
+// the ```embedded_ipv4``` set takes the next bits
+const OPTION_EMBEDDED_IPV4_SHIFT: u8 = 2;
+
+pub fn dump(table: &Table, options: TableOptions) -> Vec<u8> {
+    let mut out = Vec::with_capacity(64 + table.tree.len() * 32);
+    out.extend_from_slice(MAGIC);
+    out.extend_from_slice(&VERSION.to_be_bytes());
+    let mut flags = 0;
+    if options.strict {
+        flags |= OPTION_STRICT;
+    }
+    if options.prefix_format == PrefixFormat::Cidr {
+        flags |= OPTION_PREFIX_CIDR;
+    }
+    flags |= options.embedded_ipv4 << OPTION_EMBEDDED_IPV4_SHIFT;
+    out.push(flags);
+    out.extend_from_slice(&(table.tree.len() as u64).to_be_bytes());
+    for (nibbles, masklen, slot) in table.tree.iter() {
+        out.push(masklen as u8);
+        out.extend_from_slice(&nibbles::to_bytes(&nibbles, masklen));
+        match slot {
+            Slot::Value(value) => {
+                out.push(SLOT_VALUE);
+                write_term(&mut out, value);
+            }
+            Slot::Routes(routes) => {
+                out.push(SLOT_ROUTES);
+                out.extend_from_slice(&(routes.len() as u32).to_be_bytes());
+                for route in routes {
+                    write_term(&mut out, &route.source);
+                    out.extend_from_slice(&route.distance.to_be_bytes());
+                    out.extend_from_slice(&route.metric.to_be_bytes());
+                    write_term(&mut out, &route.value);
+                }
+            }
+            Slot::Ecmp(members) => {
+                out.push(SLOT_ECMP);
+                out.extend_from_slice(&(members.len() as u32).to_be_bytes());
+                for member in members {
+                    write_term(&mut out, &member.value);
+                }
+            }
+        }
+    }
+    let checksum = crc32(&out);
+    out.extend_from_slice(&checksum.to_be_bytes());
+    out
+}
+
+/// Rebuilds a table of ```family``` prefixes from ```dump``` output. Values
+/// are decoded as untrusted terms, so a corrupted dump cannot produce invalid
+/// terms, into the envs they are kept in.
+pub fn load(bytes: &[u8], family: AddrFamily) -> Result<(Table, TableOptions), Atom> {
+    if bytes.len() < MAGIC.len() + 2 + 1 + 8 + 4 || &bytes[..4] != MAGIC {
+        return Err(atoms::invalid_dump());
+    }
+    let (bytes, checksum) = bytes.split_at(bytes.len() - 4);
+    let mut reader = Reader { bytes, pos: 4 };
+    let version = reader.u16()?;
+    if !(VERSION_1..=VERSION).contains(&version) {
+        return Err(atoms::unsupported_version());
+    }
+    if crc32(bytes) != u32::from_be_bytes(checksum.try_into().unwrap()) {
+        return Err(atoms::checksum_mismatch());
+    }
+    let flags = reader.u8()?;
+    let options = TableOptions {
+        strict: flags & OPTION_STRICT != 0,
+        prefix_format: if flags & OPTION_PREFIX_CIDR != 0 {
+            PrefixFormat::Cidr
+        } else {
+            PrefixFormat::Tuple
+        },
+        embedded_ipv4: flags >> OPTION_EMBEDDED_IPV4_SHIFT,
+    };
+
+    let mut table = Table::new();
+    for _ in 0..reader.u64()? {
+        let masklen = reader.u8()? as u32;
+        if masklen > family.octets() as u32 * 8 {
+            return Err(atoms::invalid_dump());
+        }
+        let nibbles = nibbles::from_bytes(reader.take(masklen.div_ceil(8) as usize)?, masklen);
+        let kind = if version == VERSION_1 {
+            SLOT_VALUE
+        } else {
+            reader.u8()?
+        };
+        let slot = match kind {
+            SLOT_VALUE => Slot::Value(reader.term()?),
+            SLOT_ROUTES => {
+                let count = reader.u32()?;
+                if count == 0 {
+                    return Err(atoms::invalid_dump());
+                }
+                let mut routes = Vec::new();
+                for _ in 0..count {
+                    routes.push(Route {
+                        source: reader.term()?,
+                        distance: reader.u32()?,
+                        metric: reader.u32()?,
+                        value: reader.term()?,
+                    });
+                }
+                Slot::Routes(routes)
+            }
+            SLOT_ECMP => {
+                let count = reader.u32()?;
+                if count == 0 {
+                    return Err(atoms::invalid_dump());
+                }
+                let mut members = Vec::new();
+                for _ in 0..count {
+                    members.push(Member::new(reader.term()?));
+                }
+                Slot::Ecmp(members)
+            }
+            _ => return Err(atoms::invalid_dump()),
+        };
+        if table.insert_slot(&nibbles, masklen, slot).is_some() {
+            return Err(atoms::invalid_dump());
+        }
+    }
+    if reader.pos != bytes.len() {
+        return Err(atoms::invalid_dump());
+    }
+    Ok((table, options))
+}
+
+fn write_term(out: &mut Vec<u8>, term: &OwnedTermSlot) {
+    let bytes = term.to_bytes();
+    out.extend_from_slice(&(bytes.len() as u32).to_be_bytes());
+    out.extend_from_slice(&bytes);
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], Atom> {
+        let end = self.pos.checked_add(n).ok_or(atoms::invalid_dump())?;
+        let slice = self.bytes.get(self.pos..end).ok_or(atoms::invalid_dump())?;
+        self.pos = end;
+        Ok(slice)
+    }
+
+    fn u8(&mut self) -> Result<u8, Atom> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, Atom> {
+        Ok(u16::from_be_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    fn u32(&mut self) -> Result<u32, Atom> {
+        Ok(u32::from_be_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> Result<u64, Atom> {
+        Ok(u64::from_be_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    fn term(&mut self) -> Result<OwnedTermSlot, Atom> {
+        let len = self.u32()? as usize;
+        OwnedTermSlot::from_bytes(self.take(len)?).ok_or(atoms::invalid_dump())
+    }
+}
+
+/// CRC-32 (IEEE 802.3), as computed by ```:erlang.crc32/1```.
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for byte in bytes {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = (crc >> 1) ^ (0xedb8_8320 & (!(crc & 1)).wrapping_add(1));
+        }
+    }
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn crc32_check_value() {
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+        assert_eq!(crc32(b""), 0);
+    }
+}
@@ -0,0 +1,178 @@
+use crate::tree_bitmap::TreeBitmap;
+use rustler::NifUnitEnum;
+
+/// The value kept for a prefix held by both tables: the left one, the right
+/// one, or ```{left, right}```.
+#[derive(NifUnitEnum, Clone, Copy)]
+pub enum Merge {
+    Left,
+    Right,
+    Pair,
+}
+
+/// Prefixes of both trees. Prefixes held by both get ```merge``` of their
+/// values.
+pub fn union<T: Clone>(
+    left: &TreeBitmap<T>,
+    right: &TreeBitmap<T>,
+    merge: &mut dyn FnMut(&T, &T) -> T,
+) -> TreeBitmap<T> {
+    let mut out = TreeBitmap::new();
+    left.walk_pair(right, (), &mut |_, pos| {
+        let value = match (pos.left, pos.right) {
+            (Some(left), Some(right)) => merge(left, right),
+            (Some(value), None) | (None, Some(value)) => value.clone(),
+            (None, None) => return Some(()).filter(|_| pos.left_below || pos.right_below),
+        };
+        out.insert(pos.nibbles, pos.masklen, value);
+        Some(()).filter(|_| pos.left_below || pos.right_below)
+    });
+    out
+}
+
+/// The address space covered by both trees: prefixes of either tree covered
+/// by a prefix of the other. Each gets ```merge``` of the values both trees
+/// match it with, so a lookup in the result matches what lookups in both
+/// trees do.
+pub fn intersection<T: Clone>(
+    left: &TreeBitmap<T>,
+    right: &TreeBitmap<T>,
+    merge: &mut dyn FnMut(&T, &T) -> T,
+) -> TreeBitmap<T> {
+    let mut out = TreeBitmap::new();
+    left.walk_pair(
+        right,
+        (None, None),
+        &mut |(left_cover, right_cover), pos| {
+            let left_cover = pos.left.or(left_cover);
+            let right_cover = pos.right.or(right_cover);
+            if let (Some(left), Some(right)) = (left_cover, right_cover) {
+                if pos.left.is_some() || pos.right.is_some() {
+                    out.insert(pos.nibbles, pos.masklen, merge(left, right));
+                }
+            }
+            let left_goes_on = pos.left_below || left_cover.is_some();
+            let right_goes_on = pos.right_below || right_cover.is_some();
+            Some((left_cover, right_cover))
+                .filter(|_| left_goes_on && right_goes_on && (pos.left_below || pos.right_below))
+        },
+    );
+    out
+}
+
+/// The address space covered by ```left``` but not by ```right```, with the
+/// values of ```left```. Prefixes of ```left``` containing prefixes of
+/// ```right``` are split into the fewest prefixes around them.
+pub fn difference<T: Clone, U>(left: &TreeBitmap<T>, right: &TreeBitmap<U>) -> TreeBitmap<T> {
+    let mut out = TreeBitmap::new();
+    // the value covering the position, and whether it still has to be added
+    // for the parts of the position ```right``` does not cover
+    left.walk_pair(right, (None, false), &mut |(cover, split), pos| {
+        if pos.right.is_some() {
+            return None;
+        }
+        let (cover, split) = match pos.left {
+            Some(value) => (Some(value), true),
+            None => (cover, split),
+        };
+        if !pos.right_below {
+            if let (Some(value), true) = (cover, split) {
+                out.insert(pos.nibbles, pos.masklen, value.clone());
+            }
+            return Some((cover, false)).filter(|_| pos.left_below);
+        }
+        Some((cover, split)).filter(|_| pos.left_below || cover.is_some())
+    });
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tree(prefixes: &[(&[u8], u32, u32)]) -> TreeBitmap<u32> {
+        let mut tree = TreeBitmap::new();
+        for (nibbles, masklen, value) in prefixes {
+            tree.insert(nibbles, *masklen, *value);
+        }
+        tree
+    }
+
+    fn list(tree: &TreeBitmap<u32>) -> Vec<(Vec<u8>, u32, u32)> {
+        let mut list: Vec<(Vec<u8>, u32, u32)> = tree
+            .iter()
+            .map(|(nibbles, masklen, value)| {
+                let mut nibbles = nibbles;
+                nibbles.resize(8, 0);
+                (nibbles, masklen, *value)
+            })
+            .collect();
+        list.sort();
+        list
+    }
+
+    fn prefix(nibbles: &[u8], masklen: u32, value: u32) -> (Vec<u8>, u32, u32) {
+        let mut nibbles = nibbles.to_vec();
+        nibbles.resize(8, 0);
+        (nibbles, masklen, value)
+    }
+
+    #[test]
+    fn union_merges_shared_prefixes() {
+        let left = tree(&[(&[0, 10], 8, 1), (&[0, 10, 0, 1], 16, 2)]);
+        let right = tree(&[(&[0, 10], 8, 10), (&[12, 0, 10, 8], 14, 20), (&[], 0, 30)]);
+        assert_eq!(
+            list(&union(&left, &right, &mut |l, r| l * 100 + r)),
+            vec![
+                prefix(&[], 0, 30),
+                prefix(&[0, 10], 8, 110),
+                prefix(&[0, 10, 0, 1], 16, 2),
+                prefix(&[12, 0, 10, 8], 14, 20),
+            ]
+        );
+    }
+
+    #[test]
+    fn intersection_of_address_space() {
+        // 10.0.0.0/8 and 10.1.0.0/16 against 10.0.0.0/15 and 192.168.0.0/16
+        let left = tree(&[(&[0, 10], 8, 1), (&[0, 10, 0, 1], 16, 2)]);
+        let right = tree(&[(&[0, 10, 0, 0], 15, 10), (&[12, 0, 10, 8], 16, 20)]);
+        assert_eq!(
+            list(&intersection(&left, &right, &mut |l, r| l * 100 + r)),
+            vec![
+                prefix(&[0, 10, 0, 0], 15, 110),
+                prefix(&[0, 10, 0, 1], 16, 210)
+            ]
+        );
+        let empty: TreeBitmap<u32> = TreeBitmap::new();
+        assert_eq!(intersection(&left, &empty, &mut |l, _| *l).len(), 0);
+        // prefixes shared by both, across node boundaries
+        let right = tree(&[(&[0, 10, 0, 1], 16, 3), (&[], 0, 4)]);
+        assert_eq!(
+            list(&intersection(&left, &right, &mut |l, r| l * 100 + r)),
+            vec![prefix(&[0, 10], 8, 104), prefix(&[0, 10, 0, 1], 16, 203)]
+        );
+    }
+
+    #[test]
+    fn difference_splits_around_holes() {
+        // 10.0.0.0/8 and 10.2.1.0/24 minus 10.0.0.0/15
+        let left = tree(&[(&[0, 10], 8, 1), (&[0, 10, 0, 2, 0, 1], 24, 2)]);
+        let right = tree(&[(&[0, 10, 0, 0], 15, 10)]);
+        let expected = vec![
+            prefix(&[0, 10, 0, 2], 15, 1),
+            prefix(&[0, 10, 0, 2, 0, 1], 24, 2),
+            prefix(&[0, 10, 0, 4], 14, 1),
+            prefix(&[0, 10, 0, 8], 13, 1),
+            prefix(&[0, 10, 1, 0], 12, 1),
+            prefix(&[0, 10, 2, 0], 11, 1),
+            prefix(&[0, 10, 4, 0], 10, 1),
+            prefix(&[0, 10, 8, 0], 9, 1),
+        ];
+        assert_eq!(list(&difference(&left, &right)), expected);
+
+        let empty: TreeBitmap<u32> = TreeBitmap::new();
+        assert_eq!(list(&difference(&left, &empty)), list(&left));
+        assert_eq!(difference(&left, &tree(&[(&[], 0, 0)])).len(), 0);
+    }
+}
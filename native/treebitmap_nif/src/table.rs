@@ -0,0 +1,319 @@
+use crate::addrs::{AddrFamily, AddrTuple, Ipv4Embedding, MacAddr, Maskable, TupleV4};
+use crate::nibbles;
+use crate::tree_bitmap::TreeBitmap;
+use crate::value::{OwnedTermSlot, Route, Slot};
+use rustler::{
+    types::tuple::make_tuple, Atom, Decoder, Encoder, Env, Error, NifResult, NifUnitEnum, Term,
+};
+
+mod atoms {
+    rustler::atoms! {
+        strict,
+        prefix_format,
+        embedded_ipv4,
+        invalid_masklen,
+        host_bits_set
+    }
+}
+
+/// Longest bitstring prefix, so they fit the dump format like IPv6 ones.
+pub const MAX_KEY_BITS: u32 = 128;
+
+/// How prefixes are returned: as ```{:inet4, ...}``` / ```{:inet6, ...}```
+/// records, or as canonical CIDR strings such as ```"10.0.0.0/8"```.
+#[derive(NifUnitEnum, Default, Clone, Copy, PartialEq)]
+pub enum PrefixFormat {
+    #[default]
+    Tuple,
+    Cidr,
+}
+
+/// Table behaviour, decoded from a keyword list.
+///
+/// + ```strict```: reject prefixes with bits set past the mask length.
+/// + ```prefix_format```: see ```PrefixFormat```.
+/// + ```embedded_ipv4```: the ```Ipv4Embedding```s IPv6 addresses are looked
+///   up in the IPv4 table with, as a bit set of their indices in
+///   ```Ipv4Embedding::ALL```.
+#[derive(Default, Clone, Copy)]
+pub struct TableOptions {
+    pub strict: bool,
+    pub prefix_format: PrefixFormat,
+    pub embedded_ipv4: u8,
+}
+
+impl<'a> Decoder<'a> for TableOptions {
+    fn decode(term: Term<'a>) -> NifResult<Self> {
+        let mut options = TableOptions::default();
+        for (key, value) in term.decode::<Vec<(Atom, Term)>>()? {
+            if key == atoms::strict() {
+                options.strict = value.decode()?;
+            } else if key == atoms::prefix_format() {
+                options.prefix_format = value.decode()?;
+            } else if key == atoms::embedded_ipv4() {
+                options.embedded_ipv4 = value
+                    .decode::<Vec<Ipv4Embedding>>()?
+                    .into_iter()
+                    .fold(0, |set, embedding| set | 1 << embedding as u8);
+            } else {
+                return Err(Error::BadArg);
+            }
+        }
+        Ok(options)
+    }
+}
+
+impl TableOptions {
+    /// Returns ```ip/masklen``` with the host bits cleared, or why a table
+    /// with these options does not accept it as a prefix.
+    pub fn check_prefix(&self, ip: AddrTuple, masklen: u32) -> Result<AddrTuple, Atom> {
+        if masklen > ip.max_masklen() {
+            return Err(atoms::invalid_masklen());
+        }
+        let prefix = ip.mask(masklen);
+        if self.strict && prefix != ip {
+            return Err(atoms::host_bits_set());
+        }
+        Ok(prefix)
+    }
+
+    /// Returns the IPv4 address ```ip``` embeds in one of the ways enabled by
+    /// ```embedded_ipv4```, and how.
+    pub fn extract_ipv4(&self, ip: AddrTuple) -> Option<(TupleV4, Ipv4Embedding)> {
+        let ip = match ip {
+            AddrTuple::V6(ip) => ip,
+            AddrTuple::V4(_) => return None,
+        };
+        Ipv4Embedding::ALL
+            .iter()
+            .filter(|embedding| self.embedded_ipv4 & 1 << **embedding as u8 != 0)
+            .find_map(|embedding| Some((embedding.extract(ip)?, *embedding)))
+    }
+
+    /// Same as ```check_prefix```, for MAC address prefixes.
+    pub fn check_mac(&self, mac: MacAddr, masklen: u32) -> Result<MacAddr, Atom> {
+        if masklen > mac.max_masklen() {
+            return Err(atoms::invalid_masklen());
+        }
+        let prefix = mac.mask(masklen);
+        if self.strict && prefix != mac {
+            return Err(atoms::host_bits_set());
+        }
+        Ok(prefix)
+    }
+
+    /// Returns the nibbles of the first ```masklen``` bits of ```key```, or why
+    /// a table with these options does not accept it as a prefix. Bitstring
+    /// prefixes are at most ```MAX_KEY_BITS``` long.
+    pub fn check_bits(&self, key: &[u8], masklen: u32) -> Result<Vec<u8>, Atom> {
+        if masklen > MAX_KEY_BITS || masklen as usize > key.len() * 8 {
+            return Err(atoms::invalid_masklen());
+        }
+        let bytes = &key[..masklen.div_ceil(8) as usize];
+        let nibbles = nibbles::from_bytes(bytes, masklen);
+        if self.strict
+            && (key[bytes.len()..].iter().any(|byte| *byte != 0)
+                || nibbles::to_bytes(&nibbles, masklen) != bytes)
+        {
+            return Err(atoms::host_bits_set());
+        }
+        Ok(nibbles)
+    }
+
+    /// Encodes a prefix in the format chosen by ```prefix_format```.
+    pub fn encode_prefix<'a>(&self, env: Env<'a>, prefix: AddrTuple, masklen: u32) -> Term<'a> {
+        match self.prefix_format {
+            PrefixFormat::Tuple => prefix.encode(env),
+            PrefixFormat::Cidr => prefix.to_cidr(masklen).encode(env),
+        }
+    }
+
+    /// Encodes ```{prefix, masklen, value}```.
+    pub fn encode_route<'a>(
+        &self,
+        env: Env<'a>,
+        (prefix, masklen, value): (AddrTuple, u32, &Slot),
+    ) -> Term<'a> {
+        make_tuple(
+            env,
+            &[
+                self.encode_prefix(env, prefix, masklen),
+                masklen.encode(env),
+                value.encode(env),
+            ],
+        )
+    }
+}
+
+/// A ```TreeBitmap``` of Erlang terms, with the bookkeeping shared by all NIFs.
+#[derive(Clone)]
+pub struct Table {
+    pub tree: TreeBitmap<Slot>,
+    /// Bumped whenever a prefix is added or removed, see ```CursorResource```.
+    pub version: u64,
+    /// Bytes used by the stored terms.
+    term_bytes: usize,
+}
+
+impl Table {
+    pub fn new() -> Self {
+        Self::from_tree(TreeBitmap::new())
+    }
+
+    pub fn with_capacity(n: usize) -> Self {
+        Self::from_tree(TreeBitmap::with_capacity(n))
+    }
+
+    pub fn from_tree(tree: TreeBitmap<Slot>) -> Self {
+        let term_bytes = tree.iter().map(|(_, _, value)| value.size()).sum();
+        Table {
+            tree,
+            version: 0,
+            term_bytes,
+        }
+    }
+
+    /// Sets the value of a prefix, replacing its value or candidate routes.
+    pub fn insert(&mut self, nibbles: &[u8], masklen: u32, value: OwnedTermSlot) -> Option<Slot> {
+        self.insert_slot(nibbles, masklen, Slot::Value(value))
+    }
+
+    pub fn insert_slot(&mut self, nibbles: &[u8], masklen: u32, slot: Slot) -> Option<Slot> {
+        self.term_bytes += slot.size();
+        let prev = self.tree.insert(nibbles, masklen, slot);
+        match prev {
+            Some(ref prev) => self.term_bytes -= prev.size(),
+            None => self.version += 1,
+        }
+        prev
+    }
+
+    /// Adds a candidate route to a prefix, replacing the route from the same
+    /// source if any, which is returned. A plain value set by ```insert``` is
+    /// replaced by the route.
+    pub fn add_route(&mut self, nibbles: &[u8], masklen: u32, route: Route) -> Option<Route> {
+        let routes = match self.tree.exact_match_mut(nibbles, masklen) {
+            Some(Slot::Routes(routes)) => routes,
+            _ => {
+                self.insert_slot(nibbles, masklen, Slot::Routes(vec![route]));
+                return None;
+            }
+        };
+        self.term_bytes += route.size();
+        let prev = routes
+            .iter()
+            .position(|r| r.source == route.source)
+            .map(|i| routes.remove(i));
+        if let Some(ref prev) = prev {
+            self.term_bytes -= prev.size();
+        }
+        let rank = (route.distance, route.metric);
+        let at = routes
+            .iter()
+            .position(|r| (r.distance, r.metric) > rank)
+            .unwrap_or(routes.len());
+        routes.insert(at, route);
+        prev
+    }
+
+    /// Removes the candidate route from ```source```, and the prefix along
+    /// with its last route.
+    pub fn remove_route(
+        &mut self,
+        nibbles: &[u8],
+        masklen: u32,
+        source: &OwnedTermSlot,
+    ) -> Option<Route> {
+        let routes = match self.tree.exact_match_mut(nibbles, masklen) {
+            Some(Slot::Routes(routes)) => routes,
+            _ => return None,
+        };
+        let route = routes.remove(routes.iter().position(|r| &r.source == source)?);
+        self.term_bytes -= route.size();
+        if routes.is_empty() {
+            self.remove(nibbles, masklen);
+        }
+        Some(route)
+    }
+
+    pub fn remove(&mut self, nibbles: &[u8], masklen: u32) -> Option<Slot> {
+        let prev = self.tree.remove(nibbles, masklen);
+        if let Some(ref prev) = prev {
+            self.term_bytes -= prev.size();
+            self.version += 1;
+        }
+        prev
+    }
+
+    /// The addresses of ```family``` the table covers, as ranges of
+    /// consecutive addresses with the same longest match value, in order.
+    pub fn ranges(&self, family: AddrFamily) -> Vec<(AddrTuple, AddrTuple, &Slot)> {
+        let mut ranges: Vec<(u128, u128, &Slot)> = Vec::new();
+        // the last addresses and values of the prefixes enclosing the current
+        // one, innermost last, and the first address not in ```ranges``` yet
+        let mut enclosing: Vec<(u128, &Slot)> = Vec::new();
+        let mut next = 0;
+        // the sweep needs the prefixes by address, a prefix before the ones
+        // it contains, which is the order of ```iter```
+        for (nibbles, masklen, value) in self.tree.iter() {
+            let prefix = AddrTuple::from_nibbles(family, &nibbles);
+            let (first, last) = (prefix.to_bits(), prefix.last(masklen).to_bits());
+            while let Some(&(end, value)) = enclosing.last().filter(|(end, _)| *end < first) {
+                extend_ranges(&mut ranges, next, end, value);
+                next = end + 1;
+                enclosing.pop();
+            }
+            match enclosing.last() {
+                Some(&(_, value)) if next < first => {
+                    extend_ranges(&mut ranges, next, first - 1, value)
+                }
+                _ => (),
+            }
+            next = first;
+            enclosing.push((last, value));
+        }
+        while let Some((end, value)) = enclosing.pop() {
+            extend_ranges(&mut ranges, next, end, value);
+            next = match end.checked_add(1) {
+                Some(next) => next,
+                None => break,
+            };
+        }
+        ranges
+            .into_iter()
+            .map(|(first, last, value)| {
+                (
+                    AddrTuple::from_bits(family, first),
+                    AddrTuple::from_bits(family, last),
+                    value,
+                )
+            })
+            .collect()
+    }
+
+    /// Returns the bytes used by trie nodes, and by results including the
+    /// stored terms.
+    pub fn mem_usage(&self) -> (usize, usize) {
+        let (nodes, results) = self.tree.mem_usage();
+        (nodes, results + self.term_bytes)
+    }
+}
+
+/// Appends ```first..=last```, merged into the last range when it follows it
+/// with the same value. Empty ranges are skipped.
+fn extend_ranges<'a>(
+    ranges: &mut Vec<(u128, u128, &'a Slot)>,
+    first: u128,
+    last: u128,
+    value: &'a Slot,
+) {
+    if first > last {
+        return;
+    }
+    match ranges.last_mut() {
+        Some((_, prev_last, prev)) if prev_last.checked_add(1) == Some(first) && *prev == value => {
+            *prev_last = last
+        }
+        _ => ranges.push((first, last, value)),
+    }
+}
@@ -226,6 +226,36 @@ impl<T: Sized> BucketVec<T> {
     pub fn mem_usage(&self) -> usize {
         (mem::size_of::<T>() * self.buf.cap()) + (self.freelist.capacity() * mem::size_of::<u32>())
     }
+
+    /// Returns a ```BucketVec``` with the same capacity, slots and free list,
+    /// whose entries are uninitialized and must be set before being read.
+    fn clone_layout(&self) -> BucketVec<T> {
+        let buf = RawVec::with_capacity(self.buf.cap());
+        if cfg!(debug_assertions) {
+            unsafe {
+                ptr::write_bytes(buf.ptr(), 0, self.len as usize);
+            }
+        }
+        let mut freelist = Vec::with_capacity(self.freelist.capacity());
+        freelist.extend_from_slice(&self.freelist);
+        BucketVec {
+            buf,
+            freelist,
+            len: self.len,
+            spacing: self.spacing,
+        }
+    }
+}
+
+impl<T: Copy> Clone for BucketVec<T> {
+    /// Copies the whole buffer, including unused entries.
+    fn clone(&self) -> Self {
+        let copy = self.clone_layout();
+        unsafe {
+            ptr::copy_nonoverlapping(self.buf.ptr(), copy.buf.ptr(), self.len as usize);
+        }
+        copy
+    }
 }
 
 static LEN2BUCKET: [u32; 33] = [
@@ -262,6 +292,14 @@ pub struct AllocatorHandle {
     pub offset: u32,
 }
 
+impl<T: Copy> Clone for Allocator<T> {
+    fn clone(&self) -> Self {
+        Allocator {
+            buckets: self.buckets.clone(),
+        }
+    }
+}
+
 impl AllocatorHandle {
     #[inline]
     pub fn generate(len: u32, offset: u32) -> AllocatorHandle {
@@ -315,6 +353,15 @@ impl<T: Sized> Allocator<T> {
         total
     }
 
+    /// Returns an ```Allocator``` with the same buckets and free slots, so
+    /// existing handles stay valid, but no entries: they must be set before
+    /// being read.
+    pub fn clone_layout(&self) -> Allocator<T> {
+        Allocator {
+            buckets: std::array::from_fn(|i| self.buckets[i].clone_layout()),
+        }
+    }
+
     // pub fn shrink_to_fit(&mut self) {
     //    for buckvec in &mut self.buckets {
     //        buckvec.shrink_to_fit();
@@ -452,7 +499,7 @@ mod tests {
             bucket.set_slot_entry(slot, i, 1000 + i);
         }
         for i in 0..spacing {
-            let mut x = bucket.get_slot_entry_mut(slot, i);
+            let x = bucket.get_slot_entry_mut(slot, i);
             *x += 1;
         }
         for i in 0..spacing {
@@ -474,6 +521,20 @@ mod tests {
         assert_eq!(*bucket.get_slot_entry(slot, spacing - 2), 2);
     }
 
+    #[test]
+    fn allocator_clone() {
+        let mut alloc = Allocator::<u32>::new();
+        let mut hdl = alloc.alloc(0);
+        for i in 0..20 {
+            alloc.insert(&mut hdl, 0, 1000 + i);
+        }
+        let copy = alloc.clone();
+        alloc.set(&hdl, 0, 0);
+        for i in 0..20 {
+            assert_eq!(*copy.get(&hdl, i), 1019 - i);
+        }
+    }
+
     #[test]
     fn allocator_new() {
         Allocator::<u32>::new();
@@ -539,7 +600,7 @@ mod tests {
         }
 
         for i in 0..32 {
-            let mut x = alloc.get_mut(&hdl, i);
+            let x = alloc.get_mut(&hdl, i);
             *x += 1;
         }
 
@@ -152,6 +152,53 @@ impl<T: Sized> TreeBitmap<T> {
         })
     }
 
+    /// All prefixes matching ```nibbles```, from the least to the most specific.
+    pub fn matches(&self, nibbles: &[u8]) -> Vec<(u32, &T)> {
+        let mut cur_hdl = self.root_handle();
+        let mut cur_index = 0;
+        let mut bits_searched = 0;
+        let mut ret = Vec::new();
+
+        for loop_count in 0.. {
+            let nibble = if loop_count < nibbles.len() {
+                nibbles[loop_count]
+            } else {
+                0
+            };
+
+            let cur_node = *self.trienodes.get(&cur_hdl, cur_index);
+            let match_mask = node::MATCH_MASKS[nibble as usize];
+
+            // bits are ordered by prefix length, shortest first
+            let mut result_match = cur_node.internal() & match_mask;
+            while result_match > 0 {
+                let bit_index = result_match.leading_zeros();
+                let result_index = match bit_index {
+                    0 => 0,
+                    _ => (cur_node.internal() >> (32 - bit_index)).count_ones(),
+                };
+                let bits_matched = bits_searched + node::BIT_MATCH[bit_index as usize];
+                let value = self.results.get(&cur_node.result_handle(), result_index);
+                ret.push((bits_matched, value));
+                result_match &= !(node::MSB >> bit_index);
+            }
+
+            if cur_node.is_endnode() {
+                break;
+            }
+            match cur_node.match_external(match_mask) {
+                MatchResult::Chase(child_hdl, child_index) => {
+                    bits_searched += 4;
+                    cur_hdl = child_hdl;
+                    cur_index = child_index;
+                }
+                MatchResult::None => break,
+                _ => unreachable!(),
+            }
+        }
+        ret
+    }
+
     pub fn insert(&mut self, nibbles: &[u8], masklen: u32, value: T) -> Option<T> {
         let mut cur_hdl = self.root_handle();
         let mut cur_index = 0;
@@ -258,6 +305,18 @@ impl<T: Sized> TreeBitmap<T> {
     }
 
     pub fn exact_match(&self, nibbles: &[u8], masklen: u32) -> Option<&T> {
+        let (result_hdl, result_index) = self.find_exact(nibbles, masklen)?;
+        Some(self.results.get(&result_hdl, result_index))
+    }
+
+    /// Same as ```exact_match```, for updating the value in place.
+    pub fn exact_match_mut(&mut self, nibbles: &[u8], masklen: u32) -> Option<&mut T> {
+        let (result_hdl, result_index) = self.find_exact(nibbles, masklen)?;
+        Some(self.results.get_mut(&result_hdl, result_index))
+    }
+
+    // handle and index of the result stored for the exact prefix
+    fn find_exact(&self, nibbles: &[u8], masklen: u32) -> Option<(AllocatorHandle, u32)> {
         let mut cur_hdl = self.root_handle();
         let mut cur_index = 0;
         let mut bits_left = masklen;
@@ -279,7 +338,7 @@ impl<T: Sized> TreeBitmap<T> {
             if reached_final_node {
                 match cur_node.match_internal(bitmap) {
                     MatchResult::Match(result_hdl, result_index, _) => {
-                        return Some(self.results.get(&result_hdl, result_index));
+                        return Some((result_hdl, result_index));
                     }
                     _ => return None,
                 }
@@ -353,7 +412,9 @@ impl<T: Sized> TreeBitmap<T> {
         }
     }
 
-    pub fn iter(&self) -> Iter<T> {
+    /// Iterates in prefix order: by address, a prefix before the ones it
+    /// contains.
+    pub fn iter(&self) -> Iter<'_, T> {
         let root_hdl = self.root_handle();
         let root_node = *self.trienodes.get(&root_hdl, 0);
         Iter {
@@ -366,11 +427,11 @@ impl<T: Sized> TreeBitmap<T> {
         }
     }
 
-    pub fn iter_mut(&mut self) -> IterMut<T> {
+    /// Returns a ```Cursor``` positioned before the first entry.
+    pub fn cursor(&self) -> Cursor {
         let root_hdl = self.root_handle();
         let root_node = *self.trienodes.get(&root_hdl, 0);
-        IterMut {
-            inner: self,
+        Cursor {
             path: vec![PathElem {
                 node: root_node,
                 pos: 0,
@@ -378,6 +439,478 @@ impl<T: Sized> TreeBitmap<T> {
             nibbles: vec![0],
         }
     }
+
+    /// Iterates over ```nibbles/masklen``` and the prefixes it contains.
+    pub fn iter_more_specifics(&self, nibbles: &[u8], masklen: u32) -> MoreSpecifics<'_, T> {
+        let mut cur_hdl = self.root_handle();
+        let mut cur_index = 0;
+        // ancestors of the subtree are kept in the path as fully visited, so
+        // the cursor stops once it leaves the subtree
+        let mut path = Vec::new();
+        let mut path_nibbles = Vec::new();
+
+        for loop_count in 0..(masklen / 4) as usize {
+            let nibble = if loop_count < nibbles.len() {
+                nibbles[loop_count]
+            } else {
+                0
+            };
+            let cur_node = *self.trienodes.get(&cur_hdl, cur_index);
+            if cur_node.is_endnode() {
+                // the prefix, if present, is one of the results of this node
+                break;
+            }
+            match cur_node.match_external(node::MATCH_MASKS[nibble as usize]) {
+                MatchResult::Chase(child_hdl, child_index) => {
+                    path.push(PathElem {
+                        node: cur_node,
+                        pos: PREORDER_BITS.len(),
+                    });
+                    path_nibbles.push(nibble);
+                    cur_hdl = child_hdl;
+                    cur_index = child_index;
+                }
+                // results of a normal node are shorter than the prefix
+                _ => {
+                    return MoreSpecifics {
+                        inner: self,
+                        cursor: Cursor {
+                            path: Vec::new(),
+                            nibbles: Vec::new(),
+                        },
+                        nibbles: Vec::new(),
+                        masklen,
+                    };
+                }
+            }
+        }
+        path.push(PathElem {
+            node: *self.trienodes.get(&cur_hdl, cur_index),
+            pos: 0,
+        });
+        path_nibbles.push(0);
+
+        MoreSpecifics {
+            inner: self,
+            cursor: Cursor {
+                path,
+                nibbles: path_nibbles,
+            },
+            nibbles: nibbles.to_vec(),
+            masklen,
+        }
+    }
+}
+
+/// A prefix visited by ```walk_pair```: the values both trees hold for it,
+/// and whether they hold more specific prefixes.
+pub struct PairPosition<'p, 'a, T, U> {
+    pub nibbles: &'p [u8],
+    pub masklen: u32,
+    pub left: Option<&'a T>,
+    pub right: Option<&'a U>,
+    pub left_below: bool,
+    pub right_below: bool,
+}
+
+/// Called by ```walk_pair``` on each prefix, see there.
+pub type PairVisitor<'v, 'a, T, U, S> = dyn FnMut(S, PairPosition<'_, 'a, T, U>) -> Option<S> + 'v;
+
+impl<T: Sized> TreeBitmap<T> {
+    /// Walks ```self``` and ```other``` in lockstep over the binary trie of
+    /// prefixes, in prefix order: each prefix comes before its more specifics,
+    /// and the ```0``` half before the ```1``` half.
+    ///
+    /// ```visit``` gets the state of the enclosing prefix and returns the
+    /// state of the prefixes below, or ```None``` to skip them. Prefixes below
+    /// are visited even when neither tree holds any, so ```visit``` has to
+    /// stop the walk. Only the trie nodes holding prefixes of either tree are
+    /// read.
+    pub fn walk_pair<'a, U, S: Copy>(
+        &'a self,
+        other: &'a TreeBitmap<U>,
+        state: S,
+        visit: &mut PairVisitor<'_, 'a, T, U, S>,
+    ) {
+        let left = View::root(self);
+        let right = View::root(other);
+        let mut walk = PairWalk {
+            left: self,
+            right: other,
+            nibbles: vec![0],
+            visit,
+        };
+        walk.bit(left, right, 0, state);
+    }
+}
+
+/// A prefix held with different values by two trees, see ```diff```.
+pub struct Change<'a, T> {
+    pub nibbles: Vec<u8>,
+    pub masklen: u32,
+    pub old: Option<&'a T>,
+    pub new: Option<&'a T>,
+}
+
+impl<T: PartialEq> TreeBitmap<T> {
+    /// The prefixes only one of ```self``` and ```other``` holds, or both
+    /// hold with different values, in prefix order.
+    ///
+    /// Trie nodes with the same bitmaps in both trees are compared result by
+    /// result and child by child, so the unchanged parts of two similar trees
+    /// cost a few comparisons per node. Where the shapes differ the nodes are
+    /// walked prefix by prefix, as ```walk_pair``` does.
+    pub fn diff<'a>(&'a self, other: &'a TreeBitmap<T>) -> Vec<Change<'a, T>> {
+        let mut walk = DiffWalk {
+            old: self,
+            new: other,
+            nibbles: vec![0],
+            changes: Vec::new(),
+        };
+        walk.node(View::root(self), View::root(other));
+        // nodes of the same shape report their prefixes before the ones of
+        // their children
+        walk.changes
+            .sort_by(|a, b| (&a.nibbles, a.masklen).cmp(&(&b.nibbles, b.masklen)));
+        walk.changes
+    }
+}
+
+impl<T: Clone + PartialEq> TreeBitmap<T> {
+    /// A tree giving the same longest match as ```self``` for every address,
+    /// with fewer prefixes: sibling prefixes with the same value are merged
+    /// into their parent, and prefixes with the value of the prefix covering
+    /// them are dropped. Addresses ```self``` does not cover stay uncovered.
+    ///
+    /// A prefix is kept even when most of its more specifics hold another
+    /// value, so the result is not always the smallest possible one.
+    pub fn aggregate(&self) -> TreeBitmap<T> {
+        let mut walk = AggregateWalk {
+            trie: self,
+            nibbles: vec![0],
+            out: TreeBitmap::new(),
+        };
+        if let Some(Some(value)) = walk.bit(View::root(self), 0, None) {
+            walk.out.insert(&[0], 0, value.clone());
+        }
+        walk.out
+    }
+}
+
+/// A trie node as seen by ```walk_pair```: prefixes ending on a nibble
+/// boundary live either in the parent end node or as bit 0 of the child, so
+/// the former are presented as the root result of a missing child.
+struct View<'a, T> {
+    node: Option<Node>,
+    root: Option<&'a T>,
+}
+
+impl<T> Clone for View<'_, T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for View<'_, T> {}
+
+impl<'a, T> View<'a, T> {
+    fn root(trie: &'a TreeBitmap<T>) -> Self {
+        View {
+            node: Some(*trie.trienodes.get(&trie.root_handle(), 0)),
+            root: None,
+        }
+    }
+
+    fn result(&self, trie: &'a TreeBitmap<T>, bit: u32) -> Option<&'a T> {
+        if bit == 0 && self.root.is_some() {
+            return self.root;
+        }
+        let node = self.node?;
+        if node.internal() & (node::MSB >> bit) == 0 {
+            return None;
+        }
+        let index = match bit {
+            0 => 0,
+            _ => (node.internal() >> (32 - bit)).count_ones(),
+        };
+        Some(trie.results.get(&node.result_handle(), index))
+    }
+
+    /// Are there prefixes strictly below internal ```bit```?
+    fn below(&self, bit: u32) -> bool {
+        let node = match self.node {
+            Some(node) => node,
+            None => return false,
+        };
+        let (internal, children) = BELOW_MASKS[bit as usize];
+        let present = match node.is_endnode() {
+            true => node.internal() & node::EXT_MASK,
+            false => node.external(),
+        };
+        node.internal() & internal != 0 || present & children != 0
+    }
+
+    fn child(&self, trie: &'a TreeBitmap<T>, nibble: u8) -> Self {
+        let node = match self.node {
+            Some(node) => node,
+            None => {
+                return View {
+                    node: None,
+                    root: None,
+                }
+            }
+        };
+        if node.is_endnode() {
+            return View {
+                node: None,
+                root: self.result(trie, 16 + nibble as u32),
+            };
+        }
+        let bit = node::MSB >> (16 + nibble);
+        if node.external() & bit == 0 {
+            return View {
+                node: None,
+                root: None,
+            };
+        }
+        let index = (node.external() >> (16 - nibble)).count_ones();
+        View {
+            node: Some(*trie.trienodes.get(&node.child_handle(), index)),
+            root: None,
+        }
+    }
+}
+
+// for each internal bit 0 to 14, the bits of the internal bits and of the
+// children strictly below it
+static BELOW_MASKS: [(u32, u32); 15] = below_masks();
+
+const fn below_masks() -> [(u32, u32); 15] {
+    let mut masks = [(0, 0); 15];
+    let mut bit = 0;
+    while bit < 15 {
+        let mut internal = 0;
+        // descendants in heap order: bit i has 2i + 1 and 2i + 2 below it
+        let (mut first, mut last) = (2 * bit + 1, 2 * bit + 2);
+        while first < 15 {
+            let mut i = first;
+            while i <= last {
+                internal |= node::MSB >> i;
+                i += 1;
+            }
+            first = 2 * first + 1;
+            last = 2 * last + 2;
+        }
+        let len = node::BIT_MATCH[bit] as usize;
+        let pattern = bit + 1 - (1 << len);
+        let mut children = 0;
+        let mut nibble = pattern << (4 - len);
+        while nibble < (pattern + 1) << (4 - len) {
+            children |= node::MSB >> (16 + nibble);
+            nibble += 1;
+        }
+        masks[bit] = (internal, children);
+        bit += 1;
+    }
+    masks
+}
+
+struct PairWalk<'a, 'v, T, U, S> {
+    left: &'a TreeBitmap<T>,
+    right: &'a TreeBitmap<U>,
+    nibbles: Vec<u8>,
+    visit: &'v mut PairVisitor<'v, 'a, T, U, S>,
+}
+
+impl<'a, T, U, S: Copy> PairWalk<'a, '_, T, U, S> {
+    fn bit(&mut self, left: View<'a, T>, right: View<'a, U>, bit: u32, state: S) {
+        let len = node::BIT_MATCH[bit as usize];
+        let pattern = bit + 1 - (1 << len);
+        let depth = self.nibbles.len() - 1;
+        self.nibbles[depth] = (pattern << (4 - len)) as u8;
+        let position = PairPosition {
+            nibbles: &self.nibbles,
+            masklen: depth as u32 * 4 + len,
+            left: left.result(self.left, bit),
+            right: right.result(self.right, bit),
+            left_below: left.below(bit),
+            right_below: right.below(bit),
+        };
+        let state = match (self.visit)(state, position) {
+            Some(state) => state,
+            None => return,
+        };
+        if len < 3 {
+            self.bit(left, right, 2 * bit + 1, state);
+            self.bit(left, right, 2 * bit + 2, state);
+            return;
+        }
+        for nibble in [pattern << 1, pattern << 1 | 1] {
+            self.nibbles[depth] = nibble as u8;
+            self.nibbles.push(0);
+            let (left, right) = (
+                left.child(self.left, nibble as u8),
+                right.child(self.right, nibble as u8),
+            );
+            self.bit(left, right, 0, state);
+            self.nibbles.pop();
+        }
+    }
+}
+
+struct DiffWalk<'a, T> {
+    old: &'a TreeBitmap<T>,
+    new: &'a TreeBitmap<T>,
+    nibbles: Vec<u8>,
+    changes: Vec<Change<'a, T>>,
+}
+
+impl<'a, T: PartialEq> DiffWalk<'a, T> {
+    fn node(&mut self, old: View<'a, T>, new: View<'a, T>) {
+        let (old_node, new_node) = match (old.node, new.node) {
+            (Some(old_node), Some(new_node))
+                if old_node.is_endnode() == new_node.is_endnode()
+                    && old_node.internal() == new_node.internal()
+                    && old_node.external() == new_node.external() =>
+            {
+                (old_node, new_node)
+            }
+            _ => return self.bit(old, new, 0),
+        };
+        let depth = self.nibbles.len() - 1;
+        let internal = old_node.internal();
+        let bits = (0..32).filter(|bit| internal & (node::MSB >> bit) != 0);
+        for (index, bit) in bits.enumerate() {
+            let old_value = self
+                .old
+                .results
+                .get(&old_node.result_handle(), index as u32);
+            let new_value = self
+                .new
+                .results
+                .get(&new_node.result_handle(), index as u32);
+            if old_value == new_value {
+                continue;
+            }
+            let len = node::BIT_MATCH[bit as usize];
+            self.nibbles[depth] = match bit {
+                16..=31 => bit as u8 - 16,
+                _ => ((bit + 1 - (1 << len)) << (4 - len)) as u8,
+            };
+            self.change(depth as u32 * 4 + len, Some(old_value), Some(new_value));
+        }
+        for nibble in
+            (0..16).filter(|nibble| old_node.external() & (node::MSB >> (16 + nibble)) != 0)
+        {
+            self.nibbles[depth] = nibble;
+            self.nibbles.push(0);
+            self.node(old.child(self.old, nibble), new.child(self.new, nibble));
+            self.nibbles.pop();
+        }
+    }
+
+    fn bit(&mut self, old: View<'a, T>, new: View<'a, T>, bit: u32) {
+        let len = node::BIT_MATCH[bit as usize];
+        let pattern = bit + 1 - (1 << len);
+        let depth = self.nibbles.len() - 1;
+        self.nibbles[depth] = (pattern << (4 - len)) as u8;
+        let old_value = old.result(self.old, bit);
+        let new_value = new.result(self.new, bit);
+        if old_value != new_value {
+            self.change(depth as u32 * 4 + len, old_value, new_value);
+        }
+        if !old.below(bit) && !new.below(bit) {
+            return;
+        }
+        if len < 3 {
+            self.bit(old, new, 2 * bit + 1);
+            self.bit(old, new, 2 * bit + 2);
+            return;
+        }
+        for nibble in [pattern << 1, pattern << 1 | 1] {
+            self.nibbles[depth] = nibble as u8;
+            self.nibbles.push(0);
+            self.node(
+                old.child(self.old, nibble as u8),
+                new.child(self.new, nibble as u8),
+            );
+            self.nibbles.pop();
+        }
+    }
+
+    fn change(&mut self, masklen: u32, old: Option<&'a T>, new: Option<&'a T>) {
+        self.changes.push(Change {
+            nibbles: self.nibbles[..masklen.div_ceil(4) as usize].to_vec(),
+            masklen,
+            old,
+            new,
+        });
+    }
+}
+
+struct AggregateWalk<'a, T> {
+    trie: &'a TreeBitmap<T>,
+    nibbles: Vec<u8>,
+    out: TreeBitmap<T>,
+}
+
+impl<'a, T: Clone + PartialEq> AggregateWalk<'a, T> {
+    /// Aggregates the prefixes below internal ```bit``` of ```view```, where
+    /// ```cover``` is the value of the closest enclosing prefix. Returns the
+    /// value of the whole position when it is the same everywhere, leaving it
+    /// to the caller to add it; otherwise adds the prefixes it needs.
+    fn bit(&mut self, view: View<'a, T>, bit: u32, cover: Option<&'a T>) -> Option<Option<&'a T>> {
+        let len = node::BIT_MATCH[bit as usize];
+        let pattern = bit + 1 - (1 << len);
+        let depth = self.nibbles.len() - 1;
+        let value = view.result(self.trie, bit).or(cover);
+        if !view.below(bit) {
+            return Some(value);
+        }
+        let mut halves = [None, None];
+        for (side, half) in halves.iter_mut().enumerate() {
+            let pattern = pattern << 1 | side as u32;
+            *half = if len < 3 {
+                self.bit(view, 2 * bit + 1 + side as u32, value)
+            } else {
+                self.nibbles[depth] = pattern as u8;
+                self.nibbles.push(0);
+                let half = self.bit(view.child(self.trie, pattern as u8), 0, value);
+                self.nibbles.pop();
+                half
+            };
+        }
+        if let [Some(left), Some(right)] = halves {
+            if left == right {
+                return Some(left);
+            }
+        }
+        // a value differing from the cover is always a prefix of the trie,
+        // and uncovered addresses have no cover
+        if value != cover {
+            self.add(depth, pattern << (4 - len), depth as u32 * 4 + len, value);
+        }
+        for (side, half) in halves.iter().enumerate() {
+            if let Some(half) = *half {
+                if half != value {
+                    let pattern = pattern << 1 | side as u32;
+                    self.add(
+                        depth,
+                        pattern << (3 - len),
+                        depth as u32 * 4 + len + 1,
+                        half,
+                    );
+                }
+            }
+        }
+        None
+    }
+
+    fn add(&mut self, depth: usize, nibble: u32, masklen: u32, value: Option<&T>) {
+        self.nibbles[depth] = nibble as u8;
+        let value = value.expect("covered position").clone();
+        self.out.insert(&self.nibbles, masklen, value);
+    }
 }
 
 #[derive(Debug)]
@@ -392,12 +925,61 @@ pub struct Iter<'a, T: 'a> {
     nibbles: Vec<u8>,
 }
 
-pub struct IterMut<'a, T: 'a> {
-    inner: &'a mut TreeBitmap<T>,
+/// Iteration state that does not borrow the tree, so it can be kept between
+/// calls.
+///
+/// The path holds copies of trie nodes: any insert or remove on the tree may
+/// move them, after which the cursor must not be used with that tree again.
+pub struct Cursor {
     path: Vec<PathElem>,
     nibbles: Vec<u8>,
 }
 
+impl Cursor {
+    /// Advances the cursor on ```trie```, which must be the tree it was created
+    /// from and must not have been modified since.
+    pub fn next<'a, T>(&mut self, trie: &'a TreeBitmap<T>) -> Option<(Vec<u8>, u32, &'a T)> {
+        match next(trie, &mut self.path, &mut self.nibbles) {
+            Some((path, bits_matched, hdl, index)) => {
+                let value = trie.results.get(&hdl, index);
+                Some((path, bits_matched, value))
+            }
+            None => None,
+        }
+    }
+}
+
+pub struct MoreSpecifics<'a, T: 'a> {
+    inner: &'a TreeBitmap<T>,
+    cursor: Cursor,
+    nibbles: Vec<u8>,
+    masklen: u32,
+}
+
+impl<'a, T: 'a> Iterator for MoreSpecifics<'a, T> {
+    type Item = (Vec<u8>, u32, &'a T); //(nibbles, masklen, &T)
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let (path, bits_matched, value) = self.cursor.next(self.inner)?;
+            if bits_matched >= self.masklen && is_prefix_of(&self.nibbles, self.masklen, &path) {
+                return Some((path, bits_matched, value));
+            }
+        }
+    }
+}
+
+/// Are the first ```masklen``` bits of ```prefix``` and ```nibbles``` equal?
+fn is_prefix_of(prefix: &[u8], masklen: u32, nibbles: &[u8]) -> bool {
+    let nibble = |nibbles: &[u8], i: usize| *nibbles.get(i).unwrap_or(&0);
+    let full = (masklen / 4) as usize;
+    if (0..full).any(|i| nibble(prefix, i) != nibble(nibbles, i)) {
+        return false;
+    }
+    let rest = masklen % 4;
+    rest == 0 || (nibble(prefix, full) ^ nibble(nibbles, full)) >> (4 - rest) == 0
+}
+
 #[rustfmt::skip]
 static PREFIX_OF_BIT: [u8; 32] = [// 0       1       2      3        4       5       6       7
                                   0b0000, 0b0000, 0b1000, 0b0000, 0b0100, 0b1000, 0b1100, 0b0000,
@@ -408,35 +990,34 @@ static PREFIX_OF_BIT: [u8; 32] = [// 0       1       2      3        4       5
                                   // 24      25      26      27      28      29      30      31
                                   0b1000, 0b1001, 0b1010, 0b1011, 0b1100, 0b1101, 0b1110, 0b1111];
 
+// Bit positions of a node in prefix order: each prefix comes before the
+// prefixes it contains, and "0" halves before "1" halves.
+#[rustfmt::skip]
+static PREORDER_BITS: [usize; 31] = [0,
+                                     1, 3, 7, 16, 17, 8, 18, 19, 4, 9, 20, 21, 10, 22, 23,
+                                     2, 5, 11, 24, 25, 12, 26, 27, 6, 13, 28, 29, 14, 30, 31];
+
 fn next<T: Sized>(
     trie: &TreeBitmap<T>,
     path: &mut Vec<PathElem>,
     nibbles: &mut Vec<u8>,
 ) -> Option<(Vec<u8>, u32, AllocatorHandle, u32)> {
     loop {
-        let mut path_elem = match path.pop() {
-            Some(elem) => elem,
-            None => return None,
-        };
+        let mut path_elem = path.pop()?;
         let cur_node = path_elem.node;
-        let mut cur_pos = path_elem.pos;
         nibbles.pop();
-        // optim:
-        if cur_pos == 0 && cur_node.result_count() == 0 {
-            path_elem.pos = 16;
-            cur_pos = 16;
-        }
-        if path_elem.pos == 32 {
+        if path_elem.pos == PREORDER_BITS.len() {
             continue;
         }
-        let nibble = PREFIX_OF_BIT[path_elem.pos];
-        let bitmap = 1 << (31 - path_elem.pos);
+        let cur_bit = PREORDER_BITS[path_elem.pos];
+        let nibble = PREFIX_OF_BIT[cur_bit];
+        let bitmap = 1 << (31 - cur_bit);
 
         path_elem.pos += 1;
         nibbles.push(nibble);
         path.push(path_elem);
         // match internal
-        if cur_pos < 16 || cur_node.is_endnode() {
+        if cur_bit < 16 || cur_node.is_endnode() {
             let match_result = cur_node.match_internal(bitmap);
             if let MatchResult::Match(result_hdl, result_index, matching_bit) = match_result {
                 let bits_matched =
@@ -468,28 +1049,13 @@ impl<'a, T: 'a> Iterator for Iter<'a, T> {
     }
 }
 
-impl<'a, T: 'a> Iterator for IterMut<'a, T> {
-    type Item = (Vec<u8>, u32, &'a mut T); //(nibbles, masklen, &T)
-
-    fn next(&mut self) -> Option<Self::Item> {
-        match next(self.inner, &mut self.path, &mut self.nibbles) {
-            Some((path, bits_matched, hdl, index)) => unsafe {
-                let ptr: *mut T = self.inner.results.get_mut(&hdl, index);
-                let val_ref = &mut *ptr;
-                Some((path, bits_matched, val_ref))
-            },
-            None => None,
-        }
-    }
-}
-
 pub struct IntoIter<T> {
     inner: TreeBitmap<T>,
     path: Vec<PathElem>,
     nibbles: Vec<u8>,
 }
 
-impl<'a, T: 'a> Iterator for IntoIter<T> {
+impl<T> Iterator for IntoIter<T> {
     type Item = (Vec<u8>, u32, T); //(nibbles, masklen, T)
 
     fn next(&mut self) -> Option<Self::Item> {
@@ -529,6 +1095,31 @@ impl<T> Drop for IntoIter<T> {
     }
 }
 
+impl<T: Clone> Clone for TreeBitmap<T> {
+    /// Copies the trie nodes as they are and clones each result into the same
+    /// location, instead of inserting every prefix again.
+    fn clone(&self) -> Self {
+        let mut results = self.results.clone_layout();
+        let mut stack = vec![(self.root_handle(), 0)];
+        while let Some((hdl, index)) = stack.pop() {
+            let node = self.trienodes.get(&hdl, index);
+            let result_hdl = node.result_handle();
+            for i in 0..result_hdl.len {
+                results.set(&result_hdl, i, self.results.get(&result_hdl, i).clone());
+            }
+            for i in 0..node.child_count() {
+                stack.push((node.child_handle(), i));
+            }
+        }
+        TreeBitmap {
+            trienodes: self.trienodes.clone(),
+            results,
+            len: self.len,
+            should_drop: true,
+        }
+    }
+}
+
 impl<T> Drop for TreeBitmap<T> {
     fn drop(&mut self) {
         if self.should_drop {
@@ -588,6 +1179,239 @@ mod tests {
         assert_eq!(value, None);
     }
 
+    #[test]
+    fn exact_match_mut() {
+        let mut tbm: TreeBitmap<u32> = TreeBitmap::new();
+        let (nibbles_a, mask_a) = (&[0, 10, 0, 0, 0, 0, 0, 0], 8);
+        let (nibbles_b, mask_b) = (&[0, 10, 0, 10, 0, 10, 0, 0], 24);
+        tbm.insert(nibbles_a, mask_a, 1);
+        tbm.insert(nibbles_b, mask_b, 2);
+        *tbm.exact_match_mut(nibbles_b, mask_b).unwrap() += 40;
+        assert_eq!(tbm.exact_match(nibbles_b, mask_b), Some(&42));
+        assert_eq!(tbm.exact_match(nibbles_a, mask_a), Some(&1));
+        assert_eq!(tbm.exact_match_mut(nibbles_b, 16), None);
+    }
+
+    #[test]
+    fn matches() {
+        let mut tbm: TreeBitmap<u32> = TreeBitmap::new();
+        tbm.insert(&[0], 0, 1);
+        tbm.insert(&[0, 10], 8, 2);
+        tbm.insert(&[0, 10, 0], 10, 3);
+        tbm.insert(&[0, 10, 0, 10, 0, 10], 24, 4);
+        tbm.insert(&[0, 10, 0, 10, 1, 11], 24, 5);
+
+        let nibbles = &[0, 10, 0, 10, 0, 10, 0, 1];
+        assert_eq!(
+            tbm.matches(nibbles),
+            vec![(0, &1), (8, &2), (10, &3), (24, &4)]
+        );
+        assert_eq!(
+            tbm.matches(nibbles).last(),
+            tbm.longest_match(nibbles).as_ref()
+        );
+        assert_eq!(
+            tbm.matches(&[0, 10, 4, 0, 0, 0, 0, 0]),
+            vec![(0, &1), (8, &2)]
+        );
+        assert_eq!(tbm.matches(&[1, 0, 0, 0, 0, 0, 0, 0]), vec![(0, &1)]);
+
+        let tbm: TreeBitmap<u32> = TreeBitmap::new();
+        assert!(tbm.matches(&[0, 10, 0, 0, 0, 0, 0, 0]).is_empty());
+    }
+
+    #[test]
+    fn iter_more_specifics() {
+        let mut tbm: TreeBitmap<u32> = TreeBitmap::new();
+        tbm.insert(&[0], 0, 1);
+        tbm.insert(&[0, 10], 8, 2);
+        tbm.insert(&[0, 10, 0], 10, 3);
+        tbm.insert(&[0, 10, 4], 10, 4);
+        tbm.insert(&[0, 10, 0, 10, 0, 10], 24, 5);
+        tbm.insert(&[0, 10, 0, 10, 1, 11], 24, 6);
+        tbm.insert(&[0, 11, 0, 10, 1, 11], 24, 7);
+
+        let values = |nibbles: &[u8], masklen| -> Vec<u32> {
+            tbm.iter_more_specifics(nibbles, masklen)
+                .map(|(_, _, value)| *value)
+                .collect()
+        };
+        assert_eq!(values(&[0], 0), vec![1, 2, 3, 5, 6, 4, 7]);
+        assert_eq!(values(&[0, 10], 8), vec![2, 3, 5, 6, 4]);
+        assert_eq!(values(&[0, 10], 7), vec![2, 3, 5, 6, 4, 7]);
+        assert_eq!(values(&[0, 10, 0], 10), vec![3, 5, 6]);
+        assert_eq!(values(&[0, 10, 0, 10], 16), vec![5, 6]);
+        assert_eq!(values(&[0, 10, 0, 10, 1, 11], 24), vec![6]);
+        assert_eq!(values(&[0, 10, 0, 10, 1, 11], 28), vec![]);
+        assert_eq!(values(&[0, 12], 8), vec![]);
+        assert_eq!(values(&[1], 4), vec![]);
+
+        // prefixes found in an end node above the prefix depth
+        let mut endnode: TreeBitmap<u32> = TreeBitmap::new();
+        endnode.insert(&[0, 10], 8, 1);
+        endnode.insert(&[0, 11], 8, 2);
+        assert_eq!(endnode.iter_more_specifics(&[0, 10], 8).count(), 1);
+        assert_eq!(endnode.iter_more_specifics(&[0, 10], 7).count(), 2);
+        assert_eq!(endnode.iter_more_specifics(&[0, 10, 1], 12).count(), 0);
+
+        let (nibbles, masklen, _) = tbm.iter_more_specifics(&[0, 10, 0, 10], 16).next().unwrap();
+        assert_eq!((&nibbles[..6], masklen), (&[0, 10, 0, 10, 0, 10][..], 24));
+    }
+
+    #[test]
+    fn walk_pair() {
+        let mut left: TreeBitmap<u32> = TreeBitmap::new();
+        left.insert(&[0, 10], 8, 1);
+        left.insert(&[0, 10, 8], 9, 2);
+        // pushed down from the end node into a child
+        let mut right: TreeBitmap<u32> = TreeBitmap::new();
+        right.insert(&[0, 10], 8, 3);
+        right.insert(&[0, 10, 0, 1], 16, 4);
+
+        let mut visited = Vec::new();
+        left.walk_pair(&right, (), &mut |_, pos| {
+            if pos.left.is_some() || pos.right.is_some() {
+                visited.push((pos.masklen, pos.left.copied(), pos.right.copied()));
+            }
+            Some(()).filter(|_| pos.left_below || pos.right_below)
+        });
+        assert_eq!(
+            visited,
+            vec![
+                (8, Some(1), Some(3)),
+                (16, None, Some(4)),
+                (9, Some(2), None)
+            ]
+        );
+    }
+
+    #[test]
+    fn diff() {
+        let mut old: TreeBitmap<u32> = TreeBitmap::new();
+        for i in 0..=255u8 {
+            old.insert(&[i >> 4, i & 0xf, 0, 1], 16, i as u32);
+        }
+        old.insert(&[0, 10], 8, 8);
+        let mut new = old.clone();
+        new.insert(&[0, 11, 0, 1], 16, 1000);
+        new.remove(&[0, 12, 0, 1], 16);
+        new.insert(&[0], 0, 0);
+        // nodes of different shapes
+        new.insert(&[0, 10, 8], 9, 9);
+
+        let changes: Vec<_> = old
+            .diff(&new)
+            .into_iter()
+            .map(|change| {
+                (
+                    change.nibbles,
+                    change.masklen,
+                    change.old.copied(),
+                    change.new.copied(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            changes,
+            vec![
+                (vec![], 0, None, Some(0)),
+                (vec![0, 10, 8], 9, None, Some(9)),
+                (vec![0, 11, 0, 1], 16, Some(11), Some(1000)),
+                (vec![0, 12, 0, 1], 16, Some(12), None),
+            ]
+        );
+        assert!(new.diff(&new.clone()).is_empty());
+        assert_eq!(TreeBitmap::new().diff(&new).len(), new.len());
+    }
+
+    #[test]
+    fn aggregate() {
+        let mut tbm: TreeBitmap<u32> = TreeBitmap::new();
+        // 192.0.2.0/25 and 192.0.2.128/25 make 192.0.2.0/24
+        tbm.insert(&[12, 0, 0, 0, 0, 2, 0], 25, 1);
+        tbm.insert(&[12, 0, 0, 0, 0, 2, 8], 25, 1);
+        // 10.0.0.0/8 covers 10.1.0.0/16 with the same value
+        tbm.insert(&[0, 10], 8, 2);
+        tbm.insert(&[0, 10, 0, 1], 16, 2);
+        tbm.insert(&[0, 10, 0, 2], 16, 3);
+        // the /17 halves of a /16 with another value replace it
+        tbm.insert(&[0, 10, 0, 4], 16, 3);
+        tbm.insert(&[0, 10, 0, 4, 0], 17, 4);
+        tbm.insert(&[0, 10, 0, 4, 8], 17, 4);
+        // 198.51.100.0/25 has no sibling
+        tbm.insert(&[12, 6, 3, 3, 6, 4, 0], 25, 1);
+
+        let aggregated = tbm.aggregate();
+        let prefixes: Vec<_> = aggregated
+            .iter()
+            .map(|(nibbles, masklen, value)| {
+                (
+                    nibbles[..(masklen as usize).div_ceil(4)].to_vec(),
+                    masklen,
+                    *value,
+                )
+            })
+            .collect();
+        assert_eq!(
+            prefixes,
+            vec![
+                (vec![0, 10], 8, 2),
+                (vec![0, 10, 0, 2], 16, 3),
+                (vec![0, 10, 0, 4], 16, 4),
+                (vec![12, 0, 0, 0, 0, 2], 24, 1),
+                (vec![12, 6, 3, 3, 6, 4, 0], 25, 1),
+            ]
+        );
+        for address in [
+            [0, 10, 0, 1, 0, 0, 0, 1],
+            [0, 10, 0, 4, 8, 0, 0, 1],
+            [0, 11, 0, 0, 0, 0, 0, 1],
+            [12, 6, 3, 3, 6, 4, 8, 1],
+            [12, 0, 0, 0, 0, 2, 8, 1],
+        ] {
+            assert_eq!(
+                aggregated.longest_match(&address).map(|(_, v)| v),
+                tbm.longest_match(&address).map(|(_, v)| v)
+            );
+        }
+
+        let mut default: TreeBitmap<u32> = TreeBitmap::new();
+        default.insert(&[0], 1, 5);
+        default.insert(&[8], 1, 5);
+        assert_eq!(
+            default
+                .aggregate()
+                .iter()
+                .map(|(_, masklen, _)| masklen)
+                .collect::<Vec<_>>(),
+            vec![0]
+        );
+    }
+
+    #[test]
+    fn clone() {
+        let mut tbm: TreeBitmap<String> = TreeBitmap::new();
+        for i in 0..=255u8 {
+            tbm.insert(&[i >> 4, i & 0xf, 0, 1], 16, format!("{}", i));
+        }
+        tbm.insert(&[0], 0, "default".to_owned());
+        let copy = tbm.clone();
+        tbm.remove(&[0, 10, 0, 1], 16);
+        tbm.insert(&[0, 11, 0, 1], 16, "replaced".to_owned());
+        std::mem::drop(tbm);
+        assert_eq!(copy.len(), 257);
+        assert_eq!(copy.exact_match(&[0, 10, 0, 1], 16), Some(&"10".to_owned()));
+        assert_eq!(
+            copy.longest_match(&[0, 11, 0, 1]),
+            Some((16, &"11".to_owned()))
+        );
+        assert_eq!(
+            copy.longest_match(&[0, 11, 0, 2]),
+            Some((0, &"default".to_owned()))
+        );
+        assert_eq!(copy.iter().count(), 257);
+    }
+
     #[test]
     fn iter() {
         let mut tbm: TreeBitmap<u32> = TreeBitmap::new();
@@ -606,6 +1430,16 @@ mod tests {
         assert_eq!(iter.next().unwrap().2, &3);
         assert_eq!(iter.next().unwrap().2, &4);
         assert_eq!(iter.next(), None);
+
+        // prefix order, not the breadth-first order of the bits of a node
+        let mut tbm: TreeBitmap<u32> = TreeBitmap::new();
+        tbm.insert(&[8], 1, 1);
+        tbm.insert(&[0, 10], 8, 2);
+        tbm.insert(&[0], 2, 3);
+        tbm.insert(&[0, 10, 8], 9, 4);
+        tbm.insert(&[0, 11], 8, 5);
+        let order: Vec<(u32, u32)> = tbm.iter().map(|(_, masklen, v)| (masklen, *v)).collect();
+        assert_eq!(order, vec![(2, 3), (8, 2), (9, 4), (8, 5), (1, 1)]);
     }
 
     #[test]
@@ -628,6 +1462,27 @@ mod tests {
         assert_eq!(iter.next(), None);
     }
 
+    #[test]
+    fn cursor() {
+        let mut tbm: TreeBitmap<u32> = TreeBitmap::new();
+        tbm.insert(&[0], 0, 1);
+        tbm.insert(&[0, 10], 8, 2);
+        tbm.insert(&[0, 10, 0, 10, 0, 10], 24, 3);
+        tbm.insert(&[0, 10, 0, 10, 1, 11], 24, 4);
+
+        let mut cursor = tbm.cursor();
+        let (nibbles, masklen, value) = cursor.next(&tbm).unwrap();
+        assert_eq!((&nibbles[..1], masklen, value), (&[0][..], 0, &1));
+        let (nibbles, masklen, value) = cursor.next(&tbm).unwrap();
+        assert_eq!((&nibbles[..2], masklen, value), (&[0, 10][..], 8, &2));
+        // resuming after the cursor was set aside continues where it stopped
+        let mut resumed = cursor;
+        assert_eq!(resumed.next(&tbm).unwrap().2, &3);
+        assert_eq!(resumed.next(&tbm).unwrap().2, &4);
+        assert!(resumed.next(&tbm).is_none());
+        assert!(resumed.next(&tbm).is_none());
+    }
+
     struct Thing {
         id: usize,
     }
@@ -81,7 +81,7 @@ pub fn gen_bitmap(prefix: u8, masklen: u32) -> u32 {
 /// | bit   |    8 |    9 |   10 |   11 |   12 |   13 |   14 |          15 |
 /// |-------|------|------|------|------|------|------|------|-------------|
 /// | match | 001* | 010* | 011* | 100* | 101* | 110* | 111* | endnode-bit |
-
+///
 /// If the end node bit is set, the last bits are also used to match internal
 /// nodes:
 ///
@@ -92,7 +92,7 @@ pub fn gen_bitmap(prefix: u8, masklen: u32) -> u32 {
 /// | bit   |    24 |    25 |    26 |    27 |    28 |    29 |    30 |    31 |
 /// |-------|-------|-------|-------|-------|-------|-------|-------|-------|
 /// | match | 1000* | 1001* | 1010* | 1011* | 1100* | 1101* | 1110* | 1111* |
-
+///
 /// The location of the result value is computed with the ```result_ptr``` base
 /// pointer and the number of bits set left of the matching bit.
 ///
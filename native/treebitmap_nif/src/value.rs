@@ -0,0 +1,189 @@
+use crate::ecmp;
+use rustler::{env::SavedTerm, types::tuple::make_tuple, Encoder, Env, OwnedEnv, Term};
+use std::sync::Arc;
+
+/// An Erlang term owned by the NIF.
+///
+/// Each term is copied into its own ```OwnedEnv```, so lookups copy it to the
+/// caller instead of decoding it, and removing a value frees its term. Clones
+/// share the env.
+#[derive(Clone)]
+pub struct OwnedTermSlot {
+    inner: Arc<SavedSlot>,
+}
+
+struct SavedSlot {
+    env: OwnedEnv,
+    term: SavedTerm,
+    size: usize,
+}
+
+// The env is only written to by ```OwnedTermSlot::new```: afterwards terms are
+// only read from it, to be copied or compared, which any thread can do.
+unsafe impl Send for SavedSlot {}
+unsafe impl Sync for SavedSlot {}
+
+impl OwnedTermSlot {
+    pub fn new(term: Term) -> Self {
+        let env = OwnedEnv::new();
+        let term = env.save(term);
+        let size = env.run(|env| term.load(env).to_binary().len());
+        OwnedTermSlot {
+            inner: Arc::new(SavedSlot { env, term, size }),
+        }
+    }
+
+    /// Decodes bytes in external term format coming from outside the NIF
+    /// straight into the slot's env, returns ```None``` unless they hold
+    /// exactly one valid term.
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        let env = OwnedEnv::new();
+        let term = env.run(|decode_env| match decode_env.binary_to_term(bytes) {
+            Some((term, size)) if size == bytes.len() => Some(env.save(term)),
+            _ => None,
+        })?;
+        Some(OwnedTermSlot {
+            inner: Arc::new(SavedSlot {
+                env,
+                term,
+                size: bytes.len(),
+            }),
+        })
+    }
+
+    /// Returns a copy of the term in ```env```.
+    pub fn load<'a>(&self, env: Env<'a>) -> Term<'a> {
+        let slot = &*self.inner;
+        slot.env.run(|owned| slot.term.load(owned).in_env(env))
+    }
+
+    /// The term in external term format.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let slot = &*self.inner;
+        slot.env
+            .run(|env| slot.term.load(env).to_binary().as_slice().to_vec())
+    }
+
+    /// Bytes of the stored term in external term format, which memory
+    /// reporting counts as its size, not counting the slot itself.
+    pub fn size(&self) -> usize {
+        self.inner.size
+    }
+}
+
+/// Slots are equal when their terms are identical, as ```=:=```.
+impl PartialEq for OwnedTermSlot {
+    fn eq(&self, other: &Self) -> bool {
+        // terms are compared in one env, their own envs are never written to
+        Arc::ptr_eq(&self.inner, &other.inner)
+            || OwnedEnv::new().run(|env| self.load(env) == other.load(env))
+    }
+}
+
+impl Encoder for OwnedTermSlot {
+    fn encode<'a>(&self, env: Env<'a>) -> Term<'a> {
+        self.load(env)
+    }
+}
+
+/// A candidate route for a prefix, learnt from ```source```.
+#[derive(Clone, PartialEq)]
+pub struct Route {
+    pub source: OwnedTermSlot,
+    pub distance: u32,
+    pub metric: u32,
+    pub value: OwnedTermSlot,
+}
+
+impl Route {
+    pub fn size(&self) -> usize {
+        self.source.size() + self.value.size()
+    }
+}
+
+impl Encoder for Route {
+    fn encode<'a>(&self, env: Env<'a>) -> Term<'a> {
+        make_tuple(
+            env,
+            &[
+                self.source.encode(env),
+                self.distance.encode(env),
+                self.metric.encode(env),
+                self.value.encode(env),
+            ],
+        )
+    }
+}
+
+/// A member of an ECMP group, with its key for ```ecmp::pick```.
+#[derive(Clone, PartialEq)]
+pub struct Member {
+    pub key: u64,
+    pub value: OwnedTermSlot,
+}
+
+impl Member {
+    pub fn new(value: OwnedTermSlot) -> Self {
+        Member {
+            key: ecmp::member_key(&value.to_bytes()),
+            value,
+        }
+    }
+}
+
+/// What a table holds for a prefix.
+///
+/// Slots are 32 bytes, where the ets ids tables held before took 4: results
+/// preallocated by ```new_with_capacity(1000)``` take 289152 bytes rather
+/// than 37152.
+#[derive(Clone, PartialEq)]
+pub enum Slot {
+    /// A single value, set by ```add```.
+    Value(OwnedTermSlot),
+    /// Candidate routes, at most one per source, ordered by preference:
+    /// lowest administrative distance, then lowest metric, then oldest.
+    /// Never empty.
+    Routes(Vec<Route>),
+    /// Equal-cost next hops, one of them is selected per flow hash. Never
+    /// empty.
+    Ecmp(Vec<Member>),
+}
+
+impl Slot {
+    /// The value forwarding uses for a flow hashed to ```hash```: the value
+    /// itself, the one of the selected route, or the ECMP member picked for
+    /// the hash.
+    pub fn select(&self, hash: u64) -> &OwnedTermSlot {
+        match self {
+            Slot::Value(value) => value,
+            Slot::Routes(routes) => &routes[0].value,
+            Slot::Ecmp(members) => {
+                let index = ecmp::pick(members.iter().map(|member| member.key), hash).unwrap();
+                &members[index].value
+            }
+        }
+    }
+
+    pub fn size(&self) -> usize {
+        match self {
+            Slot::Value(value) => value.size(),
+            Slot::Routes(routes) => routes.iter().map(Route::size).sum(),
+            Slot::Ecmp(members) => members.iter().map(|member| member.value.size()).sum(),
+        }
+    }
+}
+
+/// Slots encode as the value lookups return, ECMP groups as the list of
+/// their members.
+impl Encoder for Slot {
+    fn encode<'a>(&self, env: Env<'a>) -> Term<'a> {
+        match self {
+            Slot::Ecmp(members) => {
+                let values: Vec<&OwnedTermSlot> =
+                    members.iter().map(|member| &member.value).collect();
+                values.encode(env)
+            }
+            _ => self.select(0).encode(env),
+        }
+    }
+}
@@ -0,0 +1,181 @@
+use crate::addrs::AddrTuple;
+use crate::nibbles::Nibbles;
+use crate::table::Table;
+use crate::value::{OwnedTermSlot, Slot};
+use std::collections::HashMap;
+use std::mem;
+
+/// Bits of the VRF id in front of every prefix.
+const VRF_BITS: u32 = 32;
+
+/// Routes of many VRFs of an address family in a single trie.
+///
+/// Prefixes are stored behind the 32 bits of their VRF id, so VRFs share the
+/// trie allocator pools and a lookup can never match a prefix of another
+/// VRF: every stored prefix is longer than the id.
+pub struct VrfTable {
+    table: Table,
+    usage: HashMap<u32, VrfUsage>,
+    /// Route-leak rules: VRFs looked up when a VRF has no matching route.
+    fallbacks: HashMap<u32, u32>,
+}
+
+/// Prefixes of a VRF, and the bytes used by their terms.
+#[derive(Default, Clone, Copy)]
+pub struct VrfUsage {
+    pub prefixes: usize,
+    pub term_bytes: usize,
+}
+
+impl VrfUsage {
+    /// Bytes used by the results of the VRF, including the stored terms.
+    pub fn result_bytes(&self) -> usize {
+        self.prefixes * mem::size_of::<Slot>() + self.term_bytes
+    }
+}
+
+fn vrf_key(vrf: u32) -> Vec<u8> {
+    (0..VRF_BITS / 4)
+        .rev()
+        .map(|i| (vrf >> (i * 4)) as u8 & 0xf)
+        .collect()
+}
+
+fn key(vrf: u32, ip: AddrTuple) -> Vec<u8> {
+    let mut key = vrf_key(vrf);
+    key.extend_from_slice(Nibbles::from(ip).as_ref());
+    key
+}
+
+impl VrfTable {
+    pub fn new() -> Self {
+        VrfTable {
+            table: Table::new(),
+            usage: HashMap::new(),
+            fallbacks: HashMap::new(),
+        }
+    }
+
+    /// Sets the value of a prefix, ```ip``` must be masked.
+    pub fn insert(
+        &mut self,
+        vrf: u32,
+        ip: AddrTuple,
+        masklen: u32,
+        value: OwnedTermSlot,
+    ) -> Option<Slot> {
+        let size = value.size();
+        let prev = self.table.insert(&key(vrf, ip), VRF_BITS + masklen, value);
+        let usage = self.usage.entry(vrf).or_default();
+        usage.term_bytes += size;
+        match prev {
+            Some(ref prev) => usage.term_bytes -= prev.size(),
+            None => usage.prefixes += 1,
+        }
+        prev
+    }
+
+    pub fn remove(&mut self, vrf: u32, ip: AddrTuple, masklen: u32) -> Option<Slot> {
+        let prev = self.table.remove(&key(vrf, ip), VRF_BITS + masklen)?;
+        self.forget(vrf, &prev);
+        Some(prev)
+    }
+
+    /// Removes every prefix of a VRF, and returns how many there were.
+    pub fn clear(&mut self, vrf: u32) -> usize {
+        let prefixes: Vec<(Vec<u8>, u32)> = self
+            .table
+            .tree
+            .iter_more_specifics(&vrf_key(vrf), VRF_BITS)
+            .map(|(nibbles, masklen, _)| (nibbles, masklen))
+            .collect();
+        for (nibbles, masklen) in &prefixes {
+            let prev = self.table.remove(nibbles, *masklen).unwrap();
+            self.forget(vrf, &prev);
+        }
+        prefixes.len()
+    }
+
+    fn forget(&mut self, vrf: u32, prev: &Slot) {
+        let usage = self.usage.get_mut(&vrf).unwrap();
+        usage.prefixes -= 1;
+        usage.term_bytes -= prev.size();
+        if usage.prefixes == 0 {
+            self.usage.remove(&vrf);
+        }
+    }
+
+    pub fn exact_match(&self, vrf: u32, ip: AddrTuple, masklen: u32) -> Option<&Slot> {
+        self.table
+            .tree
+            .exact_match(&key(vrf, ip), VRF_BITS + masklen)
+    }
+
+    /// Looks ```ip``` up in ```vrf```, then in its fallback VRFs in turn until
+    /// a route matches. Returns the VRF the route was found in, along with
+    /// its mask length and value. Cycles of fallbacks end the search.
+    pub fn longest_match(&self, vrf: u32, ip: AddrTuple) -> Option<(u32, u32, &Slot)> {
+        let mut vrf = vrf;
+        let mut visited = Vec::new();
+        loop {
+            if let Some((bits_matched, value)) = self.table.tree.longest_match(&key(vrf, ip)) {
+                return Some((vrf, bits_matched - VRF_BITS, value));
+            }
+            visited.push(vrf);
+            vrf = *self.fallbacks.get(&vrf)?;
+            if visited.contains(&vrf) {
+                return None;
+            }
+        }
+    }
+
+    /// Sets or, with ```None```, removes the VRF ```vrf``` lookups fall back to.
+    pub fn set_fallback(&mut self, vrf: u32, fallback: Option<u32>) {
+        match fallback {
+            Some(fallback) => self.fallbacks.insert(vrf, fallback),
+            None => self.fallbacks.remove(&vrf),
+        };
+    }
+
+    /// Usage of each VRF holding prefixes.
+    pub fn usage(&self) -> &HashMap<u32, VrfUsage> {
+        &self.usage
+    }
+
+    /// Returns the bytes used by trie nodes, shared by all VRFs, and by
+    /// results including the stored terms.
+    pub fn mem_usage(&self) -> (usize, usize) {
+        self.table.mem_usage()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::addrs::TupleV4;
+    use crate::tree_bitmap::TreeBitmap;
+
+    #[test]
+    fn vrf_isolation() {
+        let net = AddrTuple::V4(TupleV4::from(0x0a00_0000));
+        let host = AddrTuple::V4(TupleV4::from(0x0a01_0101));
+        assert_eq!(
+            &key(0x1234_abcd, net)[..9],
+            &[1, 2, 3, 4, 0xa, 0xb, 0xc, 0xd, 0]
+        );
+
+        let mut tbm: TreeBitmap<&str> = TreeBitmap::new();
+        tbm.insert(&key(1, net), VRF_BITS + 8, "one");
+        tbm.insert(&key(2, net), VRF_BITS, "two default");
+        assert_eq!(
+            tbm.longest_match(&key(1, host)),
+            Some((VRF_BITS + 8, &"one"))
+        );
+        assert_eq!(
+            tbm.longest_match(&key(2, host)),
+            Some((VRF_BITS, &"two default"))
+        );
+        assert_eq!(tbm.longest_match(&key(3, host)), None);
+        assert_eq!(tbm.longest_match(&key(0, host)), None);
+    }
+}
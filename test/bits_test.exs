@@ -0,0 +1,67 @@
+defmodule BitsTest do
+  use ExUnit.Case
+  alias RoutingTable.Bits
+
+  test "MAC address blocks" do
+    t = Bits.new()
+    assert nil == Bits.add(t, <<0x00, 0x1B, 0x63>>, :apple)
+    assert nil == Bits.add(t, <<0x70, 0xB3, 0xD5, 0x4C, 0x5::4>>, :ma_m)
+    assert %{prefix: <<0x00, 0x1B, 0x63>>, len: 24, value: :apple} == Bits.lookup(t, <<0x00, 0x1B, 0x63, 0x84, 0x45, 0xE6>>)
+    assert %{len: 36, value: :ma_m} = Bits.lookup(t, <<0x70, 0xB3, 0xD5, 0x4C, 0x50, 0x01>>)
+    assert nil == Bits.lookup(t, <<0x70, 0xB3, 0xD5, 0x4C, 0x60, 0x01>>)
+    assert :apple == Bits.match(t, <<0x00, 0x1B, 0x63, 0xFF>>, 24)
+    assert 2 == Bits.length(t)
+  end
+
+  test "E.164 prefixes as nibbles" do
+    t = Bits.new()
+    Bits.add(t, <<4::4, 4::4>>, :uk)
+    Bits.add(t, <<4::4, 4::4, 2::4, 0::4>>, :london)
+    Bits.add(t, <<3::4, 3::4>>, :france)
+
+    assert %{prefix: <<4::4, 4::4, 2::4, 0::4>>, value: :london} = Bits.lookup(t, <<4::4, 4::4, 2::4, 0::4, 7::4, 9::4>>)
+    assert %{len: 8, value: :uk} = Bits.lookup(t, <<4::4, 4::4, 1::4, 6::4>>)
+    # a key shorter than a prefix does not match it, even when followed by zeros
+    assert %{len: 8, value: :uk} = Bits.lookup(t, <<4::4, 4::4, 2::4>>)
+
+    assert [{<<3::4, 3::4>>, 8, :france}, {<<4::4, 4::4>>, 8, :uk}, {<<0x44, 0x20>>, 16, :london}] == Bits.to_list(t)
+    assert :uk == Bits.remove(t, <<0x44>>)
+    assert %{len: 16} = Bits.lookup(t, <<0x44, 0x20>>)
+    assert nil == Bits.lookup(t, <<0x44, 0x10>>)
+  end
+
+  test "zero length prefix and short keys" do
+    t = Bits.new()
+    Bits.add(t, <<>>, :default)
+    Bits.add(t, <<0::1>>, :zero)
+    assert %{prefix: <<>>, len: 0, value: :default} == Bits.lookup(t, <<>>)
+    assert %{len: 1, value: :zero} = Bits.lookup(t, <<0::1>>)
+    assert %{len: 1, value: :zero} = Bits.lookup(t, <<0::7>>)
+    assert %{len: 0, value: :default} = Bits.lookup(t, <<1::1>>)
+
+    Bits.add(t, <<0x0A>>, :ten)
+    Bits.add(t, <<1::1>>, :one)
+    assert [{<<>>, 0, :default}, {<<0::1>>, 1, :zero}, {<<0x0A>>, 8, :ten}, {<<1::1>>, 1, :one}] == Bits.to_list(t)
+  end
+
+  test "invalid prefixes" do
+    t = Bits.new(strict: true)
+    assert_raise ArgumentError, fn -> Bits.add(t, <<0::129>>, :too_long) end
+    assert_raise ArgumentError, fn -> Bits.add(t, <<0xFF>>, 9, :too_long) end
+    assert_raise ArgumentError, fn -> Bits.add(t, <<0xFF>>, 4, :host_bits) end
+    assert_raise ArgumentError, fn -> Bits.add(t, <<0xF0, 1>>, 4, :host_bits) end
+    assert nil == Bits.add(t, <<0xF0, 0>>, 4, :ok)
+    assert :ok == Bits.match(t, <<0xF::4>>)
+  end
+
+  test "dump/1, load/1 and clone/1" do
+    t = Bits.new()
+    Bits.add(t, <<0x00, 0x1B, 0x63>>, :apple)
+    Bits.add(t, <<0b101::3>>, :three_bits)
+    {:ok, loaded} = Bits.load(Bits.dump(t))
+    assert Bits.to_list(t) == Bits.to_list(loaded)
+    copy = Bits.clone(t)
+    Bits.remove(t, <<0b101::3>>)
+    assert 2 == Bits.length(copy)
+  end
+end
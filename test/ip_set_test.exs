@@ -0,0 +1,64 @@
+defmodule IPSetTest do
+  use ExUnit.Case
+  alias RoutingTable.IPSet
+
+  test "membership" do
+    set = IPSet.new()
+    assert true == IPSet.add(set, "10.0.0.0/8")
+    assert false == IPSet.add(set, {10, 0, 0, 0}, 8)
+    assert true == IPSet.add(set, "2001:db8::/32")
+
+    assert IPSet.contains?(set, {10, 1, 2, 3})
+    assert IPSet.contains?(set, "2001:db8::1")
+    refute IPSet.contains?(set, "192.0.2.1")
+    assert IPSet.contains?(set, {:raw, <<10, 1, 2, 3>>})
+    assert [{{10, 0, 0, 0}, 8}, {{8193, 3512, 0, 0, 0, 0, 0, 0}, 32}] == IPSet.to_list(set)
+    assert %{inet4: 1, inet6: 1} == IPSet.length(set)
+
+    assert true == IPSet.remove(set, "10.0.0.0/8")
+    assert false == IPSet.remove(set, "10.0.0.0/8")
+    refute IPSet.contains?(set, {10, 1, 2, 3})
+    assert_raise ArgumentError, ~r/invalid_masklen/, fn -> IPSet.add(set, {10, 0, 0, 0}, 33) end
+  end
+
+  test "cover_count/1" do
+    set = IPSet.new()
+    assert %{inet4: 0, inet6: 0} == IPSet.cover_count(set)
+
+    IPSet.add(set, "10.0.0.0/8")
+    IPSet.add(set, "10.1.0.0/16")
+    IPSet.add(set, "192.0.2.1")
+    IPSet.add(set, "2001:db8::/32")
+    assert %{inet4: 16_777_217, inet6: 79_228_162_514_264_337_593_543_950_336} == IPSet.cover_count(set)
+
+    IPSet.add(set, "0.0.0.0/0")
+    IPSet.add(set, "::/0")
+    assert %{inet4: 4_294_967_296, inet6: 340_282_366_920_938_463_463_374_607_431_768_211_456} == IPSet.cover_count(set)
+
+    set = IPSet.new()
+    IPSet.add(set, "128.0.0.0/1")
+    IPSet.add(set, "0.0.0.0/2")
+    IPSet.add(set, "10.0.0.0/8")
+    assert %{inet4: 3_221_225_472, inet6: 0} == IPSet.cover_count(set)
+    assert [{{0, 0, 0, 0}, 2}, {{10, 0, 0, 0}, 8}, {{128, 0, 0, 0}, 1}] == IPSet.to_list(set)
+  end
+
+  test "complement/2" do
+    allocated = IPSet.new(prefix_format: :cidr)
+    IPSet.add(allocated, "10.0.0.0/9")
+    IPSet.add(allocated, "10.192.0.0/10")
+    IPSet.add(allocated, "2001:db8::/33")
+
+    free = IPSet.complement(allocated, "10.0.0.0/8")
+    assert [{"10.128.0.0/10", 10}] == IPSet.to_list(free)
+    assert IPSet.contains?(free, {10, 128, 0, 1})
+    refute IPSet.contains?(free, {10, 0, 0, 1})
+
+    assert [{"2001:db8:8000::/33", 33}] == IPSet.to_list(IPSet.complement(allocated, "2001:db8::/32"))
+    assert [] == IPSet.to_list(IPSet.complement(allocated, "10.0.0.0/10"))
+    # the new set is independent and keeps the options
+    IPSet.add(free, {192, 0, 2, 0}, 24)
+    assert [{"10.128.0.0/10", 10}, {"192.0.2.0/24", 24}] == IPSet.to_list(free)
+    assert %{inet4: 2, inet6: 1} == IPSet.length(allocated)
+  end
+end
@@ -0,0 +1,69 @@
+defmodule MACTest do
+  use ExUnit.Case
+  alias RoutingTable.MAC
+
+  test "EUI-48 and EUI-64 prefixes" do
+    t = MAC.new()
+    assert nil == MAC.add(t, "00:1b:63:00:00:00/24", :apple)
+    assert nil == MAC.add(t, {0x70, 0xB3, 0xD5, 0x4C, 0x50, 0}, 36, :ma_s)
+    assert nil == MAC.add(t, "00-1B-63-84-45-E6", :host)
+    assert nil == MAC.add(t, "00:1b:63:84:45:e6:00:00/56", :eui64)
+
+    assert %{prefix: {0x00, 0x1B, 0x63, 0x84, 0x45, 0xE6}, len: 48, value: :host} ==
+             MAC.lookup(t, {0x00, 0x1B, 0x63, 0x84, 0x45, 0xE6})
+
+    assert %{prefix: {0x00, 0x1B, 0x63, 0, 0, 0}, len: 24, value: :apple} == MAC.lookup(t, "00:1b:63:84:45:e7")
+    assert %{len: 56, value: :eui64} = MAC.lookup(t, "00:1b:63:84:45:e6:00:01")
+    # EUI-64 addresses do not match EUI-48 prefixes
+    assert nil == MAC.lookup(t, {0x00, 0x1B, 0x63, 1, 2, 3, 4, 5})
+    assert nil == MAC.lookup(t, "00:1b:63:84:45:e6:01:00")
+    assert nil == MAC.match(t, "00:1b:63:00:00:00:00:00/24")
+    assert %{len: 36, value: :ma_s} = MAC.lookup(t, "70:b3:d5:4c:5f:ff")
+    assert nil == MAC.lookup(t, "70:b3:d5:4c:6f:ff")
+
+    assert :apple == MAC.match(t, "00:1b:63:00:00:00/24")
+    assert nil == MAC.match(t, "00:1b:63:00:00:00/32")
+
+    assert [
+             {{0x00, 0x1B, 0x63, 0, 0, 0}, 24, :apple},
+             {{0x00, 0x1B, 0x63, 0x84, 0x45, 0xE6}, 48, :host},
+             {{0x70, 0xB3, 0xD5, 0x4C, 0x50, 0}, 36, :ma_s},
+             {{0x00, 0x1B, 0x63, 0x84, 0x45, 0xE6, 0, 0}, 56, :eui64}
+           ] == MAC.to_list(t)
+
+    assert :host == MAC.remove(t, "00:1b:63:84:45:e6")
+    assert %{len: 24} = MAC.lookup(t, "00:1b:63:84:45:e6")
+    assert %{eui48: 2, eui64: 1} == MAC.length(t)
+
+    MAC.add(t, "80:00:00:00:00:00/1", :upper_half)
+    assert [{_, 24, :apple}, {_, 36, :ma_s}, {{0x80, 0, 0, 0, 0, 0}, 1, :upper_half}, {_, 56, :eui64}] = MAC.to_list(t)
+  end
+
+  test "import_oui/2" do
+    t = MAC.new()
+    assert {:ok, 5} == MAC.import_oui(t, {:file, Path.join(__DIR__, "../native/treebitmap_nif/tests/fixtures/oui.csv")})
+
+    assert %{len: 24, value: %{registry: "MA-L", organization: "Apple, Inc.", address: "1 Infinite Loop Cupertino CA US 95014"}} =
+             MAC.lookup(t, "00:1b:63:84:45:e6")
+
+    assert %{len: 28, value: %{registry: "MA-M", organization: ~s(Acme "Labs" GmbH)}} = MAC.lookup(t, "70:b3:d5:e1:23:45")
+    assert %{len: 36, value: %{registry: "MA-S", address: ""}} = MAC.lookup(t, "70:b3:d5:f2:c1:23")
+    assert nil == MAC.lookup(t, "0a:1b:2c:00:00:01")
+    assert %{len: 24, value: %{registry: "MA-L"}} = MAC.lookup(t, "00:1b:63:ff:fe:84:45:e6")
+
+    assert {:error, :enoent} == MAC.import_oui(t, {:file, Path.join(__DIR__, "../native/treebitmap_nif/tests/fixtures/missing.csv")})
+    assert {:error, :invalid_oui} == MAC.import_oui(t, "not,a,registry\n")
+    assert {:error, :invalid_oui} ==
+             MAC.import_oui(t, "Registry,Assignment,Organization Name,Organization Address\nMA-L,,Nobody,\n")
+
+    assert %{eui48: 5, eui64: 5} == MAC.length(t)
+  end
+
+  test "invalid prefixes" do
+    t = MAC.new(strict: true)
+    assert_raise ArgumentError, fn -> MAC.add(t, "00:1b:63:84:45:e6/24", :bad) end
+    assert_raise ArgumentError, fn -> MAC.add(t, "00:1b:63:00:00:00/49", :bad) end
+    assert_raise ArgumentError, fn -> MAC.add(t, "00:1b:63/24", :bad) end
+    assert_raise ArgumentError, fn -> MAC.lookup(t, {1, 2, 3}) end
+  end
+end
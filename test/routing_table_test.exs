@@ -22,19 +22,473 @@ defmodule RoutingTableTest do
     assert nil == RoutingTable.match(t, {8, 8, 8, 8}, 32)
     assert false == RoutingTable.reachable?(t, {8, 8, 8, 8}, 32)
 
-    assert %{ets: 330, inet4: {1248, 1168}, inet6: {1344, 1168}} = RoutingTable.memory(t)
+    lan_size = byte_size(:erlang.term_to_binary(:lan))
+    assert %{inet4: {1248, 1280 + ^lan_size}, inet6: {1344, 1280 + ^lan_size}} = RoutingTable.memory(t)
 
-    assert %{ets: 2, inet4: 1, inet6: 1} = RoutingTable.length(t)
+    assert %{inet4: 1, inet6: 1} = RoutingTable.length(t)
     assert :lan = RoutingTable.remove(t, {8193, 3512, 34211, 0, 0, 35374, 880, 1}, 64)
     assert nil == RoutingTable.lookup(t, {8193, 3512, 34211, 0, 0, 35374, 880, 29492})
-    assert %{ets: 2, inet4: 1, inet6: 0} = RoutingTable.length(t)
+    assert %{inet4: 1, inet6: 0} = RoutingTable.length(t)
     assert :lan == RoutingTable.remove(t, {192, 168, 1, 0}, 24)
-    assert %{ets: 1, inet4: 0, inet6: 0} = RoutingTable.length(t)
+    assert %{inet4: 0, inet6: 0} = RoutingTable.length(t)
 
     assert nil == RoutingTable.add(t, {8193, 3512, 34211, 0, 0, 35374, 880, 1}, 64, :lan)
     assert :lan = RoutingTable.add(t, {8193, 3512, 34211, 0, 0, 35374, 880, 1}, 64, :lan2)
-    assert %{ets: 2, inet4: 0, inet6: 1} = RoutingTable.length(t)
+    assert %{inet4: 0, inet6: 1} = RoutingTable.length(t)
   end
 
+  test "any term as value" do
+    t = RoutingTable.new()
+    value = %{next_hop: {192, 168, 1, 1}, tags: ["a", :b], ref: make_ref()}
+    RoutingTable.add(t, {10, 0, 0, 0}, 8, value)
+    assert %{value: ^value} = RoutingTable.lookup(t, {10, 1, 2, 3})
+    assert ^value = RoutingTable.match(t, {10, 0, 0, 0}, 8)
+    assert ^value = RoutingTable.add(t, {10, 0, 0, 0}, 8, "other")
+    assert "other" = RoutingTable.remove(t, {10, 0, 0, 0}, 8)
+  end
+
+  test "add_many/2" do
+    t = RoutingTable.new()
+    routes = for i <- 0..255, do: {{10, i, 0, 0}, 16, i}
+    assert [] == RoutingTable.add_many(t, [{{8193, 3512, 0, 0, 0, 0, 0, 0}, 32, :doc} | routes])
+    assert %{inet4: 256, inet6: 1} == RoutingTable.length(t)
+    assert %{value: 42} = RoutingTable.lookup(t, {10, 42, 1, 1})
+
+    assert [{{:nope, 8, :bad}, :badarg}, {{{10, 0, 0, 0}, 40, :bad}, :invalid_masklen}] ==
+             RoutingTable.add_many(t, [{{10, 0, 0, 0}, 40, :bad}, {:nope, 8, :bad}, {{10, 0, 0, 0}, 8, :ok}])
+
+    assert :ok == RoutingTable.match(t, {10, 0, 0, 0}, 8)
+  end
+
+  test "lookup_many/2 and lookup_packed/3" do
+    t = RoutingTable.new()
+    RoutingTable.add(t, {10, 0, 0, 0}, 8, :v4)
+    RoutingTable.add(t, {8193, 3512, 0, 0, 0, 0, 0, 0}, 32, :v6)
+
+    assert [
+             %{prefix: {8193, 3512, 0, 0, 0, 0, 0, 0}, len: 32, value: :v6},
+             %{prefix: {10, 0, 0, 0}, len: 8, value: :v4},
+             nil,
+             %{prefix: {10, 0, 0, 0}, len: 8, value: :v4}
+           ] ==
+             RoutingTable.lookup_many(t, [
+               {8193, 3512, 0, 0, 0, 0, 0, 1},
+               "10.1.1.1",
+               {192, 168, 1, 1},
+               {:raw, <<10, 2, 2, 2>>}
+             ])
+
+    assert [%{value: :v4}, nil] = RoutingTable.lookup_packed(t, :inet4, <<10, 0, 0, 1, 11, 0, 0, 1>>)
+    assert [%{value: :v6}] = RoutingTable.lookup_packed(t, :inet6, <<0x20010DB8::32, 1::96>>)
+  end
+
+  test "CIDR strings and raw binaries" do
+    t = RoutingTable.new()
+    assert nil == RoutingTable.add(t, "10.0.0.0/8", :rir)
+    assert nil == RoutingTable.add(t, "2001:db8::/32", :doc)
+    assert nil == RoutingTable.add(t, <<192, 168, 1, 0>>, 24, :lan)
+
+    assert %{prefix: {10, 0, 0, 0}, len: 8, value: :rir} = RoutingTable.lookup(t, "10.1.2.3")
+    assert %{value: :doc} = RoutingTable.lookup(t, {8193, 3512, 0, 0, 0, 0, 0, 1})
+    assert %{value: :lan} = RoutingTable.lookup(t, {192, 168, 1, 1})
+    assert true == RoutingTable.reachable?(t, "2001:db8::1")
+    assert :rir == RoutingTable.match(t, "10.0.0.0/8")
+    assert :doc == RoutingTable.match(t, <<0x20010DB8::32, 0::96>>, 32)
+    assert true == RoutingTable.reachable?(t, <<192, 168, 1, 0>>, 24)
+    # raw addresses are tagged, bare binaries are always address strings
+    assert %{value: :rir} = RoutingTable.lookup(t, {:raw, <<10, 1, 2, 3>>})
+    assert %{value: :lan} = RoutingTable.lookup(t, {:raw, <<192, 168, 1, 1>>})
+    assert %{value: :doc} = RoutingTable.lookup(t, {:raw, <<0x20010DB8::32, 1::96>>})
+    assert true == RoutingTable.reachable?(t, {:raw, <<192, 168, 1, 1>>})
+    assert %{value: :lan} = RoutingTable.lookup_hashed(t, {:raw, <<192, 168, 1, 1>>}, 7)
+    assert [%{value: :rir}] = RoutingTable.matches(t, "10.1.2.3")
+    assert [%{value: :lan}] = RoutingTable.matches(t, {:raw, <<192, 168, 1, 1>>})
+    # "2001:db8::dead:1" is 16 bytes, but still an address string
+    assert %{value: :doc} = RoutingTable.lookup(t, "2001:db8::dead:1")
+    assert_raise ArgumentError, ~r/not a prefix/, fn -> RoutingTable.lookup(t, "10.0.0.0/8") end
+    assert_raise ArgumentError, ~r/not a prefix/, fn -> RoutingTable.lookup_many(t, ["10.0.0.1/32"]) end
+    assert_raise ArgumentError, ~r/invalid_prefix/, fn -> RoutingTable.lookup(t, <<10, 1, 2, 3>>) end
+    assert_raise ArgumentError, ~r/invalid raw address/, fn -> RoutingTable.lookup(t, {:raw, <<10, 1, 2>>}) end
+    assert [{{10, 0, 0, 0}, 8, :rir}] == RoutingTable.more_specifics(t, "10.0.0.0/8")
+    assert :lan == RoutingTable.remove(t, "192.168.1.0/24")
+
+    assert_raise ArgumentError, ~r/invalid_prefix/, fn -> RoutingTable.add(t, "nope", :bad) end
+    assert_raise ArgumentError, ~r/invalid_masklen/, fn -> RoutingTable.add(t, "10.0.0.0/33", :bad) end
+    assert_raise ArgumentError, ~r/10.0.0.0\/33/, fn -> RoutingTable.add(t, <<10, 0, 0, 0>>, 33, :bad) end
+  end
+
+  test "prefix_format: :cidr" do
+    t = RoutingTable.new(prefix_format: :cidr)
+    RoutingTable.add(t, "10.0.0.0/8", :rir)
+    RoutingTable.add(t, {8193, 3512, 0, 0, 0, 0, 0, 0}, 32, :doc)
+    assert %{prefix: "10.0.0.0/8", len: 8} = RoutingTable.lookup(t, {10, 1, 1, 1})
+    assert [{"10.0.0.0/8", 8, :rir}, {"2001:db8::/32", 32, :doc}] == RoutingTable.to_list(t)
+  end
+
+  test "embedded_ipv4: [:mapped]" do
+    t = RoutingTable.new(embedded_ipv4: [:mapped])
+    RoutingTable.add(t, "10.0.0.0/8", :rir)
+    RoutingTable.add(t, "::/0", :default6)
+    mapped = {0, 0, 0, 0, 0, 0xFFFF, 0x0A01, 0x0203}
+
+    assert %{prefix: {0, 0, 0, 0, 0, 0xFFFF, 0x0A00, 0}, len: 104, value: :rir} == RoutingTable.lookup(t, mapped)
+    assert %{len: 104, value: :rir} = RoutingTable.lookup(t, "::ffff:10.1.2.3")
+    # IPv6 routes still match normalized addresses the IPv4 table misses
+    assert %{len: 0, value: :default6} = RoutingTable.lookup(t, "::ffff:192.0.2.1")
+    assert [%{len: 0, value: :default6}, %{len: 104, value: :rir}] = RoutingTable.matches(t, mapped)
+    assert [%{len: 104, value: :rir}, %{len: 8, value: :rir}] = RoutingTable.lookup_many(t, [mapped, {10, 1, 2, 3}])
+    # only the listed embeddings are normalized
+    assert %{len: 0, value: :default6} = RoutingTable.lookup(t, "64:ff9b::10.1.2.3")
+
+    plain = RoutingTable.new()
+    RoutingTable.add(plain, "10.0.0.0/8", :rir)
+    assert nil == RoutingTable.lookup(plain, mapped)
+
+    assert %{len: 104} = RoutingTable.lookup(RoutingTable.clone(t), mapped)
+    assert {:ok, loaded} = RoutingTable.load(RoutingTable.dump(t))
+    assert %{len: 104} = RoutingTable.lookup(loaded, mapped)
+  end
+
+  test "embedded_ipv4: [:compatible, :nat64, :six_to_four]" do
+    t = RoutingTable.new(embedded_ipv4: [:compatible, :nat64, :six_to_four], prefix_format: :cidr)
+    RoutingTable.add(t, "10.0.0.0/8", :rir)
+    RoutingTable.add(t, "0.0.0.0/0", :default)
+
+    assert %{prefix: "::a00:0/104", len: 104, value: :rir} == RoutingTable.lookup(t, "::10.1.2.3")
+    assert %{prefix: "64:ff9b::a00:0/104", len: 104, value: :rir} == RoutingTable.lookup(t, "64:ff9b::10.1.2.3")
+    assert %{prefix: "2002:a00::/24", len: 24, value: :rir} == RoutingTable.lookup(t, "2002:a01:203::1")
+    assert %{prefix: "2002::/16", len: 16, value: :default} == RoutingTable.lookup(t, "2002:c000:201::1")
+    # neither the unspecified nor the loopback address are IPv4-compatible
+    assert nil == RoutingTable.lookup(t, "::1")
+    assert nil == RoutingTable.lookup(t, "::ffff:10.1.2.3")
+
+    t = RoutingTable.new(embedded_ipv4: [:nat64])
+    RoutingTable.add(t, "192.0.2.0/24", {:nat64, :pool})
+    RoutingTable.add(t, "64:ff9b::/96", :nat64)
+    assert {:ok, [%{len: 120, value: {:nat64, :pool}}]} = RoutingTable.resolve(t, "64:ff9b::192.0.2.1", 4)
+    assert %{len: 96, value: :nat64} = RoutingTable.lookup(t, "64:ff9b::198.51.100.1")
+    assert [%{len: 96}, %{len: 120}] = RoutingTable.matches(t, {0x64, 0xFF9B, 0, 0, 0, 0, 0xC000, 0x0201})
+    assert_raise ArgumentError, fn -> RoutingTable.new(embedded_ipv4: [:teredo]) end
+  end
+
+  test "union/3" do
+    announced = RoutingTable.new()
+    RoutingTable.add(announced, "192.0.2.0/24", :announced)
+    RoutingTable.add(announced, "2001:db8::/32", :announced)
+    received = RoutingTable.new()
+    RoutingTable.add(received, "192.0.2.0/24", :received)
+    RoutingTable.add(received, "198.51.100.0/24", :received)
+
+    union = RoutingTable.union(announced, received)
+    assert [{{192, 0, 2, 0}, 24, :announced}, {{198, 51, 100, 0}, 24, :received}, {{8193, 3512, 0, 0, 0, 0, 0, 0}, 32, :announced}] ==
+             RoutingTable.to_list(union)
+
+    assert %{value: :received} = RoutingTable.match(RoutingTable.union(announced, received, merge: :right), "192.0.2.0/24")
+    assert %{value: {:announced, :received}} = RoutingTable.match(RoutingTable.union(announced, received, merge: :pair), "192.0.2.0/24")
+    # the new table is independent
+    RoutingTable.remove(announced, "192.0.2.0/24")
+    assert %{value: :announced} = RoutingTable.match(union, "192.0.2.0/24")
+  end
+
+  test "intersection/3" do
+    irr = RoutingTable.new()
+    RoutingTable.add(irr, "10.0.0.0/8", :as64500)
+    RoutingTable.add(irr, "192.0.2.0/24", :as64501)
+    bgp = RoutingTable.new()
+    RoutingTable.add(bgp, "10.1.0.0/16", :peer_a)
+    RoutingTable.add(bgp, "10.2.0.0/16", :peer_b)
+    RoutingTable.add(bgp, "198.51.100.0/24", :peer_a)
+
+    both = RoutingTable.intersection(irr, bgp, merge: :pair)
+    assert [{{10, 1, 0, 0}, 16, {:as64500, :peer_a}}, {{10, 2, 0, 0}, 16, {:as64500, :peer_b}}] == RoutingTable.to_list(both)
+    assert %{value: {:as64500, :peer_b}} = RoutingTable.lookup(both, {10, 2, 3, 4})
+    assert nil == RoutingTable.lookup(both, {10, 3, 0, 1})
+    assert [{{10, 1, 0, 0}, 16, :as64500}, {{10, 2, 0, 0}, 16, :as64500}] == RoutingTable.to_list(RoutingTable.intersection(irr, bgp))
+    assert [] == RoutingTable.to_list(RoutingTable.intersection(irr, RoutingTable.new()))
+  end
+
+  test "difference/2" do
+    t = RoutingTable.new()
+    RoutingTable.add(t, "10.0.0.0/8", :private)
+    RoutingTable.add(t, "192.0.2.0/24", :doc)
+    holes = RoutingTable.new()
+    RoutingTable.add(holes, "10.0.0.0/9", :hole)
+    RoutingTable.add(holes, "192.0.2.0/24", :hole)
+
+    assert [{{10, 128, 0, 0}, 9, :private}] == RoutingTable.to_list(RoutingTable.difference(t, holes))
+
+    RoutingTable.add(holes, "10.128.0.0/10", :hole)
+    diff = RoutingTable.difference(t, holes)
+    assert [{{10, 192, 0, 0}, 10, :private}] == RoutingTable.to_list(diff)
+    assert nil == RoutingTable.lookup(diff, {10, 1, 1, 1})
+    assert RoutingTable.to_list(t) == RoutingTable.to_list(RoutingTable.difference(t, RoutingTable.new()))
+  end
+
+  test "diff/2" do
+    running = RoutingTable.new()
+    RoutingTable.add(running, "10.0.0.0/8", :rir)
+    RoutingTable.add(running, "192.0.2.0/24", :doc)
+    RoutingTable.add(running, "2001:db8::/32", :doc)
+    assert [] == RoutingTable.diff(running, running)
+
+    candidate = RoutingTable.clone(running)
+    RoutingTable.add(candidate, "10.0.0.0/8", :private)
+    RoutingTable.add(candidate, "10.1.0.0/16", :lab)
+    RoutingTable.remove(candidate, "2001:db8::/32")
+    RoutingTable.add(candidate, "0.0.0.0/0", :default)
+
+    assert [
+             {:added, {0, 0, 0, 0}, 0, :default},
+             {:changed, {10, 0, 0, 0}, 8, :rir, :private},
+             {:added, {10, 1, 0, 0}, 16, :lab},
+             {:withdrawn, {8193, 3512, 0, 0, 0, 0, 0, 0}, 32, :doc}
+           ] == RoutingTable.diff(running, candidate)
+
+    assert {:withdrawn, {10, 1, 0, 0}, 16, :lab} in RoutingTable.diff(candidate, running)
+  end
+
+  test "aggregate/1" do
+    t = RoutingTable.new()
+    for host <- 0..255, do: RoutingTable.add(t, {192, 0, 2, host}, 32, :allow)
+    RoutingTable.add(t, "198.51.100.0/24", :deny)
+    RoutingTable.add(t, "198.51.100.128/25", :deny)
+    RoutingTable.add(t, "198.51.100.7/32", :allow)
+    RoutingTable.add(t, "2001:db8::/33", :allow)
+    RoutingTable.add(t, "2001:db8:8000::/33", :allow)
+
+    aggregated = RoutingTable.aggregate(t)
+
+    assert [
+             {{192, 0, 2, 0}, 24, :allow},
+             {{198, 51, 100, 0}, 24, :deny},
+             {{198, 51, 100, 7}, 32, :allow},
+             {{8193, 3512, 0, 0, 0, 0, 0, 0}, 32, :allow}
+           ] == RoutingTable.to_list(aggregated)
+
+    for ip <- [{192, 0, 2, 77}, {198, 51, 100, 7}, {198, 51, 100, 200}, {192, 0, 3, 1}] do
+      assert RoutingTable.lookup(t, ip)[:value] == RoutingTable.lookup(aggregated, ip)[:value]
+    end
+
+    assert %{inet4: 259, inet6: 2} == RoutingTable.length(t)
+  end
+
+  test "add_range/4 and ranges/1" do
+    t = RoutingTable.new()
+
+    assert [{{10, 0, 0, 1}, 32}, {{10, 0, 0, 2}, 31}, {{10, 0, 0, 4}, 30}, {{10, 0, 0, 8}, 31}, {{10, 0, 0, 10}, 32}] ==
+             RoutingTable.add_range(t, "10.0.0.1", "10.0.0.10", :lab)
+
+    assert [{{192, 0, 2, 0}, 24}] == RoutingTable.add_range(t, {192, 0, 2, 0}, {192, 0, 2, 255}, :doc)
+    assert [{{8193, 3512, 0, 0, 0, 0, 0, 0}, 47}] == RoutingTable.add_range(t, "2001:db8::", "2001:db8:1:ffff:ffff:ffff:ffff:ffff", :doc)
+    assert %{value: :lab} = RoutingTable.lookup(t, {10, 0, 0, 5})
+    assert nil == RoutingTable.lookup(t, {10, 0, 0, 11})
+
+    assert_raise ArgumentError, ~r/invalid_range/, fn -> RoutingTable.add_range(t, "10.0.0.2", "10.0.0.1", :bad) end
+    assert_raise ArgumentError, ~r/invalid_range/, fn -> RoutingTable.add_range(t, "10.0.0.1", "::1", :bad) end
+
+    RoutingTable.add(t, "10.0.0.11/32", :lab)
+    RoutingTable.add(t, "192.0.2.0/25", :doc)
+    RoutingTable.add(t, "192.0.2.64/26", :test)
+
+    assert [
+             {{10, 0, 0, 1}, {10, 0, 0, 11}, :lab},
+             {{192, 0, 2, 0}, {192, 0, 2, 63}, :doc},
+             {{192, 0, 2, 64}, {192, 0, 2, 127}, :test},
+             {{192, 0, 2, 128}, {192, 0, 2, 255}, :doc},
+             {{8193, 3512, 0, 0, 0, 0, 0, 0}, {8193, 3512, 1, 65535, 65535, 65535, 65535, 65535}, :doc}
+           ] == RoutingTable.ranges(t)
+
+    # a /26 sits with its /24 in a trie node, but before the /25 by address
+    t = RoutingTable.new()
+    RoutingTable.add(t, "10.0.0.0/24", :a)
+    RoutingTable.add(t, "10.0.0.128/25", :b)
+    RoutingTable.add(t, "10.0.0.0/26", :c)
+
+    assert [
+             {{10, 0, 0, 0}, {10, 0, 0, 63}, :c},
+             {{10, 0, 0, 64}, {10, 0, 0, 127}, :a},
+             {{10, 0, 0, 128}, {10, 0, 0, 255}, :b}
+           ] == RoutingTable.ranges(t)
+  end
+
+  test "clone/1" do
+    t = RoutingTable.new()
+    RoutingTable.add(t, {10, 0, 0, 0}, 8, :before)
+    RoutingTable.add(t, {8193, 3512, 0, 0, 0, 0, 0, 0}, 32, :doc)
+    snapshot = RoutingTable.clone(t)
+
+    RoutingTable.add(t, {10, 0, 0, 0}, 8, :after)
+    RoutingTable.remove(t, {8193, 3512, 0, 0, 0, 0, 0, 0}, 32)
+    assert [{{10, 0, 0, 0}, 8, :before}, {{8193, 3512, 0, 0, 0, 0, 0, 0}, 32, :doc}] == RoutingTable.to_list(snapshot)
+    assert [{{10, 0, 0, 0}, 8, :after}] == RoutingTable.to_list(t)
+  end
+
+  test "dump/1 and load/1" do
+    t = RoutingTable.new(prefix_format: :cidr)
+    RoutingTable.add(t, "10.0.0.0/8", :rir)
+    RoutingTable.add(t, "2001:db8::/32", {:doc, 3849})
+
+    assert {:ok, loaded} = RoutingTable.load(RoutingTable.dump(t))
+    assert [{"10.0.0.0/8", 8, :rir}, {"2001:db8::/32", 32, {:doc, 3849}}] == RoutingTable.to_list(loaded)
+    assert {:error, :invalid_dump} == RoutingTable.load(<<>>)
+  end
+
+  test "import_mrt/2" do
+    t = RoutingTable.new()
+    path = Path.join(__DIR__, "../native/treebitmap_nif/tests/fixtures/table_dump_v2.mrt")
+    assert {:ok, %{inet4: 2, inet6: 1}} == RoutingTable.import_mrt(t, {:file, path})
+
+    assert %{prefix: {10, 0, 0, 0}, len: 8, value: [first, second]} = RoutingTable.lookup(t, {10, 1, 1, 1})
+
+    assert %{
+             peer_ip: {192, 0, 2, 1},
+             peer_as: 64496,
+             origin: :igp,
+             as_path: [64496, 3356, 64500],
+             origin_as: 64500,
+             next_hop: {192, 0, 2, 1}
+           } = first
+
+    assert %{peer_ip: {8193, 3512, 0, 0, 0, 0, 0, 1}, origin: :incomplete, origin_as: 64501} = second
+    assert [%{as_path: [64496, [64510, 64511]], origin_as: nil}] = RoutingTable.match(t, "192.0.2.0/24")
+    assert [%{next_hop: {8193, 3512, 0, 0, 0, 0, 0, 1}}] = RoutingTable.match(t, "2001:db8::/32")
+    assert nil == RoutingTable.match(t, "224.0.0.0/4")
+
+    legacy = File.read!(Path.join(__DIR__, "../native/treebitmap_nif/tests/fixtures/table_dump.mrt"))
+    assert {:ok, %{inet4: 2, inet6: 1}} == RoutingTable.import_mrt(t, legacy)
+    assert [%{peer_as: 64496, origin_as: 3356}, %{peer_as: 64497, origin_as: 174}] = RoutingTable.match(t, "10.0.0.0/8")
+    # AS_TRANS in 2-byte AS paths is replaced from AS4_PATH
+    assert [%{as_path: [64498, 4_200_000_000], origin_as: 4_200_000_000}, %{origin_as: 174}] =
+             RoutingTable.match(t, "192.0.2.0/24")
+
+    assert {:error, :enoent} == RoutingTable.import_mrt(t, {:file, "/nonexistent.mrt"})
+    assert {:error, :invalid_mrt} == RoutingTable.import_mrt(t, binary_part(legacy, 0, 20))
+  end
+
+  test "add_route/4, remove_route/4 and routes/3" do
+    t = RoutingTable.new()
+    static = %{source: :static, distance: 1, metric: 0, value: {192, 0, 2, 1}}
+    ospf = %{source: :ospf, distance: 110, metric: 20, value: {192, 0, 2, 2}}
+
+    assert nil == RoutingTable.add_route(t, {10, 0, 0, 0}, 8, ospf)
+    assert %{value: {192, 0, 2, 2}} = RoutingTable.lookup(t, {10, 1, 1, 1})
+    assert nil == RoutingTable.add_route(t, "10.0.0.0/8", static)
+    assert %{prefix: {10, 0, 0, 0}, len: 8, value: {192, 0, 2, 1}} == RoutingTable.lookup(t, {10, 1, 1, 1})
+    assert [static, ospf] == RoutingTable.routes(t, {10, 0, 0, 0}, 8)
+    assert [static, ospf] == RoutingTable.routes(t, "10.0.0.0/8")
+
+    assert static == RoutingTable.remove_route(t, "10.0.0.0/8", :static)
+    assert {192, 0, 2, 2} == RoutingTable.match(t, <<10, 0, 0, 0>>, 8)
+    assert ospf == RoutingTable.remove_route(t, {10, 0, 0, 0}, 8, :ospf)
+    assert nil == RoutingTable.lookup(t, {10, 1, 1, 1})
+
+    assert nil == RoutingTable.add_route(t, {8193, 3512, 0, 0, 0, 0, 0, 0}, 32, static)
+    assert [static] == RoutingTable.routes(t, "2001:db8::/32")
+    assert_raise ArgumentError, fn -> RoutingTable.routes(t, {10, 0, 0, 0}, 33) end
+  end
+
+  test "add_ecmp/4 and lookup_hashed/3" do
+    t = RoutingTable.new()
+    next_hops = [{192, 0, 2, 1}, {192, 0, 2, 2}]
+    assert nil == RoutingTable.add_ecmp(t, {10, 0, 0, 0}, 8, next_hops)
+    assert nil == RoutingTable.add_ecmp(t, "2001:db8::/32", [:a, :b])
+    assert %{len: 8, value: ^next_hops} = RoutingTable.lookup(t, {10, 1, 1, 1})
+
+    flow = :erlang.phash2({{10, 1, 1, 1}, {10, 2, 2, 2}, 6, 49152, 443})
+    %{prefix: {10, 0, 0, 0}, len: 8, value: next_hop} = RoutingTable.lookup_hashed(t, {10, 2, 2, 2}, flow)
+    assert next_hop in next_hops
+    assert %{value: ^next_hop} = RoutingTable.lookup_hashed(t, "10.2.2.2", flow)
+    assert %{value: value} = RoutingTable.lookup_hashed(t, {8193, 3512, 0, 0, 0, 0, 0, 1}, flow)
+    assert value in [:a, :b]
+    assert nil == RoutingTable.lookup_hashed(t, {192, 168, 1, 1}, flow)
+  end
+
+  test "resolve/4" do
+    bgp = RoutingTable.new()
+    igp = RoutingTable.new()
+    RoutingTable.add(bgp, "203.0.113.0/24", {192, 0, 2, 1})
+    RoutingTable.add(igp, "192.0.2.0/24", {10, 0, 0, 1})
+    RoutingTable.add(igp, "10.0.0.0/30", {:interface, "eth0"})
+
+    assert {:ok,
+            [
+              %{prefix: {203, 0, 113, 0}, len: 24, value: {192, 0, 2, 1}},
+              %{prefix: {192, 0, 2, 0}, len: 24, value: {10, 0, 0, 1}},
+              %{prefix: {10, 0, 0, 0}, len: 30, value: {:interface, "eth0"}}
+            ]} == RoutingTable.resolve(bgp, {203, 0, 113, 7}, 4, via: igp)
+
+    assert {:ok, [_, _, _]} = RoutingTable.resolve(bgp, "203.0.113.7", 4, via: igp)
+    assert {:error, :unreachable, [%{len: 24}]} = RoutingTable.resolve(bgp, {203, 0, 113, 7}, 4)
+
+    RoutingTable.add(igp, "0.0.0.0/0", {192, 0, 2, 254})
+    RoutingTable.remove(igp, "10.0.0.0/30")
+    assert {:error, :routing_loop, chain} = RoutingTable.resolve(igp, {192, 0, 2, 10}, 8)
+    assert [24, 0, 24] == Enum.map(chain, & &1.len)
+  end
+
+  test "matches/2" do
+    t = RoutingTable.new()
+    RoutingTable.add(t, {10, 0, 0, 0}, 8, :rir)
+    RoutingTable.add(t, {10, 69, 0, 0}, 16, :customer)
+    RoutingTable.add(t, {10, 69, 1, 0}, 24, :site)
+
+    assert [
+             %{prefix: {10, 0, 0, 0}, len: 8, value: :rir},
+             %{prefix: {10, 69, 0, 0}, len: 16, value: :customer},
+             %{prefix: {10, 69, 1, 0}, len: 24, value: :site}
+           ] == RoutingTable.matches(t, {10, 69, 1, 1})
+
+    assert [] == RoutingTable.matches(t, {192, 168, 1, 1})
+    assert [] == RoutingTable.matches(t, {8193, 3512, 0, 0, 0, 0, 0, 1})
+  end
+
+  test "more_specifics/3" do
+    t = RoutingTable.new()
+    RoutingTable.add(t, {10, 69, 0, 0}, 16, :customer)
+    RoutingTable.add(t, {10, 69, 1, 0}, 24, :site)
+    RoutingTable.add(t, {10, 70, 1, 0}, 24, :other)
+
+    assert [{{10, 69, 0, 0}, 16, :customer}, {{10, 69, 1, 0}, 24, :site}] ==
+             RoutingTable.more_specifics(t, {10, 69, 0, 0}, 16)
+
+    assert 3 == length(RoutingTable.more_specifics(t, {10, 0, 0, 0}, 8))
+    assert [] == RoutingTable.more_specifics(t, {8193, 3512, 0, 0, 0, 0, 0, 0}, 32)
+  end
+
+  test "invalid prefixes" do
+    t = RoutingTable.new()
+    assert_raise ArgumentError, fn -> RoutingTable.add(t, {10, 0, 0, 0}, 33, :lan) end
+    assert_raise ArgumentError, fn -> RoutingTable.match(t, {10, 0, 0, 0}, 33) end
+    assert nil == RoutingTable.add(t, {10, 0, 0, 1}, 8, :lan)
+    assert :lan == RoutingTable.match(t, {10, 0, 0, 0}, 8)
+
+    t = RoutingTable.new(strict: true)
+    assert_raise ArgumentError, fn -> RoutingTable.add(t, {10, 0, 0, 1}, 8, :lan) end
+    assert_raise ArgumentError, fn -> RoutingTable.reachable?(t, {10, 0, 0, 1}, 8) end
+  end
+
+  test "to_list/1 and fold/3" do
+    t = RoutingTable.new()
+    assert [] == RoutingTable.to_list(t)
+    RoutingTable.add(t, {192, 168, 1, 0}, 24, :lan)
+    RoutingTable.add(t, {0, 0, 0, 0}, 0, :default)
+    RoutingTable.add(t, {8193, 3512, 0, 0, 0, 0, 0, 0}, 32, :doc)
+
+    assert [
+             {{0, 0, 0, 0}, 0, :default},
+             {{192, 168, 1, 0}, 24, :lan},
+             {{8193, 3512, 0, 0, 0, 0, 0, 0}, 32, :doc}
+           ] == RoutingTable.to_list(t)
+
+    assert [:doc, :lan, :default] == RoutingTable.fold(t, [], fn {_, _, value}, acc -> [value | acc] end)
+  end
+
+  test "stream/2" do
+    t = RoutingTable.new()
+    for i <- 0..9, do: RoutingTable.add(t, {10, i, 0, 0}, 16, i)
+    RoutingTable.add(t, {8193, 3512, 0, 0, 0, 0, 0, 0}, 32, :doc)
+    assert RoutingTable.to_list(t) == Enum.to_list(RoutingTable.stream(t, 3))
+    assert RoutingTable.to_list(t) == Enum.to_list(RoutingTable.stream(t, 5))
+  end
 
 end
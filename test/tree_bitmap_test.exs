@@ -11,13 +11,14 @@ defmodule TreeBitmapTest do
     table = TreeBitmap.new()
     assert {1200, 1152} == TreeBitmap.memory(table)
     {:ok, _} = TreeBitmap.add(table, {:inet4, 192, 168, 1, 0}, 24, 0)
-    assert {1248, 1168} == TreeBitmap.memory(table)
+    # results include the stored term, 0 is 3 bytes in external term format
+    assert {1248, 1283} == TreeBitmap.memory(table)
   end
 
   test "new_with_capacity/1" do
     table = TreeBitmap.new_with_capacity(1000)
     assert is_reference(table)
-    assert {109152, 37152} = TreeBitmap.memory(table)
+    assert {109152, 289152} = TreeBitmap.memory(table)
   end
 
   test "length/1" do
@@ -39,6 +40,33 @@ defmodule TreeBitmapTest do
     assert {:ok, _, _, 1} = TreeBitmap.longest_match(table, {:inet4, 10, 69, 1, 1})
   end
 
+  test "add/4 any term" do
+    table = TreeBitmap.new()
+    {:ok, nil} = TreeBitmap.add(table, {:inet4, 10, 69, 0, 0}, 16, {:vpn, "wg0"})
+    assert {:ok, _, 16, {:vpn, "wg0"}} = TreeBitmap.longest_match(table, {:inet4, 10, 69, 1, 1})
+    assert {:ok, {:vpn, "wg0"}} = TreeBitmap.exact_match(table, {:inet4, 10, 69, 0, 0}, 16)
+    assert {:ok, {:vpn, "wg0"}} = TreeBitmap.remove(table, {:inet4, 10, 69, 0, 0}, 16)
+    assert {1200, 1280} == TreeBitmap.memory(table)
+  end
+
+  test "add_many/2" do
+    table = TreeBitmap.new()
+    {:ok, _} = TreeBitmap.add(table, {:inet4, 10, 69, 0, 0}, 16, :old)
+
+    assert [{:ok, nil}, {:ok, :old}, {:error, :invalid_masklen}, {:error, :badarg}, {:ok, nil}] ==
+             TreeBitmap.add_many(table, [
+               {{:inet4, 10, 69, 1, 0}, 24, 1},
+               {{:inet4, 10, 69, 0, 0}, 16, 2},
+               {{:inet4, 10, 70, 0, 0}, 33, 3},
+               {:not_a_route},
+               {{:inet4, 0, 0, 0, 0}, 0, 4}
+             ])
+
+    assert 3 == TreeBitmap.length(table)
+    assert {:ok, _, 16, 2} = TreeBitmap.longest_match(table, {:inet4, 10, 69, 2, 1})
+    assert [] == TreeBitmap.add_many(table, [])
+  end
+
   test "remove/3" do
     table = TreeBitmap.new()
     {:ok, _} = TreeBitmap.add(table, {:inet4, 192, 168, 1, 0}, 24, 0)
@@ -79,6 +107,135 @@ defmodule TreeBitmapTest do
     assert {:ok, _, _, 1} = TreeBitmap.longest_match(table, {:inet4, 10, 69, 2, 2})
   end
 
+  test "to_list/2" do
+    table = TreeBitmap.new()
+    assert [] == TreeBitmap.to_list(table, :inet4)
+    {:ok, _} = TreeBitmap.add(table, {:inet4, 10, 69, 1, 0}, 24, 2)
+    {:ok, _} = TreeBitmap.add(table, {:inet4, 10, 69, 0, 0}, 16, 1)
+    {:ok, _} = TreeBitmap.add(table, {:inet4, 0, 0, 0, 0}, 0, 0)
+    {:ok, _} = TreeBitmap.add(table, {:inet4, 192, 168, 1, 1}, 32, 3)
+    assert [
+             {{:inet4, 0, 0, 0, 0}, 0, 0},
+             {{:inet4, 10, 69, 0, 0}, 16, 1},
+             {{:inet4, 10, 69, 1, 0}, 24, 2},
+             {{:inet4, 192, 168, 1, 1}, 32, 3}
+           ] == TreeBitmap.to_list(table, :inet4)
+
+    table = TreeBitmap.new()
+    {:ok, _} = TreeBitmap.add(table, {:inet6, 8193, 3512, 0, 0, 0, 0, 0, 0}, 32, 0)
+    {:ok, _} = TreeBitmap.add(table, {:inet6, 8193, 3512, 34211, 0, 0, 35374, 880, 1}, 128, 1)
+    assert [
+             {{:inet6, 8193, 3512, 0, 0, 0, 0, 0, 0}, 32, 0},
+             {{:inet6, 8193, 3512, 34211, 0, 0, 35374, 880, 1}, 128, 1}
+           ] == TreeBitmap.to_list(table, :inet6)
+
+    # shorter prefixes are stored first in a trie node, but listed by address
+    table = TreeBitmap.new()
+    {:ok, _} = TreeBitmap.add(table, {:inet4, 128, 0, 0, 0}, 1, 0)
+    {:ok, _} = TreeBitmap.add(table, {:inet4, 10, 0, 0, 0}, 8, 1)
+    {:ok, _} = TreeBitmap.add(table, {:inet4, 0, 0, 0, 0}, 2, 2)
+    assert [
+             {{:inet4, 0, 0, 0, 0}, 2, 2},
+             {{:inet4, 10, 0, 0, 0}, 8, 1},
+             {{:inet4, 128, 0, 0, 0}, 1, 0}
+           ] == TreeBitmap.to_list(table, :inet4)
+
+    cursor = TreeBitmap.iter(table, :inet4)
+    assert {:ok, [{_, 2, 2}, {_, 8, 1}, {_, 1, 0}]} = TreeBitmap.iter_next(cursor, 3)
+  end
+
+  test "iter/2 and iter_next/2" do
+    table = TreeBitmap.new()
+    {:ok, _} = TreeBitmap.add(table, {:inet4, 10, 69, 1, 0}, 24, 2)
+    {:ok, _} = TreeBitmap.add(table, {:inet4, 10, 69, 0, 0}, 16, 1)
+    {:ok, _} = TreeBitmap.add(table, {:inet4, 0, 0, 0, 0}, 0, 0)
+    cursor = TreeBitmap.iter(table, :inet4)
+    assert {:ok, [{{:inet4, 0, 0, 0, 0}, 0, 0}, {{:inet4, 10, 69, 0, 0}, 16, 1}]} = TreeBitmap.iter_next(cursor, 2)
+    assert {:ok, [{{:inet4, 10, 69, 1, 0}, 24, 2}]} = TreeBitmap.iter_next(cursor, 2)
+    assert {:ok, []} = TreeBitmap.iter_next(cursor, 2)
+  end
+
+  test "iter_next/2 after modification" do
+    table = TreeBitmap.new()
+    {:ok, _} = TreeBitmap.add(table, {:inet4, 10, 69, 0, 0}, 16, 1)
+    {:ok, _} = TreeBitmap.add(table, {:inet4, 10, 69, 1, 0}, 24, 2)
+    cursor = TreeBitmap.iter(table, :inet4)
+    assert {:ok, [_]} = TreeBitmap.iter_next(cursor, 1)
+    # replacing a value keeps the cursor valid
+    {:ok, 2} = TreeBitmap.add(table, {:inet4, 10, 69, 1, 0}, 24, 3)
+    assert {:ok, [{_, 24, 3}]} = TreeBitmap.iter_next(cursor, 1)
+    {:ok, nil} = TreeBitmap.add(table, {:inet4, 10, 70, 0, 0}, 16, 4)
+    assert {:error, :invalidated} = TreeBitmap.iter_next(cursor, 1)
+  end
+
+  test "invalid masklen" do
+    table = TreeBitmap.new()
+    assert {:error, :invalid_masklen} = TreeBitmap.add(table, {:inet4, 10, 0, 0, 0}, 40, 0)
+    assert {:error, :invalid_masklen} = TreeBitmap.remove(table, {:inet4, 10, 0, 0, 0}, 33)
+    assert {:error, :invalid_masklen} = TreeBitmap.exact_match(table, {:inet4, 10, 0, 0, 0}, 33)
+    assert {:error, :invalid_masklen} = TreeBitmap.add(table, {:inet6, 0, 0, 0, 0, 0, 0, 0, 0}, 129, 0)
+    assert {:ok, nil} = TreeBitmap.add(table, {:inet6, 0, 0, 0, 0, 0, 0, 0, 1}, 128, 0)
+    assert 1 == TreeBitmap.length(table)
+  end
+
+  test "host bits" do
+    table = TreeBitmap.new()
+    assert {:ok, nil} = TreeBitmap.add(table, {:inet4, 10, 69, 0, 1}, 30, 0)
+    assert {:ok, 0} = TreeBitmap.exact_match(table, {:inet4, 10, 69, 0, 0}, 30)
+    assert {:ok, 0} = TreeBitmap.exact_match(table, {:inet4, 10, 69, 0, 3}, 30)
+    assert [{{:inet4, 10, 69, 0, 0}, 30, 0}] = TreeBitmap.to_list(table, :inet4)
+
+    table = TreeBitmap.new_with_options(strict: true)
+    assert {:error, :host_bits_set} = TreeBitmap.add(table, {:inet4, 10, 69, 0, 1}, 30, 0)
+    assert {:error, :host_bits_set} = TreeBitmap.remove(table, {:inet4, 10, 69, 0, 1}, 30)
+    assert {:error, :host_bits_set} = TreeBitmap.exact_match(table, {:inet4, 10, 69, 0, 1}, 30)
+    assert {:ok, nil} = TreeBitmap.add(table, {:inet4, 10, 69, 0, 0}, 30, 0)
+  end
+
+  test "matches/2" do
+    table = TreeBitmap.new()
+    assert [] == TreeBitmap.matches(table, {:inet4, 10, 69, 1, 2})
+    {:ok, _} = TreeBitmap.add(table, {:inet4, 10, 69, 1, 0}, 24, 2)
+    {:ok, _} = TreeBitmap.add(table, {:inet4, 10, 69, 0, 0}, 16, 1)
+    {:ok, _} = TreeBitmap.add(table, {:inet4, 0, 0, 0, 0}, 0, 0)
+    {:ok, _} = TreeBitmap.add(table, {:inet4, 10, 69, 2, 0}, 24, 3)
+
+    assert [
+             {{:inet4, 0, 0, 0, 0}, 0, 0},
+             {{:inet4, 10, 69, 0, 0}, 16, 1},
+             {{:inet4, 10, 69, 1, 0}, 24, 2}
+           ] == TreeBitmap.matches(table, {:inet4, 10, 69, 1, 2})
+
+    assert [{{:inet4, 0, 0, 0, 0}, 0, 0}, {{:inet4, 10, 69, 0, 0}, 16, 1}] ==
+             TreeBitmap.matches(table, {:inet4, 10, 69, 3, 2})
+
+    assert [{{:inet4, 0, 0, 0, 0}, 0, 0}] == TreeBitmap.matches(table, {:inet4, 8, 8, 8, 8})
+  end
+
+  test "more_specifics/3" do
+    table = TreeBitmap.new()
+    {:ok, _} = TreeBitmap.add(table, {:inet4, 0, 0, 0, 0}, 0, 0)
+    {:ok, _} = TreeBitmap.add(table, {:inet4, 10, 0, 0, 0}, 8, 1)
+    {:ok, _} = TreeBitmap.add(table, {:inet4, 10, 69, 0, 0}, 16, 2)
+    {:ok, _} = TreeBitmap.add(table, {:inet4, 10, 69, 1, 0}, 24, 3)
+    {:ok, _} = TreeBitmap.add(table, {:inet4, 11, 0, 0, 0}, 8, 4)
+
+    assert {:ok,
+            [
+              {{:inet4, 10, 0, 0, 0}, 8, 1},
+              {{:inet4, 10, 69, 0, 0}, 16, 2},
+              {{:inet4, 10, 69, 1, 0}, 24, 3}
+            ]} == TreeBitmap.more_specifics(table, {:inet4, 10, 0, 0, 0}, 8)
+
+    assert {:ok, [{{:inet4, 10, 69, 1, 0}, 24, 3}]} ==
+             TreeBitmap.more_specifics(table, {:inet4, 10, 69, 1, 0}, 17)
+
+    {:ok, all} = TreeBitmap.more_specifics(table, {:inet4, 0, 0, 0, 0}, 0)
+    assert 5 == length(all)
+    assert {:ok, []} == TreeBitmap.more_specifics(table, {:inet4, 192, 168, 0, 0}, 16)
+    assert {:error, :invalid_masklen} == TreeBitmap.more_specifics(table, {:inet4, 10, 0, 0, 0}, 33)
+  end
+
   test "multiple routes" do
     table = TreeBitmap.new()
     {:ok, _} = TreeBitmap.add(table, {:inet4, 8, 8, 8, 0}, 24, 8)
@@ -89,4 +246,239 @@ defmodule TreeBitmapTest do
     assert {:ok, _, _, 200} = TreeBitmap.longest_match(table, {:inet4, 192, 168, 1, 1})
   end
 
+  test "longest_match_many/2 and longest_match_packed/3" do
+    table = TreeBitmap.new()
+    {:ok, _} = TreeBitmap.add(table, {:inet4, 10, 0, 0, 0}, 8, :rir)
+    {:ok, _} = TreeBitmap.add(table, {:inet4, 10, 69, 1, 0}, 24, :site)
+
+    assert [{{:inet4, 10, 69, 1, 0}, 24, :site}, nil, {{:inet4, 10, 0, 0, 0}, 8, :rir}] ==
+             TreeBitmap.longest_match_many(table, [
+               {:inet4, 10, 69, 1, 1},
+               {:inet4, 192, 168, 1, 1},
+               {:inet4, 10, 1, 1, 1}
+             ])
+
+    assert [] == TreeBitmap.longest_match_many(table, [])
+
+    assert [{{:inet4, 10, 69, 1, 0}, 24, :site}, nil] ==
+             TreeBitmap.longest_match_packed(table, :inet4, <<10, 69, 1, 1, 192, 168, 1, 1>>)
+
+    assert_raise ArgumentError, fn -> TreeBitmap.longest_match_packed(table, :inet4, <<10, 69, 1>>) end
+  end
+
+  test "parse_prefix/1" do
+    assert {:ok, {:inet4, 10, 0, 0, 0}, 8} == TreeBitmap.parse_prefix("10.0.0.0/8")
+    assert {:ok, {:inet4, 10, 0, 0, 1}, 32} == TreeBitmap.parse_prefix("10.0.0.1")
+    assert {:ok, {:inet6, 8193, 3512, 0, 0, 0, 0, 0, 0}, 32} == TreeBitmap.parse_prefix("2001:db8::/32")
+    assert {:ok, {:inet6, 0, 0, 0, 0, 0, 0, 0, 1}, 128} == TreeBitmap.parse_prefix("::1")
+    assert {:ok, {:inet4, 10, 0, 0, 0}, 33} == TreeBitmap.parse_prefix("10.0.0.0/33")
+
+    for invalid <- ["", "10.0.0.0/", "10.0.0.0/+8", "10.0.0/8", "010.0.0.0/8", "2001:db8::/a", "10.0.0.0/8 "] do
+      assert {:error, :invalid_prefix} == TreeBitmap.parse_prefix(invalid)
+    end
+  end
+
+  test "raw binary addresses" do
+    table = TreeBitmap.new()
+    assert {:ok, nil} == TreeBitmap.add(table, <<10, 69, 0, 0>>, 16, :v4)
+    assert {:ok, nil} == TreeBitmap.add(table, <<0x20010DB8::32, 0::96>>, 32, :v6)
+    assert {:ok, {:inet4, 10, 69, 0, 0}, 16, :v4} == TreeBitmap.longest_match(table, <<10, 69, 1, 1>>)
+    assert {:ok, :v4} == TreeBitmap.exact_match(table, {:inet4, 10, 69, 0, 0}, 16)
+    assert {:ok, :v6} == TreeBitmap.remove(table, <<0x20010DB8::32, 0::96>>, 32)
+    assert_raise ArgumentError, fn -> TreeBitmap.add(table, <<10, 69, 0>>, 16, :v4) end
+  end
+
+  test "prefix_format: :cidr" do
+    table = TreeBitmap.new_with_options(prefix_format: :cidr)
+    {:ok, _} = TreeBitmap.add(table, {:inet6, 8193, 3512, 0, 0, 0, 0, 0, 0}, 32, :doc)
+    assert {:ok, "2001:db8::/32", 32, :doc} == TreeBitmap.longest_match(table, {:inet6, 8193, 3512, 0, 0, 0, 0, 0, 1})
+    assert [{"2001:db8::/32", 32, :doc}] == TreeBitmap.to_list(table, :inet6)
+
+    table = TreeBitmap.new_with_options(prefix_format: :cidr)
+    {:ok, _} = TreeBitmap.add(table, {:inet4, 10, 0, 0, 0}, 8, :rir)
+    assert [{"10.0.0.0/8", 8, :rir}] == TreeBitmap.matches(table, {:inet4, 10, 1, 1, 1})
+    assert_raise ArgumentError, fn -> TreeBitmap.new_with_options(prefix_format: :text) end
+  end
+
+  test "clone/1" do
+    table = TreeBitmap.new_with_options(strict: true)
+    {:ok, _} = TreeBitmap.add(table, {:inet4, 10, 0, 0, 0}, 8, :rir)
+    {:ok, _} = TreeBitmap.add(table, {:inet4, 10, 69, 1, 0}, 24, %{site: "par"})
+    copy = TreeBitmap.clone(table)
+    assert TreeBitmap.memory(copy) == TreeBitmap.memory(table)
+
+    {:ok, _} = TreeBitmap.remove(table, {:inet4, 10, 69, 1, 0}, 24)
+    {:ok, _} = TreeBitmap.add(table, {:inet4, 10, 0, 0, 0}, 8, :changed)
+    assert {:ok, _, 24, %{site: "par"}} = TreeBitmap.longest_match(copy, {:inet4, 10, 69, 1, 1})
+    assert {:ok, :rir} == TreeBitmap.exact_match(copy, {:inet4, 10, 0, 0, 0}, 8)
+    assert 2 == TreeBitmap.length(copy)
+    assert {:error, :host_bits_set} == TreeBitmap.add(copy, {:inet4, 10, 0, 0, 1}, 8, :bad)
+  end
+
+  test "dump/1 and load/1" do
+    table = TreeBitmap.new_with_options(strict: true)
+    {:ok, _} = TreeBitmap.add(table, {:inet4, 0, 0, 0, 0}, 0, :default)
+    {:ok, _} = TreeBitmap.add(table, {:inet4, 10, 64, 0, 0}, 10, %{ref: "x"})
+    {:ok, _} = TreeBitmap.add(table, {:inet4, 10, 69, 1, 1}, 32, [1, 2, 3])
+    dump = TreeBitmap.dump(table)
+    assert <<"RTBM", 3::16, _::binary>> = dump
+
+    assert {:ok, loaded} = TreeBitmap.load(dump, :inet4)
+    assert TreeBitmap.to_list(table, :inet4) == TreeBitmap.to_list(loaded, :inet4)
+    assert {:error, :host_bits_set} == TreeBitmap.add(loaded, {:inet4, 10, 0, 0, 1}, 8, :bad)
+    assert dump == TreeBitmap.dump(loaded)
+
+    body_size = byte_size(dump) - 4
+    <<body::binary-size(body_size), crc::32>> = dump
+    assert crc == :erlang.crc32(body)
+
+    <<head::binary-size(20), byte, rest::binary>> = dump
+    assert {:error, :checksum_mismatch} == TreeBitmap.load(<<head::binary, byte + 1, rest::binary>>, :inet4)
+    assert {:error, :unsupported_version} == TreeBitmap.load(<<"RTBM", 4::16, binary_part(dump, 6, body_size - 2)::binary>>, :inet4)
+    assert {:error, :invalid_dump} == TreeBitmap.load(binary_part(dump, 0, 10), :inet4)
+    assert {:error, :invalid_dump} == TreeBitmap.load("nope", :inet4)
+
+    {:ok, empty} = TreeBitmap.load(TreeBitmap.dump(TreeBitmap.new()), :inet4)
+    assert 0 == TreeBitmap.length(empty)
+
+    {:ok, _} = TreeBitmap.add_route(table, {:inet4, 192, 0, 2, 0}, 24, {:ospf, 110, 20, :b})
+    {:ok, _} = TreeBitmap.add_route(table, {:inet4, 192, 0, 2, 0}, 24, {:static, 1, 0, :a})
+    {:ok, _} = TreeBitmap.add_ecmp(table, {:inet4, 198, 51, 100, 0}, 24, [:a, :b])
+    {:ok, loaded} = TreeBitmap.load(TreeBitmap.dump(table), :inet4)
+    assert {:ok, [{:static, 1, 0, :a}, {:ospf, 110, 20, :b}]} == TreeBitmap.routes(loaded, {:inet4, 192, 0, 2, 0}, 24)
+    assert {:ok, [:a, :b]} == TreeBitmap.exact_match(loaded, {:inet4, 198, 51, 100, 0}, 24)
+  end
+
+  test "add_ecmp/4 and longest_match_hashed/3" do
+    table = TreeBitmap.new()
+    members = [:nh1, :nh2, :nh3, :nh4]
+    assert {:ok, nil} == TreeBitmap.add_ecmp(table, {:inet4, 10, 0, 0, 0}, 8, members)
+    {:ok, _} = TreeBitmap.add(table, {:inet4, 0, 0, 0, 0}, 0, :default)
+    assert {:ok, _, 8, ^members} = TreeBitmap.longest_match(table, {:inet4, 10, 1, 1, 1})
+
+    picks = for hash <- 0..999, do: hash_pick(table, hash)
+    assert picks == for(hash <- 0..999, do: hash_pick(table, hash))
+    counts = Enum.frequencies(picks)
+    assert Enum.sort(Map.keys(counts)) == members
+    assert Enum.all?(Map.values(counts), &(&1 > 150))
+
+    {:ok, _} = TreeBitmap.add_ecmp(table, {:inet4, 10, 0, 0, 0}, 8, [:nh1, :nh2, :nh4])
+
+    for {pick, hash} <- Enum.with_index(picks), pick != :nh3 do
+      assert pick == hash_pick(table, hash)
+    end
+
+    assert {:ok, _, 0, :default} = TreeBitmap.longest_match_hashed(table, {:inet4, 11, 0, 0, 1}, 7)
+    assert {:ok, nil} == TreeBitmap.longest_match_hashed(TreeBitmap.new(), {:inet4, 10, 0, 0, 1}, 7)
+    assert_raise ArgumentError, fn -> TreeBitmap.add_ecmp(table, {:inet4, 10, 0, 0, 0}, 8, []) end
+  end
+
+  defp hash_pick(table, hash) do
+    {:ok, _, 8, member} = TreeBitmap.longest_match_hashed(table, {:inet4, 10, 0, 0, 1}, hash)
+    member
+  end
+
+  test "resolve/6" do
+    bgp = TreeBitmap.new()
+    igp4 = TreeBitmap.new()
+    igp6 = TreeBitmap.new()
+    {:ok, _} = TreeBitmap.add(bgp, {:inet4, 203, 0, 113, 0}, 24, {192, 0, 2, 1})
+    {:ok, _} = TreeBitmap.add(igp4, {:inet4, 192, 0, 2, 0}, 24, {10, 0, 0, 1})
+    {:ok, _} = TreeBitmap.add(igp4, {:inet4, 10, 0, 0, 0}, 30, "eth0")
+    ip = {:inet4, 203, 0, 113, 7}
+
+    assert {:ok,
+            [
+              {{:inet4, 203, 0, 113, 0}, 24, {192, 0, 2, 1}},
+              {{:inet4, 192, 0, 2, 0}, 24, {10, 0, 0, 1}},
+              {{:inet4, 10, 0, 0, 0}, 30, "eth0"}
+            ]} == TreeBitmap.resolve(bgp, igp6, igp4, igp6, ip, 8)
+
+    assert {:error, :max_depth, [_, _]} = TreeBitmap.resolve(bgp, igp6, igp4, igp6, ip, 2)
+    assert {:error, :max_depth, []} == TreeBitmap.resolve(bgp, igp6, igp4, igp6, ip, 0)
+    assert {:error, :unreachable, [_]} = TreeBitmap.resolve(bgp, igp6, nil, nil, ip, 8)
+    assert {:error, :unreachable, []} == TreeBitmap.resolve(bgp, igp6, nil, nil, {:inet4, 8, 8, 8, 8}, 8)
+
+    {:ok, _} = TreeBitmap.add(igp4, {:inet4, 10, 0, 0, 0}, 30, {8193, 3512, 0, 0, 0, 0, 0, 1})
+    {:ok, _} = TreeBitmap.add(igp6, {:inet6, 8193, 3512, 0, 0, 0, 0, 0, 0}, 64, {192, 0, 2, 9})
+
+    assert {:error, :routing_loop, chain} = TreeBitmap.resolve(bgp, igp6, igp4, igp6, ip, 8)
+    assert [
+             {{:inet4, 192, 0, 2, 0}, 24, _},
+             {{:inet4, 10, 0, 0, 0}, 30, _},
+             {{:inet6, 8193, 3512, 0, 0, 0, 0, 0, 0}, 64, _},
+             {{:inet4, 192, 0, 2, 0}, 24, _}
+           ] = tl(chain)
+  end
+
+  test "load/1 version 1 dumps" do
+    value = :erlang.term_to_binary(:rir)
+    body = <<"RTBM", 1::16, 0, 1::64, 8, 10, byte_size(value)::32, value::binary>>
+    assert {:ok, table} = TreeBitmap.load(<<body::binary, :erlang.crc32(body)::32>>, :inet4)
+    assert [{{:inet4, 10, 0, 0, 0}, 8, :rir}] == TreeBitmap.to_list(table, :inet4)
+
+    # values compare as terms, whatever their encoding: ATOM_EXT here
+    value = <<131, 100, 3::16, "rir">>
+    body = <<"RTBM", 1::16, 0, 1::64, 8, 10, byte_size(value)::32, value::binary>>
+    assert {:ok, legacy} = TreeBitmap.load(<<body::binary, :erlang.crc32(body)::32>>, :inet4)
+    assert [] == TreeBitmap.diff(legacy, table, :inet4)
+  end
+
+  test "load/2 checks mask lengths against the family" do
+    value = :erlang.term_to_binary(:v6)
+    body = <<"RTBM", 1::16, 0, 1::64, 48, 0x2001::16, 0xDB8::16, 1::16, byte_size(value)::32, value::binary>>
+    dump = <<body::binary, :erlang.crc32(body)::32>>
+    assert {:error, :invalid_dump} == TreeBitmap.load(dump, :inet4)
+    assert {:ok, table} = TreeBitmap.load(dump, :inet6)
+    assert [{{:inet6, 8193, 3512, 1, 0, 0, 0, 0, 0}, 48, :v6}] == TreeBitmap.to_list(table, :inet6)
+  end
+
+  test "add_route/4, remove_route/4 and routes/3" do
+    table = TreeBitmap.new()
+    prefix = {:inet4, 10, 0, 0, 0}
+    assert {:ok, nil} == TreeBitmap.add_route(table, prefix, 8, {:bgp, 20, 0, :peer})
+    assert {:ok, nil} == TreeBitmap.add_route(table, prefix, 8, {:ospf, 110, 20, :area0})
+    assert {:ok, nil} == TreeBitmap.add_route(table, prefix, 8, {{:ospf, 2}, 110, 10, :area2})
+    assert {:ok, _, 8, :peer} = TreeBitmap.longest_match(table, {:inet4, 10, 1, 1, 1})
+    assert 1 == TreeBitmap.length(table)
+
+    assert {:ok, [{:bgp, 20, 0, :peer}, {{:ospf, 2}, 110, 10, :area2}, {:ospf, 110, 20, :area0}]} ==
+             TreeBitmap.routes(table, prefix, 8)
+
+    assert {:ok, {:bgp, 20, 0, :peer}} == TreeBitmap.add_route(table, prefix, 8, {:bgp, 200, 0, :ibgp})
+    assert {:ok, :area2} == TreeBitmap.exact_match(table, prefix, 8)
+
+    assert {:ok, {{:ospf, 2}, 110, 10, :area2}} == TreeBitmap.remove_route(table, prefix, 8, {:ospf, 2})
+    assert {:ok, nil} == TreeBitmap.remove_route(table, prefix, 8, {:ospf, 2})
+    assert {:ok, [{:ospf, 110, 20, :area0}, {:bgp, 200, 0, :ibgp}]} == TreeBitmap.routes(table, prefix, 8)
+
+    {:ok, _} = TreeBitmap.remove_route(table, prefix, 8, :ospf)
+    {:ok, _} = TreeBitmap.remove_route(table, prefix, 8, :bgp)
+    assert 0 == TreeBitmap.length(table)
+    assert {:ok, []} == TreeBitmap.routes(table, prefix, 8)
+
+    {:ok, _} = TreeBitmap.add(table, prefix, 8, :plain)
+    assert {:ok, []} == TreeBitmap.routes(table, prefix, 8)
+    assert {:ok, nil} == TreeBitmap.remove_route(table, prefix, 8, :plain)
+    assert {:ok, nil} == TreeBitmap.add_route(table, prefix, 8, {:static, 1, 0, :nh})
+    assert {:ok, :nh} == TreeBitmap.exact_match(table, prefix, 8)
+    assert {:ok, :nh} == TreeBitmap.add(table, prefix, 8, :plain)
+    assert {:ok, []} == TreeBitmap.routes(table, prefix, 8)
+
+    assert {:error, :invalid_masklen} == TreeBitmap.routes(table, prefix, 33)
+    assert_raise ArgumentError, fn -> TreeBitmap.add_route(table, prefix, 8, {:static, -1, 0, :nh}) end
+  end
+
+  test "add_route/4 keeps the oldest of equally preferred routes" do
+    table = TreeBitmap.new()
+    prefix = {:inet4, 10, 0, 0, 0}
+    {:ok, nil} = TreeBitmap.add_route(table, prefix, 8, {:a, 110, 20, :x})
+    {:ok, nil} = TreeBitmap.add_route(table, prefix, 8, {:b, 110, 20, :y})
+    assert {:ok, :x} == TreeBitmap.exact_match(table, prefix, 8)
+
+    # replacing a route makes it the newest
+    {:ok, {:a, 110, 20, :x}} = TreeBitmap.add_route(table, prefix, 8, {:a, 110, 20, :z})
+    assert {:ok, :y} == TreeBitmap.exact_match(table, prefix, 8)
+    assert {:ok, [{:b, 110, 20, :y}, {:a, 110, 20, :z}]} == TreeBitmap.routes(table, prefix, 8)
+  end
 end
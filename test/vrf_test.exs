@@ -0,0 +1,74 @@
+defmodule VRFTest do
+  use ExUnit.Case
+  alias RoutingTable.VRF
+
+  test "isolation between VRFs" do
+    vrfs = VRF.new()
+    assert nil == VRF.add(vrfs, 100, "10.0.0.0/8", :customer_a)
+    assert nil == VRF.add(vrfs, 200, {10, 0, 0, 0}, 8, :customer_b)
+    assert nil == VRF.add(vrfs, 200, "2001:db8::/32", :customer_b6)
+    assert :customer_b == VRF.add(vrfs, 200, "10.0.0.0/8", :customer_b2)
+
+    assert %{vrf: 100, prefix: {10, 0, 0, 0}, len: 8, value: :customer_a} == VRF.lookup(vrfs, 100, {10, 1, 1, 1})
+    assert %{vrf: 200, value: :customer_b2} = VRF.lookup(vrfs, 200, "10.1.1.1")
+    assert %{vrf: 200, value: :customer_b2} = VRF.lookup(vrfs, 200, {:raw, <<10, 1, 1, 1>>})
+    assert %{vrf: 200, len: 32} = VRF.lookup(vrfs, 200, {8193, 3512, 0, 0, 0, 0, 0, 1})
+    assert nil == VRF.lookup(vrfs, 100, {8193, 3512, 0, 0, 0, 0, 0, 1})
+    assert nil == VRF.lookup(vrfs, 300, {10, 1, 1, 1})
+    assert :customer_a == VRF.match(vrfs, 100, "10.0.0.0/8")
+    assert nil == VRF.match(vrfs, 100, {10, 0, 0, 0}, 16)
+
+    assert :customer_a == VRF.remove(vrfs, 100, "10.0.0.0/8")
+    assert nil == VRF.lookup(vrfs, 100, {10, 1, 1, 1})
+    assert %{value: :customer_b2} = VRF.lookup(vrfs, 200, {10, 1, 1, 1})
+
+    assert 2 == VRF.delete(vrfs, 200)
+    assert nil == VRF.lookup(vrfs, 200, {10, 1, 1, 1})
+    assert 0 == VRF.delete(vrfs, 200)
+  end
+
+  test "set_fallback/3" do
+    vrfs = VRF.new()
+    VRF.add(vrfs, 0, "0.0.0.0/0", :internet)
+    VRF.add(vrfs, 100, "10.0.0.0/8", :customer)
+    VRF.add(vrfs, 101, "192.0.2.0/24", :shared)
+
+    assert nil == VRF.lookup(vrfs, 100, {192, 0, 2, 1})
+    :ok = VRF.set_fallback(vrfs, 100, 101)
+    :ok = VRF.set_fallback(vrfs, 101, 0)
+    assert %{vrf: 101, value: :shared} = VRF.lookup(vrfs, 100, {192, 0, 2, 1})
+    assert %{vrf: 0, value: :internet} = VRF.lookup(vrfs, 100, {198, 51, 100, 1})
+    assert %{vrf: 100, value: :customer} = VRF.lookup(vrfs, 100, {10, 1, 1, 1})
+    assert nil == VRF.match(vrfs, 100, "192.0.2.0/24")
+
+    VRF.remove(vrfs, 0, "0.0.0.0/0")
+    :ok = VRF.set_fallback(vrfs, 0, 100)
+    assert nil == VRF.lookup(vrfs, 100, {198, 51, 100, 1})
+
+    :ok = VRF.set_fallback(vrfs, 100, nil)
+    assert nil == VRF.lookup(vrfs, 100, {192, 0, 2, 1})
+  end
+
+  test "memory/1" do
+    vrfs = VRF.new()
+    for i <- 0..9, do: VRF.add(vrfs, 1, {10, i, 0, 0}, 16, i)
+    VRF.add(vrfs, 2, "10.0.0.0/8", "a longer value")
+    VRF.add(vrfs, 2, "2001:db8::/32", :doc)
+
+    assert %{inet4: {nodes, results}, inet6: {_, _}, vrfs: %{1 => one, 2 => two}} = VRF.memory(vrfs)
+    assert nodes > 0 and results > 0
+    assert %{routes: 10} = one
+    assert %{routes: 2} = two
+    assert one.results > two.results
+
+    VRF.delete(vrfs, 1)
+    assert [2] == Map.keys(VRF.memory(vrfs).vrfs)
+  end
+
+  test "invalid prefixes" do
+    vrfs = VRF.new(strict: true)
+    assert_raise ArgumentError, fn -> VRF.add(vrfs, 1, {10, 0, 0, 1}, 8, :bad) end
+    assert_raise ArgumentError, fn -> VRF.add(vrfs, 1, "10.0.0.0/33", :bad) end
+    assert_raise ArgumentError, fn -> VRF.add(vrfs, -1, "10.0.0.0/8", :bad) end
+  end
+end